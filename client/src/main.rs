@@ -1,3 +1,263 @@
-fn main() {
-    println!("Hello, world!");
+use std::{io::BufReader, os::unix::net::UnixStream, process::ExitCode};
+
+use openxr_device_simulator_protocol::{
+    self as protocol, Hand, HapticVibration, ImageFormat, InputValue, Pose, Request, Response,
+    SessionCommand,
+};
+
+const USAGE: &str = "usage: openxr-device-simulator-client <command>
+
+commands:
+  ping
+  head-pose <x> <y> <z> [<qx> <qy> <qz> <qw>]
+  ipd <meters>
+  controller-pose <left|right> <x> <y> <z> [<qx> <qy> <qz> <qw>]
+  input <path> <true|false|value|x,y>
+  interaction-profile <path>
+  session <request-exit|headset-off|headset-on|overlay-show|overlay-hide|lose>
+  system-unavailable <seconds>
+  lose-instance [<seconds>]  (lost after 1 second by default)
+  haptics [clear]
+  time
+  time advance <seconds>
+  time step [<frames>]
+  capture <dir> [png|ppm]
+  capture-continuous <dir> [png|ppm]
+  capture-continuous off";
+
+const DEFAULT_INSTANCE_LOSS_DELAY_NANOS: u64 = 1_000_000_000;
+
+fn parse_f32(value: &str) -> Result<f32, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|value: &f32| value.is_finite())
+        .ok_or_else(|| format!("invalid number: {value}"))
+}
+
+fn parse_pose(args: &[String]) -> Result<Pose, String> {
+    let values = args
+        .iter()
+        .map(|arg| parse_f32(arg))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match values[..] {
+        [x, y, z] => Ok(Pose {
+            position: [x, y, z],
+            ..Default::default()
+        }),
+        [x, y, z, qx, qy, qz, qw] => Ok(Pose {
+            position: [x, y, z],
+            orientation: [qx, qy, qz, qw],
+        }),
+        _ => Err("a pose is either <x> <y> <z> or <x> <y> <z> <qx> <qy> <qz> <qw>".into()),
+    }
+}
+
+fn parse_hand(value: &str) -> Result<Hand, String> {
+    match value {
+        "left" => Ok(Hand::Left),
+        "right" => Ok(Hand::Right),
+        _ => Err(format!("invalid hand: {value}")),
+    }
+}
+
+fn parse_input_value(value: &str) -> Result<InputValue, String> {
+    match value {
+        "true" => Ok(InputValue::Boolean(true)),
+        "false" => Ok(InputValue::Boolean(false)),
+        _ => match value.split_once(',') {
+            Some((x, y)) => Ok(InputValue::Vector2f([parse_f32(x)?, parse_f32(y)?])),
+            None => Ok(InputValue::Float(parse_f32(value)?)),
+        },
+    }
+}
+
+fn parse_seconds(value: &str) -> Result<u64, String> {
+    let seconds = parse_f32(value)?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(format!("invalid duration: {seconds}"));
+    }
+    Ok((seconds as f64 * 1e9) as u64)
+}
+
+fn parse_session_command(value: &str) -> Result<SessionCommand, String> {
+    match value {
+        "request-exit" => Ok(SessionCommand::RequestExit),
+        "headset-off" => Ok(SessionCommand::RemoveHeadset),
+        "headset-on" => Ok(SessionCommand::PutOnHeadset),
+        "overlay-show" => Ok(SessionCommand::ShowOverlay),
+        "overlay-hide" => Ok(SessionCommand::HideOverlay),
+        "lose" => Ok(SessionCommand::Lose),
+        _ => Err(format!("invalid session command: {value}")),
+    }
+}
+
+fn parse_image_format(args: &[String]) -> Result<ImageFormat, String> {
+    match args {
+        [] => Ok(ImageFormat::default()),
+        [format] if format == "png" => Ok(ImageFormat::Png),
+        [format] if format == "ppm" => Ok(ImageFormat::Ppm),
+        [format] => Err(format!("invalid image format: {format}")),
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn parse_request(args: &[String]) -> Result<Request, String> {
+    let Some((command, args)) = args.split_first() else {
+        return Err(USAGE.into());
+    };
+
+    match (command.as_str(), args) {
+        ("ping", []) => Ok(Request::Ping),
+        ("head-pose", args) => Ok(Request::SetHeadPose {
+            pose: parse_pose(args)?,
+        }),
+        ("ipd", [ipd]) => Ok(Request::SetIpd {
+            ipd: parse_f32(ipd)?,
+        }),
+        ("controller-pose", [hand, args @ ..]) => Ok(Request::SetControllerPose {
+            hand: parse_hand(hand)?,
+            pose: parse_pose(args)?,
+        }),
+        ("input", [path, value]) => Ok(Request::SetInput {
+            path: path.clone(),
+            value: parse_input_value(value)?,
+        }),
+        ("interaction-profile", [path]) => {
+            Ok(Request::SetInteractionProfile { path: path.clone() })
+        }
+        ("session", [command]) => Ok(Request::Session {
+            command: parse_session_command(command)?,
+        }),
+        ("system-unavailable", [seconds]) => Ok(Request::SetSystemUnavailable {
+            nanos: parse_seconds(seconds)?,
+        }),
+        // the app needs time to poll the loss pending event before every call fails
+        ("lose-instance", []) => Ok(Request::LoseInstance {
+            nanos: DEFAULT_INSTANCE_LOSS_DELAY_NANOS,
+        }),
+        ("lose-instance", [seconds]) => Ok(Request::LoseInstance {
+            nanos: parse_seconds(seconds)?,
+        }),
+        ("haptics", []) => Ok(Request::GetHaptics),
+        ("haptics", [command]) if command == "clear" => Ok(Request::ClearHapticHistory),
+        ("capture", [dir, format @ ..]) => Ok(Request::CaptureFrame {
+            dir: dir.clone(),
+            format: parse_image_format(format)?,
+        }),
+        ("capture-continuous", [dir]) if dir == "off" => Ok(Request::SetContinuousCapture {
+            dir: None,
+            format: ImageFormat::default(),
+        }),
+        ("capture-continuous", [dir, format @ ..]) => Ok(Request::SetContinuousCapture {
+            dir: Some(dir.clone()),
+            format: parse_image_format(format)?,
+        }),
+        ("time", []) => Ok(Request::GetTime),
+        ("time", [command, seconds]) if command == "advance" => Ok(Request::AdvanceTime {
+            nanos: parse_seconds(seconds)?,
+        }),
+        ("time", [command, count @ ..]) if command == "step" => Ok(Request::StepFrames {
+            count: match count {
+                [] => 1,
+                [count] => count
+                    .parse()
+                    .map_err(|_| format!("invalid frame count: {count}"))?,
+                _ => return Err(USAGE.into()),
+            },
+        }),
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn print_haptic(state: &str, vibration: &HapticVibration) {
+    let outputs = if vibration.outputs.is_empty() {
+        "unbound".to_string()
+    } else {
+        vibration.outputs.join(",")
+    };
+
+    print!(
+        "{state} {} {outputs} start={:.3}s duration={:.3}s frequency={} amplitude={}",
+        vibration.action,
+        vibration.start_time as f64 / 1e9,
+        vibration.duration as f64 / 1e9,
+        vibration.frequency,
+        vibration.amplitude,
+    );
+
+    match vibration.stop_time {
+        Some(stop_time) => println!(" stopped={:.3}s", stop_time as f64 / 1e9),
+        None => println!(),
+    }
+}
+
+fn send(request: &Request) -> protocol::Result<Response> {
+    let stream = UnixStream::connect(protocol::socket_path())?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    protocol::write_message(&mut writer, request)?;
+
+    match protocol::read_message(&mut reader)? {
+        Some(response) => Ok(response),
+        None => Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let request = match parse_request(&args) {
+        Ok(request) => request,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match send(&request) {
+        Ok(Response::Error { message }) => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
+        Ok(Response::Ok) => ExitCode::SUCCESS,
+        Ok(Response::Pong { runtime_version }) => {
+            println!("pong from runtime {runtime_version}");
+            ExitCode::SUCCESS
+        }
+        Ok(Response::Haptics {
+            time,
+            active,
+            history,
+        }) => {
+            println!("time={:.3}s", time as f64 / 1e9);
+            for vibration in &history {
+                print_haptic("done", vibration);
+            }
+            for vibration in &active {
+                print_haptic("active", vibration);
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(Response::Captured { files }) => {
+            for file in files {
+                println!("{file}");
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(Response::Time { time, is_virtual }) => {
+            let clock = if is_virtual { "virtual" } else { "real" };
+            println!("time={:.3}s ({clock} clock)", time as f64 / 1e9);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!(
+                "could not reach the runtime at {}: {err}",
+                protocol::socket_path().display()
+            );
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,337 @@
+use std::{
+    io::{BufRead, Write},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+pub const SOCKET_PATH_ENV: &str = "OPENXR_DEVICE_SIMULATOR_SOCKET";
+
+const SOCKET_NAME: &str = "control.sock";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("protocol version mismatch, expected {expected}, got {found}")]
+    VersionMismatch { expected: u32, found: u32 },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Path of the control socket, `$OPENXR_DEVICE_SIMULATOR_SOCKET` if set, otherwise inside the
+/// user runtime directory.
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os(SOCKET_PATH_ENV) {
+        return path.into();
+    }
+
+    directories::ProjectDirs::from("", "", "openxr-device-simulator")
+        .and_then(|dirs| dirs.runtime_dir().map(|dir| dir.join(SOCKET_NAME)))
+        .unwrap_or_else(|| {
+            std::env::temp_dir()
+                .join("openxr-device-simulator")
+                .join(SOCKET_NAME)
+        })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Pose {
+    pub position: [f32; 3],
+    /// quaternion as x, y, z, w
+    pub orientation: [f32; 4],
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Self {
+            position: [0.0; 3],
+            orientation: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+impl Hand {
+    pub fn user_path(&self) -> &'static str {
+        match self {
+            Hand::Left => "/user/hand/left",
+            Hand::Right => "/user/hand/right",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum InputValue {
+    Boolean(bool),
+    Float(f32),
+    Vector2f([f32; 2]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionCommand {
+    /// the runtime asks the app to quit
+    RequestExit,
+    /// the user takes the headset off, focused sessions lose focus and idle ones don't become
+    /// ready until it's put back on
+    RemoveHeadset,
+    PutOnHeadset,
+    /// a system overlay steals the focus from running sessions until it's hidden
+    ShowOverlay,
+    HideOverlay,
+    /// the runtime loses the sessions, apps have to destroy them and start over
+    Lose,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Ppm,
+}
+
+/// A vibration an app applied to a haptic action, times are runtime times in nanoseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HapticVibration {
+    pub action: String,
+    /// `None` when it was applied to every subaction path of the action
+    pub subaction_path: Option<String>,
+    /// output sources the action is bound to, e.g. `/user/hand/right/output/haptic`
+    pub outputs: Vec<String>,
+    pub start_time: i64,
+    pub duration: i64,
+    /// 0 when the app left it to the runtime
+    pub frequency: f32,
+    pub amplitude: f32,
+    /// when the app stopped or replaced it before it ran out
+    pub stop_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    Ping,
+    SetHeadPose {
+        pose: Pose,
+    },
+    SetIpd {
+        ipd: f32,
+    },
+    SetControllerPose {
+        hand: Hand,
+        pose: Pose,
+    },
+    /// sets an input source, e.g. `/user/hand/left/input/trigger/value`
+    SetInput {
+        path: String,
+        value: InputValue,
+    },
+    /// switches the type of the simulated controllers, e.g.
+    /// `/interaction_profiles/oculus/touch_controller`
+    SetInteractionProfile {
+        path: String,
+    },
+    Session {
+        command: SessionCommand,
+    },
+    /// xrGetSystem fails with `XR_ERROR_FORM_FACTOR_UNAVAILABLE` for a while
+    SetSystemUnavailable {
+        nanos: u64,
+    },
+    /// the instances get an instance loss pending event and are lost after `nanos`
+    LoseInstance {
+        nanos: u64,
+    },
+    GetHaptics,
+    ClearHapticHistory,
+    GetTime,
+    /// fast-forwards the virtual clock
+    AdvanceTime {
+        nanos: u64,
+    },
+    /// advances the virtual clock by a number of display periods
+    StepFrames {
+        count: u32,
+    },
+    /// writes the projection views of the next submitted frame into `dir`
+    CaptureFrame {
+        dir: String,
+        #[serde(default)]
+        format: ImageFormat,
+    },
+    /// writes every submitted frame into `dir`, `None` stops capturing
+    SetContinuousCapture {
+        dir: Option<String>,
+        #[serde(default)]
+        format: ImageFormat,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Pong {
+        runtime_version: String,
+    },
+    /// vibrations playing right now and the ones that finished, oldest first
+    Haptics {
+        time: i64,
+        active: Vec<HapticVibration>,
+        history: Vec<HapticVibration>,
+    },
+    /// runtime time in nanoseconds
+    Time {
+        time: i64,
+        is_virtual: bool,
+    },
+    Captured {
+        files: Vec<String>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u32,
+    message: T,
+}
+
+/// Writes a message as a single line of JSON.
+pub fn write_message<T: Serialize, W: Write>(writer: &mut W, message: &T) -> Result<()> {
+    let mut line = serde_json::to_vec(&Envelope {
+        version: PROTOCOL_VERSION,
+        message,
+    })?;
+    line.push(b'\n');
+    writer.write_all(&line)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the next message, `None` when the other end closed the connection.
+pub fn read_message<T: DeserializeOwned, R: BufRead>(reader: &mut R) -> Result<Option<T>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    let envelope: Envelope<serde_json::Value> = serde_json::from_str(&line)?;
+    if envelope.version != PROTOCOL_VERSION {
+        return Err(Error::VersionMismatch {
+            expected: PROTOCOL_VERSION,
+            found: envelope.version,
+        });
+    }
+
+    Ok(Some(serde_json::from_value(envelope.message)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn messages_round_trip() {
+        let requests = [
+            Request::Ping,
+            Request::SetHeadPose {
+                pose: Pose {
+                    position: [0.0, 1.6, -0.5],
+                    orientation: [0.0, 0.0, 0.0, 1.0],
+                },
+            },
+            Request::Session {
+                command: SessionCommand::RemoveHeadset,
+            },
+        ];
+
+        let mut buffer = Vec::new();
+        for request in &requests {
+            write_message(&mut buffer, request).unwrap();
+        }
+        write_message(
+            &mut buffer,
+            &Response::Error {
+                message: "no session".into(),
+            },
+        )
+        .unwrap();
+
+        let mut reader = Cursor::new(buffer);
+        for request in requests {
+            assert_eq!(
+                read_message::<Request, _>(&mut reader).unwrap(),
+                Some(request)
+            );
+        }
+        assert_eq!(
+            read_message::<Response, _>(&mut reader).unwrap(),
+            Some(Response::Error {
+                message: "no session".into()
+            })
+        );
+        assert_eq!(read_message::<Response, _>(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn messages_are_single_lines() {
+        let mut buffer = Vec::new();
+        write_message(
+            &mut buffer,
+            &Response::Error {
+                message: "first\nsecond".into(),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(buffer.iter().filter(|&&byte| byte == b'\n').count(), 1);
+        assert_eq!(buffer.last(), Some(&b'\n'));
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let line = format!(
+            "{{\"version\":{},\"message\":{{\"type\":\"ping\"}}}}\n",
+            PROTOCOL_VERSION + 1
+        );
+
+        match read_message::<Request, _>(&mut Cursor::new(line)) {
+            Err(Error::VersionMismatch { expected, found }) => {
+                assert_eq!(expected, PROTOCOL_VERSION);
+                assert_eq!(found, PROTOCOL_VERSION + 1);
+            }
+            res => panic!("expected a version mismatch, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn unknown_requests_are_errors() {
+        let line =
+            format!("{{\"version\":{PROTOCOL_VERSION},\"message\":{{\"type\":\"explode\"}}}}\n");
+
+        assert!(matches!(
+            read_message::<Request, _>(&mut Cursor::new(line)),
+            Err(Error::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use crate::{loader::START_TIME, prelude::*, utils::MyTime};
+
+/// Set to `1` to run on a virtual clock that only moves when frames are waited or the control
+/// interface advances it.
+pub const VIRTUAL_TIME_ENV: &str = "OPENXR_DEVICE_SIMULATOR_VIRTUAL_TIME";
+
+/// virtual time starts at the same offset as `START_TIME`
+const VIRTUAL_START: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// latest time apps can be handed, `XrTime` counts nanoseconds in an `i64`
+pub const MAX_TIME: Duration = Duration::from_nanos(i64::MAX as u64);
+
+static VIRTUAL_TIME: LazyLock<Option<Mutex<Duration>>> = LazyLock::new(|| {
+    let enabled = std::env::var(VIRTUAL_TIME_ENV).is_ok_and(|value| value == "1");
+    if enabled {
+        log::info!("running on a virtual clock");
+    }
+    enabled.then(|| Mutex::new(VIRTUAL_START))
+});
+
+pub fn is_virtual() -> bool {
+    VIRTUAL_TIME.is_some()
+}
+
+/// Time since the runtime's epoch, the source of every timestamp handed to apps.
+pub fn now() -> Duration {
+    match &*VIRTUAL_TIME {
+        Some(time) => *time.lock().expect("couldn't acquire the virtual clock"),
+        None => START_TIME.elapsed(),
+    }
+}
+
+pub fn now_xr() -> xr::Time {
+    MyTime::from(now()).into()
+}
+
+/// Time `delay` from now, unless it's past `MAX_TIME`.
+pub fn after(delay: Duration) -> Option<Duration> {
+    now().checked_add(delay).filter(|time| *time <= MAX_TIME)
+}
+
+fn with_virtual_time<F>(f: F) -> Result<()>
+where
+    F: FnOnce(Duration) -> Result<Duration>,
+{
+    let Some(virtual_time) = &*VIRTUAL_TIME else {
+        return Err("the clock is not virtual".into());
+    };
+
+    let mut now = virtual_time.lock()?;
+    *now = f(*now)?;
+
+    Ok(())
+}
+
+/// Moves the virtual clock forward to `time`, it never goes backwards.
+pub fn advance_to(time: Duration) -> Result<()> {
+    with_virtual_time(|now| advanced_to(now, time))
+}
+
+pub fn advance_by(duration: Duration) -> Result<()> {
+    with_virtual_time(|now| advanced_by(now, duration))
+}
+
+fn advanced_to(now: Duration, time: Duration) -> Result<Duration> {
+    if time > MAX_TIME {
+        return Err(format!("can't advance the clock past {MAX_TIME:?}")
+            .as_str()
+            .into());
+    }
+
+    Ok(now.max(time))
+}
+
+fn advanced_by(now: Duration, duration: Duration) -> Result<Duration> {
+    let time = now
+        .checked_add(duration)
+        .ok_or("can't advance the clock that far")?;
+    advanced_to(now, time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advancing_by_adds_to_the_clock() {
+        let now = advanced_by(VIRTUAL_START, Duration::from_millis(11)).unwrap();
+        assert_eq!(now, VIRTUAL_START + Duration::from_millis(11));
+
+        assert_eq!(advanced_by(now, Duration::ZERO).unwrap(), now);
+    }
+
+    #[test]
+    fn advancing_to_never_goes_backwards() {
+        let now = VIRTUAL_START + Duration::from_secs(1);
+
+        assert_eq!(advanced_to(now, VIRTUAL_START).unwrap(), now);
+        assert_eq!(advanced_to(now, now).unwrap(), now);
+        assert_eq!(
+            advanced_to(now, now + Duration::from_nanos(1)).unwrap(),
+            now + Duration::from_nanos(1)
+        );
+    }
+
+    #[test]
+    fn advancing_past_the_latest_time_fails() {
+        assert_eq!(advanced_to(VIRTUAL_START, MAX_TIME).unwrap(), MAX_TIME);
+        assert!(advanced_to(VIRTUAL_START, MAX_TIME + Duration::from_nanos(1)).is_err());
+
+        assert!(advanced_by(MAX_TIME, Duration::from_nanos(1)).is_err());
+        assert!(advanced_by(VIRTUAL_START, Duration::from_nanos(u64::MAX)).is_err());
+        assert!(advanced_by(VIRTUAL_START, Duration::MAX).is_err());
+    }
+}
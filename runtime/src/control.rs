@@ -0,0 +1,366 @@
+use std::{
+    io::BufReader,
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    sync::atomic,
+    thread,
+    time::Duration,
+};
+
+use openxr_device_simulator_protocol::{
+    self as protocol, HapticVibration, ImageFormat, InputValue, Request, Response, SessionCommand,
+};
+
+use crate::{
+    clock,
+    device::{SimulatedInputValue, with_device},
+    haptics::HapticFeedback,
+    input::{interaction_profile::update_interaction_profiles, profiles::find_profile},
+    instance::api::lose_all_instances,
+    math::quat_normalize,
+    rendering::capture::{self, CaptureTarget},
+    session::{active_session_ids, with_all_sessions},
+    system,
+};
+
+/// how long a capture request waits for the app to submit a frame
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(5);
+
+static STARTED: atomic::AtomicBool = atomic::AtomicBool::new(false);
+
+/// Starts listening for control clients, only the first call has any effect.
+pub fn start() {
+    if STARTED.fetch_or(true, atomic::Ordering::SeqCst) {
+        return;
+    }
+
+    let path = protocol::socket_path();
+
+    let listener = match bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::error!("could not listen on {}: {err}", path.display());
+            return;
+        }
+    };
+
+    log::info!("control socket listening on {}", path.display());
+
+    let res = thread::Builder::new()
+        .name("xrsim-control".into())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        thread::spawn(move || serve_client(stream));
+                    }
+                    Err(err) => log::error!("control connection failed: {err}"),
+                }
+            }
+        });
+
+    if let Err(err) = res {
+        log::error!("could not start control thread: {err}");
+    }
+}
+
+fn bind(path: &Path) -> std::io::Result<UnixListener> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                "another runtime owns the control socket",
+            ));
+        }
+
+        // left behind by a runtime that didn't shut down cleanly
+        std::fs::remove_file(path)?;
+    }
+
+    UnixListener::bind(path)
+}
+
+fn serve_client(stream: UnixStream) {
+    let mut reader = match stream.try_clone() {
+        Ok(stream) => BufReader::new(stream),
+        Err(err) => {
+            log::error!("control connection failed: {err}");
+            return;
+        }
+    };
+    let mut writer = stream;
+
+    loop {
+        let response = match protocol::read_message::<Request, _>(&mut reader) {
+            Ok(Some(request)) => handle_request(request),
+            Ok(None) => break,
+            Err(err) => Response::Error {
+                message: err.to_string(),
+            },
+        };
+
+        if let Err(err) = protocol::write_message(&mut writer, &response) {
+            log::error!("control write failed: {err}");
+            break;
+        }
+    }
+}
+
+fn handle_request(request: Request) -> Response {
+    log::debug!("control request: {request:?}");
+
+    let res = match request {
+        Request::Ping => {
+            return Response::Pong {
+                runtime_version: env!("CARGO_PKG_VERSION").into(),
+            };
+        }
+        Request::SetHeadPose { pose } => {
+            with_device(|device| device.head.pose = to_xr_pose(&pose));
+            Ok(())
+        }
+        Request::SetIpd { ipd } => {
+            if !(ipd.is_finite() && ipd >= 0.0) {
+                return Response::Error {
+                    message: format!("invalid IPD {ipd}"),
+                };
+            }
+
+            with_device(|device| device.head.ipd = ipd);
+            Ok(())
+        }
+        Request::SetControllerPose { hand, pose } => {
+            with_device(|device| {
+                device
+                    .inputs
+                    .set_controller_pose(hand.user_path(), to_xr_pose(&pose))
+            });
+            Ok(())
+        }
+        Request::SetInput { path, value } => {
+            if !path.starts_with("/user/") {
+                return Response::Error {
+                    message: format!("invalid input path {path}"),
+                };
+            }
+
+            with_device(|device| device.inputs.set(&path, to_input_value(&value)));
+            Ok(())
+        }
+        Request::SetInteractionProfile { path } => {
+            if find_profile(&path).is_none() {
+                return Response::Error {
+                    message: format!("unknown interaction profile {path}"),
+                };
+            }
+
+            with_device(|device| device.inputs.interaction_profile = Some(path));
+
+            active_session_ids().and_then(|session_ids| {
+                session_ids
+                    .into_iter()
+                    .try_for_each(update_interaction_profiles)
+            })
+        }
+        Request::Session { command } => with_all_sessions(|session| match command {
+            SessionCommand::RequestExit => session.exit(),
+            SessionCommand::RemoveHeadset => session.set_headset_worn(false),
+            SessionCommand::PutOnHeadset => session.set_headset_worn(true),
+            SessionCommand::ShowOverlay => session.set_overlay_shown(true),
+            SessionCommand::HideOverlay => session.set_overlay_shown(false),
+            SessionCommand::Lose => session.lose(),
+        }),
+        Request::LoseInstance { nanos } => lose_all_instances(Duration::from_nanos(nanos)),
+        Request::SetSystemUnavailable { nanos } => {
+            system::set_unavailable_for(Duration::from_nanos(nanos))
+        }
+        Request::GetHaptics => {
+            let now = clock::now_xr();
+            let mut active = Vec::new();
+            let mut history = Vec::new();
+
+            let res = with_all_sessions(|session| {
+                session.haptics.expire(now);
+                active.extend(session.haptics.current().map(to_haptic_vibration));
+                history.extend(session.haptics.history().map(to_haptic_vibration));
+                Ok(())
+            });
+
+            if let Err(err) = res {
+                return Response::Error {
+                    message: err.to_string(),
+                };
+            }
+
+            active.sort_by_key(|vibration| vibration.start_time);
+            history.sort_by_key(|vibration| vibration.start_time);
+
+            return Response::Haptics {
+                time: now.as_nanos(),
+                active,
+                history,
+            };
+        }
+        Request::ClearHapticHistory => with_all_sessions(|session| {
+            session.haptics.clear_history();
+            Ok(())
+        }),
+        Request::GetTime => {
+            return Response::Time {
+                time: clock::now_xr().as_nanos(),
+                is_virtual: clock::is_virtual(),
+            };
+        }
+        Request::AdvanceTime { nanos } => clock::advance_by(Duration::from_nanos(nanos)),
+        Request::CaptureFrame { dir, format } => {
+            let mut receivers = Vec::new();
+            let res = with_all_sessions(|session| {
+                receivers.push(session.capture.request(CaptureTarget {
+                    dir: dir.clone().into(),
+                    format: to_image_format(format),
+                }));
+                Ok(())
+            });
+
+            if let Err(err) = res {
+                return Response::Error {
+                    message: err.to_string(),
+                };
+            }
+
+            let mut files = Vec::new();
+            for receiver in receivers {
+                match receiver.recv_timeout(CAPTURE_TIMEOUT) {
+                    Ok(Ok(captured)) => {
+                        files.extend(captured.into_iter().map(|path| path.display().to_string()))
+                    }
+                    Ok(Err(message)) => return Response::Error { message },
+                    Err(_) => {
+                        return Response::Error {
+                            message: "no frame was submitted in time".into(),
+                        };
+                    }
+                }
+            }
+
+            return Response::Captured { files };
+        }
+        Request::SetContinuousCapture { dir, format } => with_all_sessions(|session| {
+            session.capture.continuous = dir.as_ref().map(|dir| CaptureTarget {
+                dir: dir.into(),
+                format: to_image_format(format),
+            });
+            Ok(())
+        }),
+        Request::StepFrames { count } => {
+            let mut display_period = None;
+            with_all_sessions(|session| {
+                display_period = Some(session.profile.display_period());
+                Ok(())
+            })
+            .and_then(|_| {
+                let display_period = display_period.unwrap_or_default();
+                display_period
+                    .checked_mul(count)
+                    .ok_or_else(|| "too many frames to step".into())
+                    .and_then(clock::advance_by)
+            })
+        }
+    };
+
+    match res {
+        Ok(()) => Response::Ok,
+        Err(err) => Response::Error {
+            message: err.to_string(),
+        },
+    }
+}
+
+fn to_xr_pose(pose: &protocol::Pose) -> xr::Posef {
+    let [x, y, z] = pose.position;
+    let [qx, qy, qz, qw] = pose.orientation;
+
+    xr::Posef {
+        orientation: quat_normalize(&xr::Quaternionf {
+            x: qx,
+            y: qy,
+            z: qz,
+            w: qw,
+        }),
+        position: xr::Vector3f { x, y, z },
+    }
+}
+
+fn to_input_value(value: &InputValue) -> SimulatedInputValue {
+    match *value {
+        InputValue::Boolean(value) => SimulatedInputValue::Boolean(value),
+        InputValue::Float(value) => SimulatedInputValue::Float(value),
+        InputValue::Vector2f([x, y]) => SimulatedInputValue::Vector2f(xr::Vector2f { x, y }),
+    }
+}
+
+fn to_haptic_vibration(feedback: &HapticFeedback) -> HapticVibration {
+    HapticVibration {
+        action: feedback.action_name.clone(),
+        subaction_path: feedback.subaction_path_name.clone(),
+        outputs: feedback.outputs.clone(),
+        start_time: feedback.start_time.as_nanos(),
+        duration: feedback.duration.as_nanos(),
+        frequency: feedback.frequency,
+        amplitude: feedback.amplitude,
+        stop_time: feedback.stop_time.map(|time| time.as_nanos()),
+    }
+}
+
+fn to_image_format(format: ImageFormat) -> capture::ImageFormat {
+    match format {
+        ImageFormat::Png => capture::ImageFormat::Png,
+        ImageFormat::Ppm => capture::ImageFormat::Ppm,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_ipds_are_rejected() {
+        for ipd in [f32::NAN, f32::INFINITY, -0.063] {
+            assert!(
+                matches!(
+                    handle_request(Request::SetIpd { ipd }),
+                    Response::Error { .. }
+                ),
+                "{ipd} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn poses_are_normalized() {
+        let pose = to_xr_pose(&protocol::Pose {
+            position: [1.0, 2.0, 3.0],
+            orientation: [0.0, 2.0, 0.0, 2.0],
+        });
+
+        let q = pose.orientation;
+        assert!((q.x * q.x + q.y * q.y + q.z * q.z + q.w * q.w - 1.0).abs() < 1e-5);
+        assert!((q.y - q.w).abs() < 1e-5);
+        assert_eq!(pose.position.z, 3.0);
+    }
+
+    #[test]
+    fn input_paths_must_be_user_paths() {
+        assert!(matches!(
+            handle_request(Request::SetInput {
+                path: "/interaction_profiles/khr/simple_controller".into(),
+                value: InputValue::Boolean(true),
+            }),
+            Response::Error { .. }
+        ));
+    }
+}
@@ -0,0 +1,492 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex, PoisonError},
+};
+
+use crate::{
+    math::{pose_multiply, quat_rotate},
+    utils::create_identity_pose,
+};
+
+pub const DEFAULT_IPD: f32 = 0.063;
+pub const DEFAULT_EYE_HEIGHT: f32 = 1.6;
+pub const DEFAULT_PLAY_AREA: xr::Extent2Df = xr::Extent2Df {
+    width: 2.0,
+    height: 2.0,
+};
+
+// per-eye field of view in radians, slightly wider towards the temples
+const DEFAULT_FOV: [xr::Fovf; 2] = [
+    xr::Fovf {
+        angle_left: -0.942,
+        angle_right: 0.785,
+        angle_up: 0.873,
+        angle_down: -0.942,
+    },
+    xr::Fovf {
+        angle_left: -0.785,
+        angle_right: 0.942,
+        angle_up: 0.873,
+        angle_down: -0.942,
+    },
+];
+
+/// The simulated headset and controllers. There's a single one for the whole runtime, so the
+/// control clients can set it up before the app creates a session and it stays as it was when the
+/// app recreates one.
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedDevice {
+    pub(crate) head: SimulatedHead,
+    pub(crate) inputs: SimulatedInputs,
+}
+
+static DEVICE: LazyLock<Mutex<SimulatedDevice>> =
+    LazyLock::new(|| Mutex::new(SimulatedDevice::default()));
+
+/// Runs `f` on the simulated device. It only holds plain values, so it's still usable after a
+/// panic while it was locked.
+pub fn with_device<T, F>(f: F) -> T
+where
+    F: FnOnce(&mut SimulatedDevice) -> T,
+{
+    let mut device = DEVICE.lock().unwrap_or_else(PoisonError::into_inner);
+    f(&mut device)
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulatedHead {
+    /// pose of the head in the tracking origin, which sits at floor level
+    pub(crate) pose: xr::Posef,
+    pub(crate) linear_velocity: xr::Vector3f,
+    pub(crate) angular_velocity: xr::Vector3f,
+    pub(crate) ipd: f32,
+    pub(crate) fov: [xr::Fovf; 2],
+}
+
+impl Default for SimulatedHead {
+    fn default() -> Self {
+        let mut pose = create_identity_pose();
+        pose.position.y = DEFAULT_EYE_HEIGHT;
+
+        Self {
+            pose,
+            linear_velocity: xr::Vector3f::default(),
+            angular_velocity: xr::Vector3f::default(),
+            ipd: DEFAULT_IPD,
+            fov: DEFAULT_FOV,
+        }
+    }
+}
+
+impl SimulatedHead {
+    /// head pose with pitch and roll removed, used as the origin of the LOCAL space
+    pub fn gravity_aligned_pose(&self) -> xr::Posef {
+        let forward = quat_rotate(
+            &self.pose.orientation,
+            &xr::Vector3f {
+                x: 0.0,
+                y: 0.0,
+                z: -1.0,
+            },
+        );
+        let half_yaw = (-forward.x).atan2(-forward.z) / 2.0;
+
+        xr::Posef {
+            orientation: xr::Quaternionf {
+                x: 0.0,
+                y: half_yaw.sin(),
+                z: 0.0,
+                w: half_yaw.cos(),
+            },
+            position: self.pose.position,
+        }
+    }
+
+    /// pose of the eye (0 = left, 1 = right) relative to the head
+    pub fn eye_offset(&self, eye: usize) -> xr::Posef {
+        let half_ipd = self.ipd / 2.0;
+        let mut pose = create_identity_pose();
+        pose.position.x = if eye == 0 { -half_ipd } else { half_ipd };
+        pose
+    }
+
+    /// pose of the eye (0 = left, 1 = right) in the tracking origin
+    pub fn eye_pose(&self, eye: usize) -> xr::Posef {
+        pose_multiply(&self.pose, &self.eye_offset(eye))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SimulatedInputValue {
+    Boolean(bool),
+    Float(f32),
+    Vector2f(xr::Vector2f),
+    Pose(xr::Posef),
+}
+
+impl SimulatedInputValue {
+    /// converts the value to the kind an action of `action_type` holds, `None` when a source of
+    /// this kind can't drive such an action
+    pub fn convert_to(&self, action_type: xr::ActionType) -> Option<Self> {
+        match (action_type, *self) {
+            (xr::ActionType::BOOLEAN_INPUT, Self::Boolean(_))
+            | (xr::ActionType::FLOAT_INPUT, Self::Float(_))
+            | (xr::ActionType::VECTOR2F_INPUT, Self::Vector2f(_))
+            | (xr::ActionType::POSE_INPUT, Self::Pose(_)) => Some(*self),
+            (xr::ActionType::BOOLEAN_INPUT, Self::Float(value)) => {
+                Some(Self::Boolean(value > FLOAT_TO_BOOLEAN_THRESHOLD))
+            }
+            (xr::ActionType::FLOAT_INPUT, Self::Boolean(value)) => {
+                Some(Self::Float(if value { 1.0 } else { 0.0 }))
+            }
+            _ => None,
+        }
+    }
+}
+
+const FLOAT_TO_BOOLEAN_THRESHOLD: f32 = 0.5;
+
+pub const HAND_USER_PATHS: [&str; 2] = ["/user/hand/left", "/user/hand/right"];
+
+// input sources of both simulated controllers, a superset of the common interaction profiles
+const CONTROLLER_INPUTS: &[(&str, SimulatedInputValue)] = &[
+    ("select/click", SimulatedInputValue::Boolean(false)),
+    ("menu/click", SimulatedInputValue::Boolean(false)),
+    ("squeeze/click", SimulatedInputValue::Boolean(false)),
+    ("squeeze/value", SimulatedInputValue::Float(0.0)),
+    ("trigger/click", SimulatedInputValue::Boolean(false)),
+    ("trigger/touch", SimulatedInputValue::Boolean(false)),
+    ("trigger/value", SimulatedInputValue::Float(0.0)),
+    (
+        "thumbstick",
+        SimulatedInputValue::Vector2f(xr::Vector2f { x: 0.0, y: 0.0 }),
+    ),
+    ("thumbstick/click", SimulatedInputValue::Boolean(false)),
+    ("thumbstick/touch", SimulatedInputValue::Boolean(false)),
+    (
+        "trackpad",
+        SimulatedInputValue::Vector2f(xr::Vector2f { x: 0.0, y: 0.0 }),
+    ),
+    ("trackpad/click", SimulatedInputValue::Boolean(false)),
+    ("trackpad/touch", SimulatedInputValue::Boolean(false)),
+];
+
+const HAND_INPUTS: [&[&str]; 2] = [
+    &["x/click", "x/touch", "y/click", "y/touch"],
+    &["a/click", "a/touch", "b/click", "b/touch", "system/click"],
+];
+
+/// state of the simulated input sources, keyed by their full path, e.g.
+/// `/user/hand/left/input/trigger/value`
+#[derive(Debug, Clone)]
+pub struct SimulatedInputs {
+    /// interaction profile of the simulated controllers set by a control client, the device
+    /// profile's when `None`. Other profiles the app suggested bindings for are mapped to the same
+    /// sources when the app didn't suggest this one.
+    pub(crate) interaction_profile: Option<String>,
+    pub(crate) values: HashMap<String, SimulatedInputValue>,
+}
+
+impl Default for SimulatedInputs {
+    fn default() -> Self {
+        let mut inputs = Self {
+            interaction_profile: None,
+            values: HashMap::new(),
+        };
+
+        for (hand, (user_path, x)) in HAND_USER_PATHS.into_iter().zip([-0.2, 0.2]).enumerate() {
+            for (component, value) in CONTROLLER_INPUTS {
+                inputs.set(&format!("{user_path}/input/{component}"), *value);
+            }
+
+            for component in HAND_INPUTS[hand] {
+                inputs.set(
+                    &format!("{user_path}/input/{component}"),
+                    SimulatedInputValue::Boolean(false),
+                );
+            }
+
+            let mut pose = create_identity_pose();
+            pose.position = xr::Vector3f {
+                x,
+                y: DEFAULT_EYE_HEIGHT - 0.4,
+                z: -0.3,
+            };
+            inputs.set_controller_pose(user_path, pose);
+        }
+
+        inputs
+    }
+}
+
+impl SimulatedInputs {
+    pub fn set(&mut self, path: &str, value: SimulatedInputValue) {
+        self.values.insert(path.into(), value);
+    }
+
+    pub fn set_controller_pose(&mut self, user_path: &str, pose: xr::Posef) {
+        for component in ["grip", "aim"] {
+            self.set(
+                &format!("{user_path}/input/{component}/pose"),
+                SimulatedInputValue::Pose(pose),
+            );
+        }
+    }
+
+    /// Value of the source a binding points to, converted for an action of `action_type`.
+    /// Bindings to a component without its identifier pick the one matching the action type,
+    /// e.g. `.../input/trigger` is `.../input/trigger/value` for a float action and
+    /// `.../input/trigger/click` for a boolean one.
+    pub fn resolve(
+        &self,
+        binding: &str,
+        action_type: xr::ActionType,
+    ) -> Option<SimulatedInputValue> {
+        let identifiers: &[&str] = match action_type {
+            xr::ActionType::BOOLEAN_INPUT => &["", "/click", "/value"],
+            xr::ActionType::FLOAT_INPUT => &["", "/value", "/click"],
+            xr::ActionType::POSE_INPUT => &["", "/pose"],
+            _ => &[""],
+        };
+
+        identifiers
+            .iter()
+            .find_map(|identifier| self.get(&format!("{binding}{identifier}")))
+            .and_then(|value| value.convert_to(action_type))
+    }
+
+    fn get(&self, path: &str) -> Option<SimulatedInputValue> {
+        if let Some(value) = self.values.get(path) {
+            return Some(*value);
+        }
+
+        // single axis of a 2D source, e.g. `.../input/thumbstick/x`
+        let (parent, axis) = path.rsplit_once('/')?;
+        match (self.values.get(parent)?, axis) {
+            (SimulatedInputValue::Vector2f(value), "x") => {
+                Some(SimulatedInputValue::Float(value.x))
+            }
+            (SimulatedInputValue::Vector2f(value), "y") => {
+                Some(SimulatedInputValue::Float(value.y))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::{pose_inverse, quat_multiply},
+        test_utils::{assert_vec3_eq, yaw},
+    };
+
+    #[test]
+    fn eyes_are_ipd_apart() {
+        let head = SimulatedHead {
+            ipd: 0.06,
+            ..Default::default()
+        };
+
+        assert_vec3_eq(
+            &head.eye_pose(0).position,
+            &xr::Vector3f {
+                x: -0.03,
+                y: DEFAULT_EYE_HEIGHT,
+                z: 0.0,
+            },
+        );
+        assert_vec3_eq(
+            &head.eye_pose(1).position,
+            &xr::Vector3f {
+                x: 0.03,
+                y: DEFAULT_EYE_HEIGHT,
+                z: 0.0,
+            },
+        );
+    }
+
+    #[test]
+    fn eyes_turn_with_the_head() {
+        let mut head = SimulatedHead {
+            ipd: 0.06,
+            ..Default::default()
+        };
+        // looking down -X, the right eye is on the -Z side
+        head.pose.orientation = yaw(std::f32::consts::FRAC_PI_2);
+
+        assert_vec3_eq(
+            &head.eye_pose(0).position,
+            &xr::Vector3f {
+                x: 0.0,
+                y: DEFAULT_EYE_HEIGHT,
+                z: 0.03,
+            },
+        );
+        assert_vec3_eq(
+            &head.eye_pose(1).position,
+            &xr::Vector3f {
+                x: 0.0,
+                y: DEFAULT_EYE_HEIGHT,
+                z: -0.03,
+            },
+        );
+    }
+
+    #[test]
+    fn views_in_the_view_space_are_the_eye_offsets() {
+        let mut head = SimulatedHead::default();
+        head.pose.orientation = yaw(0.7);
+        head.pose.position.x = 1.5;
+
+        for eye in 0..2 {
+            let view = pose_multiply(&pose_inverse(&head.pose), &head.eye_pose(eye));
+            assert_vec3_eq(&view.position, &head.eye_offset(eye).position);
+            assert!((view.orientation.w.abs() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn fov_is_wider_towards_the_temples() {
+        let [left, right] = SimulatedHead::default().fov;
+
+        assert!(-left.angle_left > left.angle_right);
+        assert!(right.angle_right > -right.angle_left);
+        assert_eq!(left.angle_left, -right.angle_right);
+        assert_eq!(left.angle_right, -right.angle_left);
+    }
+
+    #[test]
+    fn gravity_aligned_pose_keeps_only_the_yaw() {
+        let mut head = SimulatedHead::default();
+        let pitch = xr::Quaternionf {
+            x: (0.2f32).sin(),
+            y: 0.0,
+            z: 0.0,
+            w: (0.2f32).cos(),
+        };
+        let roll = xr::Quaternionf {
+            x: 0.0,
+            y: 0.0,
+            z: (-0.3f32).sin(),
+            w: (-0.3f32).cos(),
+        };
+        head.pose.orientation = quat_multiply(&quat_multiply(&yaw(0.5), &pitch), &roll);
+        head.pose.position.z = -0.4;
+
+        let aligned = head.gravity_aligned_pose();
+        let expected = yaw(0.5);
+
+        assert!((aligned.orientation.y - expected.y).abs() < 1e-5);
+        assert!((aligned.orientation.w - expected.w).abs() < 1e-5);
+        assert_eq!(aligned.orientation.x, 0.0);
+        assert_eq!(aligned.orientation.z, 0.0);
+        assert_vec3_eq(&aligned.position, &head.pose.position);
+    }
+
+    #[test]
+    fn gravity_aligned_pose_of_a_level_head_is_the_head_pose() {
+        let mut head = SimulatedHead::default();
+        head.pose.orientation = yaw(-2.0);
+
+        let aligned = head.gravity_aligned_pose();
+
+        assert!((aligned.orientation.y - head.pose.orientation.y).abs() < 1e-5);
+        assert!((aligned.orientation.w - head.pose.orientation.w).abs() < 1e-5);
+    }
+
+    #[test]
+    fn components_resolve_to_the_identifier_of_the_action_type() {
+        let mut inputs = SimulatedInputs::default();
+        inputs.set(
+            "/user/hand/left/input/trigger/value",
+            SimulatedInputValue::Float(0.7),
+        );
+
+        assert!(matches!(
+            inputs.resolve("/user/hand/left/input/trigger", xr::ActionType::FLOAT_INPUT),
+            Some(SimulatedInputValue::Float(value)) if value == 0.7
+        ));
+        assert!(matches!(
+            inputs.resolve(
+                "/user/hand/left/input/trigger",
+                xr::ActionType::BOOLEAN_INPUT
+            ),
+            Some(SimulatedInputValue::Boolean(false))
+        ));
+    }
+
+    #[test]
+    fn floats_and_booleans_convert_to_each_other() {
+        let mut inputs = SimulatedInputs::default();
+        inputs.set(
+            "/user/hand/right/input/squeeze/value",
+            SimulatedInputValue::Float(0.8),
+        );
+        inputs.set(
+            "/user/hand/right/input/a/click",
+            SimulatedInputValue::Boolean(true),
+        );
+
+        assert!(matches!(
+            inputs.resolve(
+                "/user/hand/right/input/squeeze/value",
+                xr::ActionType::BOOLEAN_INPUT
+            ),
+            Some(SimulatedInputValue::Boolean(true))
+        ));
+        assert!(matches!(
+            inputs.resolve("/user/hand/right/input/a/click", xr::ActionType::FLOAT_INPUT),
+            Some(SimulatedInputValue::Float(value)) if value == 1.0
+        ));
+        assert!(
+            inputs
+                .resolve("/user/hand/right/input/a/click", xr::ActionType::POSE_INPUT)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn axes_of_2d_sources_resolve_to_floats() {
+        let mut inputs = SimulatedInputs::default();
+        inputs.set(
+            "/user/hand/left/input/thumbstick",
+            SimulatedInputValue::Vector2f(xr::Vector2f { x: 0.25, y: -0.5 }),
+        );
+
+        assert!(matches!(
+            inputs.resolve(
+                "/user/hand/left/input/thumbstick/y",
+                xr::ActionType::FLOAT_INPUT
+            ),
+            Some(SimulatedInputValue::Float(value)) if value == -0.5
+        ));
+        assert!(
+            inputs
+                .resolve(
+                    "/user/hand/left/input/thumbstick/z",
+                    xr::ActionType::FLOAT_INPUT
+                )
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn controller_poses_resolve_for_grip_and_aim() {
+        let mut inputs = SimulatedInputs::default();
+        let mut pose = create_identity_pose();
+        pose.position.x = 0.5;
+        inputs.set_controller_pose("/user/hand/right", pose);
+
+        for binding in [
+            "/user/hand/right/input/grip",
+            "/user/hand/right/input/aim/pose",
+        ] {
+            assert!(matches!(
+                inputs.resolve(binding, xr::ActionType::POSE_INPUT),
+                Some(SimulatedInputValue::Pose(pose)) if pose.position.x == 0.5
+            ));
+        }
+    }
+}
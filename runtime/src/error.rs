@@ -19,6 +19,9 @@ pub enum Error {
 
     #[error("Utf8Error: {0}")]
     Utf8Error(#[from] std::str::Utf8Error),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 impl From<&str> for Error {
@@ -7,6 +7,7 @@ use std::{
 use crate::{
     instance::api::with_instance,
     prelude::*,
+    session::{check_ready_sessions, with_session},
     utils::{MyTime, with_obj_instance},
 };
 
@@ -25,18 +26,36 @@ pub extern "system" fn poll(
         Err(err) => return err.into(),
     };
 
-    with_event_queue(queue_id, |queue| {
-        if let Some(item) = queue.pop_front() {
-            log::debug!("polled event {:?}", item.ty);
-            event_data.ty = item.ty;
-            let dest_slice = &mut event_data.varying[..item.buf.len()];
-            dest_slice.copy_from_slice(&item.buf);
-            Ok(xr::Result::SUCCESS)
-        } else {
-            Ok(xr::Result::EVENT_UNAVAILABLE)
-        }
-    })
-    .into_xr_result()
+    if let Err(err) = check_ready_sessions(queue_id) {
+        return err.into();
+    }
+
+    // sessions schedule events with their lock held, so they're told about polled states after
+    // the queue is unlocked
+    let item = match with_event_queue(queue_id, |queue| Ok(queue.pop_front())) {
+        Ok(Some(item)) => item,
+        Ok(None) => return xr::Result::EVENT_UNAVAILABLE,
+        Err(err) => return err.into(),
+    };
+
+    log::debug!("polled event {:?}", item.ty);
+    event_data.ty = item.ty;
+    let dest_slice = &mut event_data.varying[..item.buf.len()];
+    dest_slice.copy_from_slice(&item.buf);
+
+    if item.ty == xr::StructureType::EVENT_DATA_SESSION_STATE_CHANGED {
+        let event = unsafe {
+            &*(event_data as *const xr::EventDataBuffer as *const xr::EventDataSessionStateChanged)
+        };
+        // the session may be gone already
+        with_session(event.session.into_raw(), |session| {
+            session.state_polled(event.state);
+            Ok(())
+        })
+        .ok();
+    }
+
+    xr::Result::SUCCESS
 }
 
 pub fn create_queue(queue_id: u64) -> Result<()> {
@@ -46,12 +65,31 @@ pub fn create_queue(queue_id: u64) -> Result<()> {
     Ok(())
 }
 
+pub fn destroy_queue(queue_id: u64) -> Result<()> {
+    INSTANCES.lock()?.remove(&queue_id);
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct QueueItem {
     ty: xr::StructureType,
     buf: Box<[u8]>,
 }
 
+impl QueueItem {
+    /// copies an `XrEventData*` struct, the buffer leaves out the `ty` and `next` header
+    fn new<T>(xr_event: &T) -> Self {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(xr_event as *const T as *const u8, std::mem::size_of::<T>())
+        };
+
+        Self {
+            ty: unsafe { *(xr_event as *const T as *const xr::StructureType) },
+            buf: bytes[SIZEOF_TY_NEXT..].into(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Event {
     SessionStateChanged {
@@ -59,6 +97,12 @@ pub enum Event {
         state: xr::SessionState,
         time: MyTime,
     },
+    InteractionProfileChanged {
+        session: xr::Session,
+    },
+    InstanceLossPending {
+        loss_time: MyTime,
+    },
 }
 
 type SharedEventQueue = UnsafeCell<VecDeque<QueueItem>>;
@@ -77,36 +121,35 @@ const SIZEOF_TY_NEXT: usize = std::mem::size_of::<xr::EventDataBaseHeader>();
 
 pub fn schedule_event(queue_id: u64, event: &Event) -> Result<()> {
     with_event_queue(queue_id, |queue| {
-        let (ptr, size) = match event {
+        let item = match event {
             Event::SessionStateChanged {
                 session,
                 state,
                 time,
-            } => {
-                let ty = xr::StructureType::EVENT_DATA_SESSION_STATE_CHANGED;
-                let xr_event = xr::EventDataSessionStateChanged {
-                    ty,
+            } => QueueItem::new(&xr::EventDataSessionStateChanged {
+                ty: xr::StructureType::EVENT_DATA_SESSION_STATE_CHANGED,
+                next: std::ptr::null_mut(),
+                session: *session,
+                state: *state,
+                time: (*time).into(),
+            }),
+            Event::InteractionProfileChanged { session } => {
+                QueueItem::new(&xr::EventDataInteractionProfileChanged {
+                    ty: xr::StructureType::EVENT_DATA_INTERACTION_PROFILE_CHANGED,
                     next: std::ptr::null_mut(),
                     session: *session,
-                    state: *state,
-                    time: (*time).into(),
-                };
-                let size = std::mem::size_of::<xr::EventDataSessionStateChanged>();
-                let ptr = &xr_event as *const _ as *const u8;
-                (ptr, size)
+                })
+            }
+            Event::InstanceLossPending { loss_time } => {
+                QueueItem::new(&xr::EventDataInstanceLossPending {
+                    ty: xr::StructureType::EVENT_DATA_INSTANCE_LOSS_PENDING,
+                    next: std::ptr::null(),
+                    loss_time: (*loss_time).into(),
+                })
             }
         };
 
-        let slice: &[u8] =
-            unsafe { std::slice::from_raw_parts(ptr.add(SIZEOF_TY_NEXT), size - SIZEOF_TY_NEXT) };
-
-        let mut buf = vec![0_u8; size - SIZEOF_TY_NEXT];
-        buf.copy_from_slice(slice);
-
-        queue.push_back(QueueItem {
-            ty: unsafe { *(ptr as *const xr::StructureType) },
-            buf: buf.into_boxed_slice(),
-        });
+        queue.push_back(item);
         Ok(())
     })
 }
@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+use crate::{
+    input::{action, action_set},
+    instance,
+    prelude::*,
+    rendering::swapchain,
+    session, spaces,
+};
+
+/// An object handed out to apps. Every type counts its ids on its own, so the type is part of the
+/// handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Handle {
+    Instance(u64),
+    Session(u64),
+    Space(u64),
+    Swapchain(u64),
+    ActionSet(u64),
+    Action(u64),
+}
+
+#[derive(Debug)]
+struct HandleNode {
+    parent: Option<Handle>,
+    children: Vec<Handle>,
+}
+
+/// every live handle with the one it was created from
+static HANDLES: LazyLock<Mutex<HashMap<Handle, HandleNode>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Tracks a new handle as a child of the one it was created from.
+pub fn register(handle: Handle, parent: Option<Handle>) -> Result<()> {
+    let mut handles = HANDLES.lock()?;
+
+    if let Some(parent) = parent {
+        let Some(parent_node) = handles.get_mut(&parent) else {
+            return Err(xr::Result::ERROR_HANDLE_INVALID.into());
+        };
+        parent_node.children.push(handle);
+    }
+
+    handles.insert(
+        handle,
+        HandleNode {
+            parent,
+            children: Vec::new(),
+        },
+    );
+
+    Ok(())
+}
+
+/// Destroys a handle and everything created from it, children before their parents.
+pub fn destroy(handle: Handle) -> Result<()> {
+    // the objects are released with the tree unlocked, they lock their own maps
+    let destroyed = {
+        let mut handles = HANDLES.lock()?;
+
+        let Some(parent) = handles.get(&handle).map(|node| node.parent) else {
+            return Err(xr::Result::ERROR_HANDLE_INVALID.into());
+        };
+
+        if let Some(parent_node) = parent.and_then(|parent| handles.get_mut(&parent)) {
+            parent_node.children.retain(|&child| child != handle);
+        }
+
+        let mut destroyed = Vec::new();
+        remove_subtree(&mut handles, handle, &mut destroyed);
+        destroyed
+    };
+
+    for handle in destroyed {
+        log::debug!("destroying {handle:?}");
+
+        let res = match handle {
+            Handle::Instance(id) => instance::api::remove(id),
+            Handle::Session(id) => session::remove(id),
+            Handle::Space(id) => spaces::remove(id),
+            Handle::Swapchain(id) => swapchain::remove(id),
+            Handle::ActionSet(id) => action_set::remove(id),
+            Handle::Action(id) => action::remove(id),
+        };
+
+        if let Err(err) = res {
+            log::error!("couldn't destroy {handle:?}: {err}");
+        }
+    }
+
+    Ok(())
+}
+
+fn remove_subtree(
+    handles: &mut HashMap<Handle, HandleNode>,
+    handle: Handle,
+    destroyed: &mut Vec<Handle>,
+) {
+    let Some(node) = handles.remove(&handle) else {
+        return;
+    };
+
+    for child in node.children {
+        remove_subtree(handles, child, destroyed);
+    }
+
+    destroyed.push(handle);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(edges: &[(Handle, Option<Handle>)]) -> HashMap<Handle, HandleNode> {
+        let mut handles: HashMap<Handle, HandleNode> = HashMap::new();
+        for &(handle, parent) in edges {
+            if let Some(node) = parent.and_then(|parent| handles.get_mut(&parent)) {
+                node.children.push(handle);
+            }
+            handles.insert(
+                handle,
+                HandleNode {
+                    parent,
+                    children: Vec::new(),
+                },
+            );
+        }
+        handles
+    }
+
+    fn is_live(handle: Handle) -> bool {
+        HANDLES.lock().unwrap().contains_key(&handle)
+    }
+
+    #[test]
+    fn children_are_removed_before_their_parents() {
+        let instance = Handle::Instance(1);
+        let session = Handle::Session(1);
+        let space = Handle::Space(1);
+        let swapchain = Handle::Swapchain(1);
+        let action_set = Handle::ActionSet(1);
+        let action = Handle::Action(1);
+        let mut handles = tree(&[
+            (instance, None),
+            (session, Some(instance)),
+            (space, Some(session)),
+            (swapchain, Some(session)),
+            (action_set, Some(instance)),
+            (action, Some(action_set)),
+        ]);
+
+        let mut destroyed = Vec::new();
+        remove_subtree(&mut handles, instance, &mut destroyed);
+
+        assert!(handles.is_empty());
+        assert_eq!(
+            destroyed,
+            [space, swapchain, session, action, action_set, instance]
+        );
+    }
+
+    #[test]
+    fn only_the_subtree_is_removed() {
+        let session = Handle::Session(1);
+        let space = Handle::Space(1);
+        let action_set = Handle::ActionSet(1);
+        let mut handles = tree(&[
+            (Handle::Instance(1), None),
+            (session, Some(Handle::Instance(1))),
+            (space, Some(session)),
+            (action_set, Some(Handle::Instance(1))),
+        ]);
+
+        let mut destroyed = Vec::new();
+        remove_subtree(&mut handles, session, &mut destroyed);
+
+        assert_eq!(destroyed, [space, session]);
+        assert!(handles.contains_key(&Handle::Instance(1)));
+        assert!(handles.contains_key(&action_set));
+    }
+
+    // the ids are unique to each test, the tree is shared with every other test in the process
+    #[test]
+    fn destroying_a_parent_destroys_its_children() {
+        let instance = Handle::Instance(u64::MAX - 101);
+        let session = Handle::Session(u64::MAX - 101);
+        let space = Handle::Space(u64::MAX - 101);
+        register(instance, None).unwrap();
+        register(session, Some(instance)).unwrap();
+        register(space, Some(session)).unwrap();
+
+        destroy(instance).unwrap();
+
+        assert!(!is_live(instance));
+        assert!(!is_live(session));
+        assert!(!is_live(space));
+    }
+
+    #[test]
+    fn destroying_a_child_keeps_its_parent() {
+        let instance = Handle::Instance(u64::MAX - 102);
+        let session = Handle::Session(u64::MAX - 102);
+        register(instance, None).unwrap();
+        register(session, Some(instance)).unwrap();
+
+        destroy(session).unwrap();
+
+        assert!(is_live(instance));
+        assert!(!is_live(session));
+        assert!(HANDLES.lock().unwrap()[&instance].children.is_empty());
+
+        destroy(instance).unwrap();
+    }
+
+    #[test]
+    fn destroyed_handles_are_invalid() {
+        let instance = Handle::Instance(u64::MAX - 103);
+        register(instance, None).unwrap();
+        destroy(instance).unwrap();
+
+        assert!(matches!(
+            destroy(instance),
+            Err(Error::XrResult(xr::Result::ERROR_HANDLE_INVALID))
+        ));
+        assert!(matches!(
+            register(Handle::Session(u64::MAX - 103), Some(instance)),
+            Err(Error::XrResult(xr::Result::ERROR_HANDLE_INVALID))
+        ));
+    }
+}
@@ -1,6 +1,16 @@
-use crate::{prelude::*, session::with_session};
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    clock, input::action::with_action, instance::api::with_instance, prelude::*,
+    session::with_session,
+};
+
+/// length of the pulse played for `XR_MIN_HAPTIC_DURATION`
+const MIN_HAPTIC_DURATION_NANOS: i64 = 10_000_000;
+
+/// how many finished vibrations are kept around for the control interface
+const HISTORY_LEN: usize = 256;
 
-#[allow(unreachable_code)]
 pub extern "system" fn apply_feedback(
     xr_session: xr::Session,
     info: *const xr::HapticActionInfo,
@@ -16,15 +26,62 @@ pub extern "system" fn apply_feedback(
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
-    with_session(xr_session.into_raw(), |_session| {
-        log::debug!("apply_feedback {info:?}, {header:?}");
-        return Err(xr::Result::ERROR_FUNCTION_UNSUPPORTED.into());
-        Ok(())
+    let vibration = match header.ty {
+        xr::StructureType::HAPTIC_VIBRATION => unsafe {
+            &*(header as *const _ as *const xr::HapticVibration)
+        },
+        _ => {
+            log::error!("unsupported haptic type {:?}", header.ty);
+            return xr::Result::ERROR_VALIDATION_FAILURE;
+        }
+    };
+
+    log::debug!("apply_feedback {info:?}, {vibration:?}");
+
+    let (instance_id, action_name) = match check_haptic_action(xr_session, info) {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
+    let subaction_path = match get_subaction_path(instance_id, info.subaction_path.into_raw()) {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
+    with_session(xr_session.into_raw(), |session| {
+        if !session.is_focused() {
+            return Ok(xr::Result::SESSION_NOT_FOCUSED);
+        }
+
+        let outputs = session
+            .bindings
+            .iter()
+            .filter(|binding| {
+                binding.action == info.action.into_raw()
+                    && (info.subaction_path.into_raw() == 0
+                        || binding.user_path == Some(info.subaction_path.into_raw()))
+            })
+            .map(|binding| binding.source.clone())
+            .collect();
+
+        session.haptics.apply(HapticFeedback {
+            action: info.action.into_raw(),
+            action_name: action_name.clone(),
+            subaction_path: info.subaction_path.into_raw(),
+            subaction_path_name: subaction_path.clone(),
+            outputs,
+            start_time: clock::now_xr(),
+            duration: vibration_duration(vibration.duration),
+            frequency: vibration.frequency,
+            amplitude: vibration.amplitude.clamp(0.0, 1.0),
+            stop_time: None,
+        });
+
+        Ok(xr::Result::SUCCESS)
     })
-    .into_xr_result()
+    .unwrap_or_else(|err| err.into())
 }
 
-#[allow(unreachable_code)]
 pub extern "system" fn stop_feedback(
     xr_session: xr::Session,
     info: *const xr::HapticActionInfo,
@@ -39,10 +96,309 @@ pub extern "system" fn stop_feedback(
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
-    with_session(xr_session.into_raw(), |_session| {
-        log::debug!("stop_feedback {info:?}");
-        return Err(xr::Result::ERROR_FUNCTION_UNSUPPORTED.into());
-        Ok(())
+    log::debug!("stop_feedback {info:?}");
+
+    if let Err(err) = check_haptic_action(xr_session, info) {
+        return err.into();
+    }
+
+    with_session(xr_session.into_raw(), |session| {
+        if !session.is_focused() {
+            return Ok(xr::Result::SESSION_NOT_FOCUSED);
+        }
+
+        session.haptics.stop(
+            info.action.into_raw(),
+            info.subaction_path.into_raw(),
+            clock::now_xr(),
+        );
+
+        Ok(xr::Result::SUCCESS)
     })
-    .into_xr_result()
+    .unwrap_or_else(|err| err.into())
+}
+
+/// Checks the action of a haptic call is an attached vibration output created for the subaction
+/// path. Returns the instance of the session and the name of the action.
+fn check_haptic_action(
+    xr_session: xr::Session,
+    info: &xr::HapticActionInfo,
+) -> Result<(u64, String)> {
+    let res = with_action(info.action.into_raw(), |action| {
+        if action.action_type != xr::ActionType::VIBRATION_OUTPUT {
+            return Err(xr::Result::ERROR_ACTION_TYPE_MISMATCH.into());
+        }
+
+        let instance_id = with_session(xr_session.into_raw(), |session| {
+            if !session.has_attached_action_set(action.action_set_id) {
+                return Err(xr::Result::ERROR_ACTIONSET_NOT_ATTACHED.into());
+            }
+            Ok(session.instance_id)
+        })?;
+
+        if !action
+            .subaction_values
+            .contains_key(&info.subaction_path.into_raw())
+        {
+            return Err(xr::Result::ERROR_PATH_UNSUPPORTED.into());
+        }
+
+        Ok((instance_id, action.name.to_string_lossy().into_owned()))
+    });
+
+    match res {
+        Err(Error::ExpectedSome(_)) => Err(xr::Result::ERROR_HANDLE_INVALID.into()),
+        res => res,
+    }
+}
+
+/// `XR_MIN_HAPTIC_DURATION`, or any duration that isn't positive, plays the shortest pulse
+fn vibration_duration(duration: xr::Duration) -> xr::Duration {
+    match duration.as_nanos() {
+        nanos if nanos > 0 => duration,
+        _ => xr::Duration::from_nanos(MIN_HAPTIC_DURATION_NANOS),
+    }
+}
+
+fn get_subaction_path(instance_id: u64, path_id: u64) -> Result<Option<String>> {
+    if path_id == 0 {
+        return Ok(None);
+    }
+
+    with_instance(instance_id, |instance| {
+        Ok(Some(instance.get_path_string(path_id)?.clone()))
+    })
+}
+
+/// A vibration the app applied to a haptic action.
+#[derive(Debug, Clone)]
+pub struct HapticFeedback {
+    pub(crate) action: u64,
+    pub(crate) action_name: String,
+    /// null path when it was applied to every subaction path of the action
+    pub(crate) subaction_path: u64,
+    pub(crate) subaction_path_name: Option<String>,
+    /// output sources the action is bound to, e.g. `/user/hand/right/output/haptic`
+    pub(crate) outputs: Vec<String>,
+    pub(crate) start_time: xr::Time,
+    /// `XR_MIN_HAPTIC_DURATION` is already replaced by the length of the shortest pulse
+    pub(crate) duration: xr::Duration,
+    /// `XR_FREQUENCY_UNSPECIFIED` when the app left it to the runtime
+    pub(crate) frequency: f32,
+    pub(crate) amplitude: f32,
+    /// when the app stopped or replaced it before it ran out
+    pub(crate) stop_time: Option<xr::Time>,
+}
+
+impl HapticFeedback {
+    pub fn end_time(&self) -> xr::Time {
+        let end = xr::Time::from_nanos(
+            self.start_time
+                .as_nanos()
+                .saturating_add(self.duration.as_nanos()),
+        );
+
+        match self.stop_time {
+            Some(stop_time) => xr::Time::from_nanos(stop_time.as_nanos().min(end.as_nanos())),
+            None => end,
+        }
+    }
+}
+
+/// Vibrations playing on the outputs of a session and the ones that already finished.
+#[derive(Debug, Default)]
+pub struct SimulatedHaptics {
+    active: HashMap<(u64, u64), HapticFeedback>,
+    history: VecDeque<HapticFeedback>,
+}
+
+impl SimulatedHaptics {
+    /// starts a vibration, replacing the one playing on the same action and subaction path
+    pub fn apply(&mut self, feedback: HapticFeedback) {
+        let now = feedback.start_time;
+        self.stop(feedback.action, feedback.subaction_path, now);
+        self.active
+            .insert((feedback.action, feedback.subaction_path), feedback);
+    }
+
+    /// stops the vibrations of an action, every subaction path's when `subaction_path` is null
+    pub fn stop(&mut self, action: u64, subaction_path: u64, now: xr::Time) {
+        self.expire(now);
+
+        let keys = self
+            .active
+            .keys()
+            .filter(|(key_action, key_path)| {
+                *key_action == action && (subaction_path == 0 || *key_path == subaction_path)
+            })
+            .copied()
+            .collect::<Vec<_>>();
+
+        for key in keys {
+            if let Some(mut feedback) = self.active.remove(&key) {
+                feedback.stop_time = Some(now);
+                self.push_history(feedback);
+            }
+        }
+    }
+
+    /// moves the vibrations that ran out by `now` to the history
+    pub fn expire(&mut self, now: xr::Time) {
+        let keys = self
+            .active
+            .iter()
+            .filter(|(_, feedback)| feedback.end_time().as_nanos() <= now.as_nanos())
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+
+        for key in keys {
+            if let Some(feedback) = self.active.remove(&key) {
+                self.push_history(feedback);
+            }
+        }
+    }
+
+    pub fn current(&self) -> impl Iterator<Item = &HapticFeedback> {
+        self.active.values()
+    }
+
+    /// finished vibrations, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &HapticFeedback> {
+        self.history.iter()
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    fn push_history(&mut self, feedback: HapticFeedback) {
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(feedback);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACTION: u64 = 1;
+    const OTHER_ACTION: u64 = 2;
+    const LEFT: u64 = 10;
+    const RIGHT: u64 = 11;
+
+    fn feedback(action: u64, subaction_path: u64, start: i64, duration: i64) -> HapticFeedback {
+        HapticFeedback {
+            action,
+            action_name: "vibrate".into(),
+            subaction_path,
+            subaction_path_name: None,
+            outputs: Vec::new(),
+            start_time: xr::Time::from_nanos(start),
+            duration: xr::Duration::from_nanos(duration),
+            frequency: 0.0,
+            amplitude: 1.0,
+            stop_time: None,
+        }
+    }
+
+    fn time(nanos: i64) -> xr::Time {
+        xr::Time::from_nanos(nanos)
+    }
+
+    #[test]
+    fn min_haptic_duration_plays_the_shortest_pulse() {
+        for duration in [xr::Duration::MIN_HAPTIC, xr::Duration::NONE] {
+            assert_eq!(
+                vibration_duration(duration).as_nanos(),
+                MIN_HAPTIC_DURATION_NANOS
+            );
+        }
+
+        assert_eq!(
+            vibration_duration(xr::Duration::from_nanos(500)).as_nanos(),
+            500
+        );
+    }
+
+    #[test]
+    fn vibrations_end_when_stopped_or_run_out() {
+        let mut vibration = feedback(ACTION, 0, 100, 50);
+        assert_eq!(vibration.end_time().as_nanos(), 150);
+
+        vibration.stop_time = Some(time(120));
+        assert_eq!(vibration.end_time().as_nanos(), 120);
+
+        vibration.stop_time = Some(time(200));
+        assert_eq!(vibration.end_time().as_nanos(), 150);
+
+        let endless = feedback(ACTION, 0, 100, xr::Duration::INFINITE.as_nanos());
+        assert_eq!(endless.end_time().as_nanos(), i64::MAX);
+    }
+
+    #[test]
+    fn expired_vibrations_move_to_the_history() {
+        let mut haptics = SimulatedHaptics::default();
+        haptics.apply(feedback(ACTION, LEFT, 100, 50));
+        haptics.apply(feedback(ACTION, RIGHT, 100, 80));
+
+        haptics.expire(time(149));
+        assert_eq!(haptics.current().count(), 2);
+
+        haptics.expire(time(150));
+        assert_eq!(haptics.current().count(), 1);
+        assert_eq!(haptics.history().count(), 1);
+
+        let finished = haptics.history().next().unwrap();
+        assert_eq!(finished.subaction_path, LEFT);
+        assert_eq!(finished.stop_time, None);
+    }
+
+    #[test]
+    fn applying_replaces_the_vibration_of_the_same_output() {
+        let mut haptics = SimulatedHaptics::default();
+        haptics.apply(feedback(ACTION, LEFT, 100, 1000));
+        haptics.apply(feedback(ACTION, LEFT, 300, 1000));
+
+        let current = haptics.current().collect::<Vec<_>>();
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].start_time.as_nanos(), 300);
+
+        let replaced = haptics.history().next().unwrap();
+        assert_eq!(replaced.start_time.as_nanos(), 100);
+        assert_eq!(replaced.stop_time.map(|time| time.as_nanos()), Some(300));
+    }
+
+    #[test]
+    fn stopping_the_null_path_stops_every_subaction_path() {
+        let mut haptics = SimulatedHaptics::default();
+        haptics.apply(feedback(ACTION, LEFT, 100, 1000));
+        haptics.apply(feedback(ACTION, RIGHT, 100, 1000));
+        haptics.apply(feedback(OTHER_ACTION, LEFT, 100, 1000));
+
+        haptics.stop(ACTION, RIGHT, time(200));
+        assert_eq!(haptics.current().count(), 2);
+
+        haptics.stop(ACTION, 0, time(300));
+        let current = haptics.current().collect::<Vec<_>>();
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].action, OTHER_ACTION);
+        assert_eq!(haptics.history().count(), 2);
+    }
+
+    #[test]
+    fn history_keeps_the_latest_vibrations() {
+        let mut haptics = SimulatedHaptics::default();
+        for start in 0..HISTORY_LEN as i64 + 10 {
+            haptics.apply(feedback(ACTION, LEFT, start, 1));
+        }
+        haptics.expire(time(i64::MAX));
+
+        assert_eq!(haptics.history().count(), HISTORY_LEN);
+        assert_eq!(haptics.history().next().unwrap().start_time.as_nanos(), 10);
+
+        haptics.clear_history();
+        assert_eq!(haptics.history().count(), 0);
+    }
 }
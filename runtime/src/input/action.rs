@@ -6,7 +6,16 @@ use std::{
 };
 
 use crate::{
-    input::action_set::with_action_set,
+    device::{SimulatedInputValue, SimulatedInputs},
+    handle::{self, Handle},
+    input::{
+        action_set::{self, with_action_set},
+        interaction_profile::{ResolvedBinding, top_level_user_path},
+        profiles::{
+            InteractionProfile, find_profile, localized_component_name, localized_user_path_name,
+        },
+    },
+    instance::api::{check_instance_lost, with_instance},
     prelude::*,
     session::with_session,
     utils::{create_identity_pose, with_obj_instance},
@@ -33,6 +42,7 @@ pub extern "system" fn create(
         action_instances.insert(
             next_id,
             UnsafeCell::new(SimulatedAction::new(
+                action_set.instance_id,
                 xr_action_set.into_raw(),
                 next_id,
                 create_info,
@@ -45,6 +55,10 @@ pub extern "system" fn create(
 
         *xr_action = xr::Action::from_raw(next_id);
 
+        handle::register(
+            Handle::Action(next_id),
+            Some(Handle::ActionSet(xr_action_set.into_raw())),
+        )?;
         action_set.add_action(next_id)
     })
     .into_xr_result()
@@ -55,68 +69,186 @@ pub extern "system" fn destroy(xr_obj: xr::Action) -> xr::Result {
         return xr::Result::ERROR_HANDLE_INVALID;
     }
 
-    let instance_id = xr_obj.into_raw();
-
-    if INSTANCES
-        .lock()
-        .expect("couldn't acquire instances")
-        .remove(&instance_id)
-        .is_some()
-    {
-        log::debug!("destroyed {instance_id}");
-    } else {
-        log::debug!("instance {instance_id} not found");
-    }
-
-    xr::Result::SUCCESS
+    handle::destroy(Handle::Action(xr_obj.into_raw())).into_xr_result()
 }
 
-#[allow(unreachable_code)]
 pub extern "system" fn enumerate_bound_sources(
     xr_session: xr::Session,
     info: *const xr::BoundSourcesForActionEnumerateInfo,
-    _capacity_in: u32,
+    capacity_in: u32,
     count_out: *mut u32,
-    _sources: *mut xr::Path,
+    sources: *mut xr::Path,
 ) -> xr::Result {
     if info.is_null() || count_out.is_null() {
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
-    let (info, _count_out) = unsafe { (&*info, &mut *count_out) };
+    let (info, count_out) = unsafe { (&*info, &mut *count_out) };
+
+    if info.ty != xr::StructureType::BOUND_SOURCES_FOR_ACTION_ENUMERATE_INFO {
+        return xr::Result::ERROR_VALIDATION_FAILURE;
+    }
+
+    log::debug!("enumerate_bound_sources {info:?}");
+
+    let (instance_id, bindings) = match with_action(info.action.into_raw(), |action| {
+        with_session(xr_session.into_raw(), |session| {
+            if !session.has_attached_action_set(action.action_set_id) {
+                return Err(xr::Result::ERROR_ACTIONSET_NOT_ATTACHED.into());
+            }
+            Ok((session.instance_id, session.bindings.clone()))
+        })
+    }) {
+        Ok(value) => value,
+        Err(Error::ExpectedSome(_)) => return xr::Result::ERROR_HANDLE_INVALID,
+        Err(err) => return err.into(),
+    };
+
+    let mut source_paths = Vec::new();
+    for binding in bindings
+        .iter()
+        .filter(|binding| binding.action == info.action.into_raw())
+    {
+        for (source, _) in binding.profile.binding_sources(&binding.source) {
+            if !source_paths.contains(&source) {
+                source_paths.push(source);
+            }
+        }
+    }
+
+    with_instance(instance_id, |instance| {
+        let source_ids = source_paths
+            .iter()
+            .map(|source| instance.register_path_str(source))
+            .collect::<Vec<_>>();
+
+        *count_out = source_ids.len() as u32;
+
+        if capacity_in == 0 {
+            return Ok(());
+        }
+
+        if (capacity_in as usize) < source_ids.len() {
+            return Err(xr::Result::ERROR_SIZE_INSUFFICIENT.into());
+        }
+
+        if sources.is_null() {
+            return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+        }
+
+        for (idx, source_id) in source_ids.into_iter().enumerate() {
+            unsafe { *sources.add(idx) = xr::Path::from_raw(source_id) };
+        }
 
-    with_session(xr_session.into_raw(), |_session| {
-        log::debug!("enumerate_bound_sources {info:?}");
-        return Err(xr::Result::ERROR_FUNCTION_UNSUPPORTED.into());
         Ok(())
     })
     .into_xr_result()
 }
 
-#[allow(unreachable_code)]
 pub extern "system" fn get_input_source_localized_name(
     xr_session: xr::Session,
     info: *const xr::InputSourceLocalizedNameGetInfo,
-    _capacity_in: u32,
+    capacity_in: u32,
     count_out: *mut u32,
-    _buf: *mut c_char,
+    buf: *mut c_char,
 ) -> xr::Result {
     if info.is_null() || count_out.is_null() {
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
-    let (info, _count_out) = unsafe { (&*info, &mut *count_out) };
+    let (info, count_out) = unsafe { (&*info, &mut *count_out) };
 
-    with_session(xr_session.into_raw(), |_session| {
-        log::debug!("get_input_source_localized_name {info:?}");
-        return Err(xr::Result::ERROR_FUNCTION_UNSUPPORTED.into());
-        Ok(())
-    })
-    .into_xr_result()
+    if info.ty != xr::StructureType::INPUT_SOURCE_LOCALIZED_NAME_GET_INFO
+        || info.which_components.is_empty()
+    {
+        return xr::Result::ERROR_VALIDATION_FAILURE;
+    }
+
+    log::debug!("get_input_source_localized_name {info:?}");
+
+    let (instance_id, interaction_profiles) = match with_session(xr_session.into_raw(), |session| {
+        Ok((session.instance_id, session.interaction_profiles.clone()))
+    }) {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
+    let name = match with_instance(instance_id, |instance| {
+        let source = instance.get_path_string(info.source_path.into_raw())?;
+        let user_path = top_level_user_path(source);
+
+        let Some(profile) = interaction_profiles
+            .get(user_path)
+            .and_then(|profile_id| instance.paths.get(profile_id))
+            .and_then(|profile| find_profile(profile))
+            .filter(|profile| !profile.binding_types(source).is_empty())
+        else {
+            log::error!("{source} is not a source of the current interaction profiles");
+            return Err(xr::Result::ERROR_PATH_UNSUPPORTED.into());
+        };
+
+        localized_source_name(profile, source, info.which_components)
+    }) {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
+    *count_out = (name.len() + 1) as u32;
+
+    if capacity_in == 0 {
+        return xr::Result::SUCCESS;
+    }
+
+    if capacity_in < *count_out {
+        return xr::Result::ERROR_SIZE_INSUFFICIENT;
+    }
+
+    if buf.is_null() {
+        return xr::Result::ERROR_VALIDATION_FAILURE;
+    }
+
+    unsafe {
+        std::ptr::copy_nonoverlapping(name.as_ptr() as *const c_char, buf, name.len());
+        *buf.add(name.len()) = 0;
+    }
+
+    xr::Result::SUCCESS
+}
+
+/// English name of the parts of a source path apps asked for, in the order the spec lists them,
+/// e.g. `Left Hand Oculus Touch Controller Trigger`.
+fn localized_source_name(
+    profile: &InteractionProfile,
+    source: &str,
+    which_components: xr::InputSourceLocalizedNameFlags,
+) -> Result<String> {
+    let user_path = top_level_user_path(source);
+    let mut parts = Vec::new();
+
+    if which_components.contains(xr::InputSourceLocalizedNameFlags::USER_PATH) {
+        parts.push(
+            localized_user_path_name(user_path)
+                .ok_or(xr::Result::ERROR_PATH_UNSUPPORTED)?
+                .to_string(),
+        );
+    }
+
+    if which_components.contains(xr::InputSourceLocalizedNameFlags::INTERACTION_PROFILE) {
+        parts.push(profile.localized_name.to_string());
+    }
+
+    if which_components.contains(xr::InputSourceLocalizedNameFlags::COMPONENT) {
+        parts.push(
+            localized_component_name(&source[user_path.len()..])
+                .ok_or(xr::Result::ERROR_PATH_UNSUPPORTED)?,
+        );
+    }
+
+    Ok(parts.join(" "))
 }
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum SimulatedActionValue {
     Boolean(bool),
     Float(f32),
@@ -132,33 +264,120 @@ impl Default for SimulatedActionValue {
     }
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Default)]
+impl From<SimulatedInputValue> for SimulatedActionValue {
+    fn from(value: SimulatedInputValue) -> Self {
+        match value {
+            SimulatedInputValue::Boolean(value) => Self::Boolean(value),
+            SimulatedInputValue::Float(value) => Self::Float(value),
+            SimulatedInputValue::Vector2f(value) => Self::Vector2f(value),
+            SimulatedInputValue::Pose(value) => Self::Pose(value),
+        }
+    }
+}
+
+impl SimulatedActionValue {
+    pub fn initial(action_type: xr::ActionType) -> Self {
+        match action_type {
+            xr::ActionType::BOOLEAN_INPUT => Self::Boolean(false),
+            xr::ActionType::FLOAT_INPUT => Self::Float(0.0),
+            xr::ActionType::VECTOR2F_INPUT => Self::Vector2f(xr::Vector2f { x: 0.0, y: 0.0 }),
+            xr::ActionType::POSE_INPUT => Self::Pose(create_identity_pose()),
+            xr::ActionType::VIBRATION_OUTPUT => Self::Vibration(0.0),
+            _ => Self::Unknown(action_type.into_raw()),
+        }
+    }
+
+    /// OpenXR combination rules for several sources bound to the same action: booleans are
+    /// OR'ed, the float and vector with the largest magnitude win, and poses aren't combined, the
+    /// first bound source is used.
+    fn combine(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Boolean(a), Self::Boolean(b)) => Self::Boolean(a || b),
+            (Self::Float(a), Self::Float(b)) if b.abs() > a.abs() => Self::Float(b),
+            (Self::Vector2f(a), Self::Vector2f(b))
+                if b.x * b.x + b.y * b.y > a.x * a.x + a.y * a.y =>
+            {
+                Self::Vector2f(b)
+            }
+            (a, _) => a,
+        }
+    }
+
+    fn is_same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Boolean(a), Self::Boolean(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a == b,
+            (Self::Vector2f(a), Self::Vector2f(b)) => a.x == b.x && a.y == b.y,
+            // pose changes aren't reported
+            (Self::Pose(_), Self::Pose(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct SimulatedActionCurrentValue {
     pub(crate) current: SimulatedActionValue,
     pub(crate) changed_since_last_sync: bool,
-    pub(crate) last_change_time: u64,
+    pub(crate) last_change_time: xr::Time,
     pub(crate) is_active: bool,
 }
 
+impl SimulatedActionCurrentValue {
+    pub fn new(action_type: xr::ActionType) -> Self {
+        Self {
+            current: SimulatedActionValue::initial(action_type),
+            changed_since_last_sync: false,
+            last_change_time: xr::Time::from_nanos(0),
+            is_active: false,
+        }
+    }
+
+    /// applies the combined value of the bound sources, `None` when nothing is bound
+    fn update(
+        &mut self,
+        action_type: xr::ActionType,
+        value: Option<SimulatedActionValue>,
+        time: xr::Time,
+    ) {
+        let previous = self.current;
+
+        self.is_active = value.is_some();
+        self.current = value.unwrap_or_else(|| SimulatedActionValue::initial(action_type));
+        self.changed_since_last_sync = self.is_active && !self.current.is_same(&previous);
+
+        if self.changed_since_last_sync {
+            self.last_change_time = time;
+        }
+    }
+}
+
 type PathId = u64;
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct SimulatedAction {
+    pub(crate) instance_id: u64,
     pub(crate) action_set_id: u64,
     pub(crate) id: u64,
     pub(crate) name: CString,
     pub(crate) localized_name: String,
+    pub(crate) action_type: xr::ActionType,
+    /// values per subaction path, the null path holds the values of all of them combined
     pub(crate) subaction_values: HashMap<PathId, SimulatedActionCurrentValue>,
 }
 
 impl SimulatedAction {
-    pub fn new(action_set_id: u64, id: u64, create_info: &xr::ActionCreateInfo) -> Result<Self> {
+    pub fn new(
+        instance_id: u64,
+        action_set_id: u64,
+        id: u64,
+        create_info: &xr::ActionCreateInfo,
+    ) -> Result<Self> {
         let name = unsafe { CStr::from_ptr(create_info.action_name.as_ptr()) };
         let localized_name = unsafe { CStr::from_ptr(create_info.localized_action_name.as_ptr()) };
 
-        let mut subaction_values = HashMap::new();
+        let mut subaction_paths = vec![0];
 
         if create_info.count_subaction_paths > 0 {
             if create_info.subaction_paths.is_null() {
@@ -167,56 +386,27 @@ impl SimulatedAction {
             }
 
             for i in 0..create_info.count_subaction_paths {
-                let path = unsafe { *create_info.subaction_paths.add(i as usize) }.into_raw();
-
-                subaction_values.insert(
-                    path,
-                    SimulatedActionCurrentValue {
-                        current: match create_info.action_type {
-                            xr::ActionType::BOOLEAN_INPUT => SimulatedActionValue::Boolean(false),
-                            xr::ActionType::FLOAT_INPUT => SimulatedActionValue::Float(0.0),
-                            xr::ActionType::VECTOR2F_INPUT => {
-                                SimulatedActionValue::Vector2f(xr::Vector2f::default())
-                            }
-                            xr::ActionType::POSE_INPUT => {
-                                SimulatedActionValue::Pose(create_identity_pose())
-                            }
-                            xr::ActionType::VIBRATION_OUTPUT => {
-                                SimulatedActionValue::Vibration(0.0)
-                            }
-                            _ => SimulatedActionValue::Unknown(create_info.action_type.into_raw()),
-                        },
-                        ..Default::default()
-                    },
-                );
+                subaction_paths
+                    .push(unsafe { *create_info.subaction_paths.add(i as usize) }.into_raw());
             }
-        } else {
-            subaction_values.insert(
-                0,
-                SimulatedActionCurrentValue {
-                    current: match create_info.action_type {
-                        xr::ActionType::BOOLEAN_INPUT => SimulatedActionValue::Boolean(false),
-                        xr::ActionType::FLOAT_INPUT => SimulatedActionValue::Float(0.0),
-                        xr::ActionType::VECTOR2F_INPUT => {
-                            SimulatedActionValue::Vector2f(xr::Vector2f::default())
-                        }
-                        xr::ActionType::POSE_INPUT => {
-                            SimulatedActionValue::Pose(create_identity_pose())
-                        }
-                        xr::ActionType::VIBRATION_OUTPUT => SimulatedActionValue::Vibration(0.0),
-                        _ => SimulatedActionValue::Unknown(create_info.action_type.into_raw()),
-                    },
-                    ..Default::default()
-                },
-            );
         }
 
         Ok(Self {
+            instance_id,
             action_set_id,
             id,
             name: name.into(),
             localized_name: localized_name.to_str()?.into(),
-            subaction_values,
+            action_type: create_info.action_type,
+            subaction_values: subaction_paths
+                .into_iter()
+                .map(|path| {
+                    (
+                        path,
+                        SimulatedActionCurrentValue::new(create_info.action_type),
+                    )
+                })
+                .collect(),
         })
     }
 
@@ -226,6 +416,37 @@ impl SimulatedAction {
             None => Err(xr::Result::ERROR_PATH_INVALID.into()),
         }
     }
+
+    /// current pose of a pose action, `None` if the action isn't active
+    pub fn current_pose(&self, path: u64) -> Result<Option<xr::Posef>> {
+        let value = self.subaction_value(path)?;
+
+        match value.current {
+            SimulatedActionValue::Pose(pose) => Ok(Some(pose).filter(|_| value.is_active)),
+            _ => Err(xr::Result::ERROR_ACTION_TYPE_MISMATCH.into()),
+        }
+    }
+
+    /// Updates the state of every subaction path from the sources bound to it.
+    pub fn sync(
+        &mut self,
+        bindings: &[&ResolvedBinding],
+        inputs: &SimulatedInputs,
+        time: xr::Time,
+    ) {
+        let action_type = self.action_type;
+
+        for (path, value) in self.subaction_values.iter_mut() {
+            let combined = bindings
+                .iter()
+                .filter(|binding| *path == 0 || binding.user_path == Some(*path))
+                .filter_map(|binding| inputs.resolve(&binding.source, action_type))
+                .map(SimulatedActionValue::from)
+                .reduce(SimulatedActionValue::combine);
+
+            value.update(action_type, combined, time);
+        }
+    }
 }
 
 static INSTANCE_COUNTER: atomic::AtomicU64 = atomic::AtomicU64::new(1);
@@ -235,9 +456,190 @@ type SharedSimulatedActionSet = UnsafeCell<SimulatedAction>;
 static INSTANCES: LazyLock<Mutex<HashMap<u64, SharedSimulatedActionSet>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
-pub fn with_action<T, F>(xr_obj_id: u64, f: F) -> Result<T>
+/// Releases a destroyed action, its action set forgets about it.
+pub fn remove(action_id: u64) -> Result<()> {
+    let Some(action) = INSTANCES.lock()?.remove(&action_id) else {
+        return Err(xr::Result::ERROR_HANDLE_INVALID.into());
+    };
+
+    action_set::remove_action(action.into_inner().action_set_id, action_id)?;
+
+    log::debug!("destroyed {action_id}");
+    Ok(())
+}
+
+pub fn with_action<T, F>(xr_obj_id: u64, mut f: F) -> Result<T>
 where
     F: FnMut(&mut SimulatedAction) -> Result<T>,
 {
-    with_obj_instance(&INSTANCES, xr_obj_id, f)
+    let res = with_obj_instance(&INSTANCES, xr_obj_id, |action| {
+        check_instance_lost(action.instance_id)?;
+        f(action)
+    });
+
+    match res {
+        Ok(res) => Ok(res),
+        Err(err) => match err {
+            Error::ExpectedSome(err) => {
+                log::error!("error: {err}");
+                Err(xr::Result::ERROR_HANDLE_INVALID.into())
+            }
+            _ => Err(err),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEFT: u64 = 10;
+    const RIGHT: u64 = 11;
+
+    fn float_action() -> SimulatedAction {
+        SimulatedAction {
+            instance_id: 1,
+            action_set_id: 1,
+            id: 1,
+            name: c"grab".into(),
+            localized_name: "Grab".into(),
+            action_type: xr::ActionType::FLOAT_INPUT,
+            subaction_values: [0, LEFT, RIGHT]
+                .into_iter()
+                .map(|path| {
+                    (
+                        path,
+                        SimulatedActionCurrentValue::new(xr::ActionType::FLOAT_INPUT),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn binding(user_path: u64, source: &str) -> ResolvedBinding {
+        ResolvedBinding {
+            action: 1,
+            profile: find_profile("/interaction_profiles/khr/simple_controller").unwrap(),
+            user_path: Some(user_path),
+            source: source.into(),
+        }
+    }
+
+    fn float_value(action: &SimulatedAction, path: u64) -> f32 {
+        match action.subaction_values[&path].current {
+            SimulatedActionValue::Float(value) => value,
+            value => panic!("not a float: {value:?}"),
+        }
+    }
+
+    #[test]
+    fn booleans_are_ored() {
+        let combined = SimulatedActionValue::Boolean(false)
+            .combine(SimulatedActionValue::Boolean(true))
+            .combine(SimulatedActionValue::Boolean(false));
+
+        assert!(matches!(combined, SimulatedActionValue::Boolean(true)));
+    }
+
+    #[test]
+    fn the_largest_magnitude_wins() {
+        let combined = SimulatedActionValue::Float(0.5).combine(SimulatedActionValue::Float(-0.75));
+        assert!(matches!(combined, SimulatedActionValue::Float(value) if value == -0.75));
+
+        let combined = SimulatedActionValue::Vector2f(xr::Vector2f { x: 0.6, y: 0.0 }).combine(
+            SimulatedActionValue::Vector2f(xr::Vector2f { x: 0.4, y: 0.4 }),
+        );
+        assert!(matches!(combined, SimulatedActionValue::Vector2f(value) if value.x == 0.6));
+    }
+
+    #[test]
+    fn poses_use_the_first_source() {
+        let mut first = create_identity_pose();
+        first.position.x = 1.0;
+
+        let combined = SimulatedActionValue::Pose(first)
+            .combine(SimulatedActionValue::Pose(create_identity_pose()));
+
+        assert!(matches!(combined, SimulatedActionValue::Pose(pose) if pose.position.x == 1.0));
+    }
+
+    #[test]
+    fn sync_combines_the_sources_of_each_subaction_path() {
+        let mut inputs = SimulatedInputs::default();
+        inputs.set(
+            "/user/hand/left/input/trigger/value",
+            SimulatedInputValue::Float(0.25),
+        );
+        inputs.set(
+            "/user/hand/right/input/trigger/value",
+            SimulatedInputValue::Float(0.5),
+        );
+
+        let left = binding(LEFT, "/user/hand/left/input/trigger");
+        let right = binding(RIGHT, "/user/hand/right/input/trigger");
+
+        let mut action = float_action();
+        action.sync(&[&left, &right], &inputs, xr::Time::from_nanos(100));
+
+        assert_eq!(float_value(&action, LEFT), 0.25);
+        assert_eq!(float_value(&action, RIGHT), 0.5);
+        assert_eq!(float_value(&action, 0), 0.5);
+
+        let value = &action.subaction_values[&0];
+        assert!(value.is_active && value.changed_since_last_sync);
+        assert_eq!(value.last_change_time.as_nanos(), 100);
+    }
+
+    #[test]
+    fn sync_deactivates_unbound_actions() {
+        let inputs = SimulatedInputs::default();
+        let mut action = float_action();
+
+        action.sync(&[], &inputs, xr::Time::from_nanos(100));
+
+        let value = &action.subaction_values[&0];
+        assert!(!value.is_active && !value.changed_since_last_sync);
+        assert_eq!(float_value(&action, 0), 0.0);
+    }
+
+    #[test]
+    fn localized_names_have_the_requested_parts() {
+        let profile = find_profile("/interaction_profiles/oculus/touch_controller").unwrap();
+        let source = "/user/hand/left/input/trigger/value";
+
+        assert_eq!(
+            localized_source_name(
+                profile,
+                source,
+                xr::InputSourceLocalizedNameFlags::COMPONENT
+            )
+            .unwrap(),
+            "Trigger"
+        );
+        assert_eq!(
+            localized_source_name(
+                profile,
+                source,
+                xr::InputSourceLocalizedNameFlags::USER_PATH
+                    | xr::InputSourceLocalizedNameFlags::INTERACTION_PROFILE
+                    | xr::InputSourceLocalizedNameFlags::COMPONENT
+            )
+            .unwrap(),
+            format!("Left Hand {} Trigger", profile.localized_name)
+        );
+    }
+
+    #[test]
+    fn localized_names_need_known_parts() {
+        let profile = find_profile("/interaction_profiles/khr/simple_controller").unwrap();
+
+        assert!(matches!(
+            localized_source_name(
+                profile,
+                "/user/hand/left/input/nothing/click",
+                xr::InputSourceLocalizedNameFlags::COMPONENT
+            ),
+            Err(Error::XrResult(xr::Result::ERROR_PATH_UNSUPPORTED))
+        ));
+    }
 }
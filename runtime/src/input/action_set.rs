@@ -5,7 +5,12 @@ use std::{
     sync::{LazyLock, Mutex, atomic},
 };
 
-use crate::{instance::api::with_instance, prelude::*, utils::with_obj_instance};
+use crate::{
+    handle::{self, Handle},
+    instance::api::{check_instance_lost, with_instance, with_instance_unchecked},
+    prelude::*,
+    utils::with_obj_instance,
+};
 
 pub extern "system" fn create(
     xr_instance: xr::Instance,
@@ -40,6 +45,10 @@ pub extern "system" fn create(
 
         *xr_action_set = xr::ActionSet::from_raw(next_id);
 
+        handle::register(
+            Handle::ActionSet(next_id),
+            Some(Handle::Instance(instance.id)),
+        )?;
         instance.add_action_set(next_id)
     })
     .into_xr_result()
@@ -50,31 +59,18 @@ pub extern "system" fn destroy(xr_obj: xr::ActionSet) -> xr::Result {
         return xr::Result::ERROR_HANDLE_INVALID;
     }
 
-    let instance_id = xr_obj.into_raw();
-
-    if INSTANCES
-        .lock()
-        .expect("couldn't acquire instances")
-        .remove(&instance_id)
-        .is_some()
-    {
-        log::debug!("destroyed {instance_id}");
-    } else {
-        log::debug!("instance {instance_id} not found");
-    }
-
-    xr::Result::SUCCESS
+    handle::destroy(Handle::ActionSet(xr_obj.into_raw())).into_xr_result()
 }
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct SimulatedActionSet {
-    instance_id: u64,
+    pub(crate) instance_id: u64,
     id: u64,
     name: CString,
     localized_name: String,
     priority: u32,
-    actions: Vec<u64>,
+    pub(crate) actions: Vec<u64>,
 }
 
 impl SimulatedActionSet {
@@ -106,9 +102,46 @@ type SharedSimulatedActionSet = UnsafeCell<SimulatedActionSet>;
 static INSTANCES: LazyLock<Mutex<HashMap<u64, SharedSimulatedActionSet>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
-pub fn with_action_set<T, F>(xr_obj_id: u64, f: F) -> Result<T>
+/// Releases a destroyed action set, its actions are destroyed before it.
+pub fn remove(action_set_id: u64) -> Result<()> {
+    let Some(action_set) = INSTANCES.lock()?.remove(&action_set_id) else {
+        return Err(xr::Result::ERROR_HANDLE_INVALID.into());
+    };
+
+    with_instance_unchecked(action_set.into_inner().instance_id, |instance| {
+        instance.action_set_ids.remove(&action_set_id);
+        Ok(())
+    })?;
+
+    log::debug!("destroyed {action_set_id}");
+    Ok(())
+}
+
+/// Forgets a destroyed action of the set.
+pub fn remove_action(action_set_id: u64, action_id: u64) -> Result<()> {
+    with_obj_instance(&INSTANCES, action_set_id, |action_set| {
+        action_set.actions.retain(|&id| id != action_id);
+        Ok(())
+    })
+}
+
+pub fn with_action_set<T, F>(xr_obj_id: u64, mut f: F) -> Result<T>
 where
     F: FnMut(&mut SimulatedActionSet) -> Result<T>,
 {
-    with_obj_instance(&INSTANCES, xr_obj_id, f)
+    let res = with_obj_instance(&INSTANCES, xr_obj_id, |action_set| {
+        check_instance_lost(action_set.instance_id)?;
+        f(action_set)
+    });
+
+    match res {
+        Ok(res) => Ok(res),
+        Err(err) => match err {
+            Error::ExpectedSome(err) => {
+                log::error!("error: {err}");
+                Err(xr::Result::ERROR_HANDLE_INVALID.into())
+            }
+            _ => Err(err),
+        },
+    }
 }
@@ -1,5 +1,10 @@
 use crate::{
-    input::action::{SimulatedActionValue, with_action},
+    clock,
+    device::with_device,
+    input::{
+        action::{SimulatedActionValue, with_action},
+        action_set::with_action_set,
+    },
     instance::api::with_instance,
     prelude::*,
     session::with_session,
@@ -54,6 +59,7 @@ macro_rules! get_action_value {
 
             state.is_active = value.is_active.into();
             state.changed_since_last_sync = value.changed_since_last_sync.into();
+            state.last_change_time = value.last_change_time;
             Ok(())
         })
         .into_xr_result()
@@ -144,7 +150,6 @@ pub extern "system" fn get_pose(
     .into_xr_result()
 }
 
-#[allow(unreachable_code)]
 pub extern "system" fn sync_actions(
     xr_session: xr::Session,
     info: *const xr::ActionsSyncInfo,
@@ -166,20 +171,65 @@ pub extern "system" fn sync_actions(
         )
     };
 
-    with_session(xr_session.into_raw(), |session| {
-        if !session.is_focused() {
-            return Ok(xr::Result::SESSION_NOT_FOCUSED);
-        }
-
-        log::debug!("sync_actions {active_action_sets:?}");
-
+    let snapshot = with_session(xr_session.into_raw(), |session| {
         for active_action_set in active_action_sets {
             if !session.has_attached_action_set(active_action_set.action_set.into_raw()) {
                 return Err(xr::Result::ERROR_ACTIONSET_NOT_ATTACHED.into());
             }
         }
 
-        Ok(xr::Result::SUCCESS)
-    })
-    .into_xr_result()
+        Ok((
+            session.is_focused(),
+            with_device(|device| device.inputs.clone()),
+            session.bindings.clone(),
+            session.action_set_ids.clone(),
+        ))
+    });
+
+    let (is_focused, inputs, bindings, action_set_ids) = match snapshot {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
+    log::debug!("sync_actions {active_action_sets:?}");
+
+    let time = clock::now_xr();
+
+    let res: Result<()> = action_set_ids.iter().try_for_each(|&action_set_id| {
+        // subaction paths the set is active for, the null path means all of them. Every action
+        // becomes inactive when the session loses focus.
+        let active_paths = active_action_sets
+            .iter()
+            .filter(|active| is_focused && active.action_set.into_raw() == action_set_id)
+            .map(|active| active.subaction_path.into_raw())
+            .collect::<Vec<_>>();
+
+        let action_ids =
+            with_action_set(action_set_id, |action_set| Ok(action_set.actions.clone()))?;
+
+        for action_id in action_ids {
+            let action_bindings = bindings
+                .iter()
+                .filter(|binding| {
+                    binding.action == action_id
+                        && active_paths
+                            .iter()
+                            .any(|&path| path == 0 || binding.user_path == Some(path))
+                })
+                .collect::<Vec<_>>();
+
+            with_action(action_id, |action| {
+                action.sync(&action_bindings, &inputs, time);
+                Ok(())
+            })?;
+        }
+
+        Ok(())
+    });
+
+    match res {
+        Ok(()) if is_focused => xr::Result::SUCCESS,
+        Ok(()) => xr::Result::SESSION_NOT_FOCUSED,
+        Err(err) => err.into(),
+    }
 }
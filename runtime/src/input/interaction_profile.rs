@@ -1,5 +1,15 @@
+use std::collections::HashMap;
+
 use crate::{
-    instance::{api::with_instance, obj::ActionBinding},
+    device::with_device,
+    input::{
+        action::with_action,
+        profiles::{InteractionProfile, TOP_LEVEL_USER_PATHS, find_profile},
+    },
+    instance::{
+        api::with_instance,
+        obj::{ActionBinding, SimulatedInstance},
+    },
     prelude::*,
     session::with_session,
 };
@@ -14,17 +24,46 @@ pub extern "system" fn suggest(
 
     let suggestion = unsafe { &*suggestion };
 
+    if suggestion.ty != xr::StructureType::INTERACTION_PROFILE_SUGGESTED_BINDING
+        || suggestion.count_suggested_bindings == 0
+        || suggestion.suggested_bindings.is_null()
+    {
+        return xr::Result::ERROR_VALIDATION_FAILURE;
+    }
+
     log::debug!("suggest interaction profile: {:?}", suggestion);
 
-    with_instance(xr_instance.into_raw(), |instance| {
-        if suggestion.count_suggested_bindings == 0 || suggestion.suggested_bindings.is_null() {
-            return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+    let suggested_bindings = unsafe {
+        std::slice::from_raw_parts(
+            suggestion.suggested_bindings,
+            suggestion.count_suggested_bindings as usize,
+        )
+    };
+
+    // action state queries lock the instance while holding an action, so the action types are
+    // looked up before taking the instance lock
+    let mut action_types = Vec::with_capacity(suggested_bindings.len());
+    for binding in suggested_bindings {
+        match with_action(binding.action.into_raw(), |action| Ok(action.action_type)) {
+            Ok(action_type) => action_types.push(action_type),
+            Err(Error::ExpectedSome(_)) => return xr::Result::ERROR_HANDLE_INVALID,
+            Err(err) => return err.into(),
         }
+    }
+
+    with_instance(xr_instance.into_raw(), |instance| {
+        let profile_path = instance.get_path_string(suggestion.interaction_profile.into_raw())?;
+        let Some(profile) = find_profile(profile_path) else {
+            log::error!("unknown interaction profile {profile_path}");
+            return Err(xr::Result::ERROR_PATH_UNSUPPORTED.into());
+        };
 
         let mut bindings = Vec::new();
 
-        for i in 0..suggestion.count_suggested_bindings {
-            let binding = unsafe { &(*suggestion.suggested_bindings.add(i as usize)) };
+        for (binding, action_type) in suggested_bindings.iter().zip(action_types.iter()) {
+            let binding_path = instance.get_path_string(binding.binding.into_raw())?;
+            profile.validate_binding(binding_path, *action_type)?;
+
             bindings.push(ActionBinding::new(
                 binding.action.into_raw(),
                 binding.binding.into_raw(),
@@ -37,7 +76,6 @@ pub extern "system" fn suggest(
     .into_xr_result()
 }
 
-#[allow(unreachable_code)]
 pub extern "system" fn get_current(
     xr_session: xr::Session,
     top_level_user_path: xr::Path,
@@ -47,12 +85,280 @@ pub extern "system" fn get_current(
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
-    let _interaction_profile = unsafe { &mut *interaction_profile };
+    let interaction_profile = unsafe { &mut *interaction_profile };
+
+    if interaction_profile.ty != xr::StructureType::INTERACTION_PROFILE_STATE {
+        return xr::Result::ERROR_VALIDATION_FAILURE;
+    }
+
+    log::debug!("get_current {top_level_user_path:?}");
+
+    let instance_id = match with_session(xr_session.into_raw(), |session| Ok(session.instance_id)) {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
+    let user_path = match with_instance(instance_id, |instance| {
+        Ok(instance
+            .get_path_string(top_level_user_path.into_raw())?
+            .clone())
+    }) {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
+    if !TOP_LEVEL_USER_PATHS.contains(&user_path.as_str()) {
+        return xr::Result::ERROR_PATH_UNSUPPORTED;
+    }
+
+    with_session(xr_session.into_raw(), |session| {
+        if session.action_set_ids.is_empty() {
+            return Err(xr::Result::ERROR_ACTIONSET_NOT_ATTACHED.into());
+        }
+
+        let profile = session
+            .interaction_profiles
+            .get(user_path.as_str())
+            .copied()
+            .unwrap_or(0);
+
+        interaction_profile.interaction_profile = xr::Path::from_raw(profile);
 
-    with_session(xr_session.into_raw(), |_session| {
-        log::debug!("get_current {top_level_user_path:?}");
-        return Err(xr::Result::ERROR_FUNCTION_UNSUPPORTED.into());
         Ok(())
     })
     .into_xr_result()
 }
+
+/// A suggested binding of an interaction profile a session uses.
+#[derive(Debug, Clone)]
+pub struct ResolvedBinding {
+    pub(crate) action: u64,
+    pub(crate) profile: &'static InteractionProfile,
+    /// top level user path of the source, `None` if the app never created a path for it
+    pub(crate) user_path: Option<u64>,
+    pub(crate) source: String,
+}
+
+/// `/user/hand/left/input/trigger/value` -> `/user/hand/left`
+pub fn top_level_user_path(path: &str) -> &str {
+    path.find("/input/")
+        .or_else(|| path.find("/output/"))
+        .map_or(path, |idx| &path[..idx])
+}
+
+/// Picks the interaction profile of each top level user path: `preferred` when the app suggested
+/// bindings for it, otherwise the profile with the user path the app suggested bindings for first.
+/// Returns the picked profiles and their bindings.
+pub fn resolve_bindings(
+    instance: &SimulatedInstance,
+    preferred: &str,
+) -> (HashMap<&'static str, u64>, Vec<ResolvedBinding>) {
+    // the sort is stable, the others stay in suggestion order
+    let mut suggested = instance.suggested_profiles.clone();
+    suggested.sort_by_key(|profile| Some(*profile) != instance.find_path(preferred));
+
+    let mut profiles = HashMap::new();
+    let mut bindings = Vec::new();
+
+    for user_path in TOP_LEVEL_USER_PATHS {
+        let Some((profile_id, profile)) = suggested.iter().find_map(|profile_id| {
+            instance
+                .paths
+                .get(profile_id)
+                .and_then(|path| find_profile(path))
+                .filter(|profile| profile.has_user_path(user_path))
+                .map(|profile| (*profile_id, profile))
+        }) else {
+            continue;
+        };
+
+        profiles.insert(*user_path, profile_id);
+
+        for binding in &instance.interaction_profile_bindings[&profile_id] {
+            let Some(source) = instance.paths.get(&binding.binding) else {
+                continue;
+            };
+
+            if top_level_user_path(source) == *user_path {
+                bindings.push(ResolvedBinding {
+                    action: binding.action,
+                    profile,
+                    user_path: instance.find_path(user_path),
+                    source: source.clone(),
+                });
+            }
+        }
+    }
+
+    (profiles, bindings)
+}
+
+/// Re-picks the interaction profiles of a session once it has action sets attached, queueing an
+/// `INTERACTION_PROFILE_CHANGED` event when they changed.
+pub fn update_interaction_profiles(session_id: u64) -> Result<()> {
+    let (instance_id, preferred) = with_session(session_id, |session| {
+        let preferred = with_device(|device| device.inputs.interaction_profile.clone());
+        Ok((
+            session.instance_id,
+            preferred.unwrap_or_else(|| session.profile.interaction_profile.clone()),
+        ))
+    })?;
+
+    let mut resolved = Some(with_instance(instance_id, |instance| {
+        Ok(resolve_bindings(instance, &preferred))
+    })?);
+
+    with_session(session_id, |session| {
+        if session.action_set_ids.is_empty() {
+            return Ok(());
+        }
+
+        let (profiles, bindings) = resolved.take().unwrap_or_default();
+        session.set_interaction_profiles(profiles, bindings)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOUCH: &str = "/interaction_profiles/oculus/touch_controller";
+    const INDEX: &str = "/interaction_profiles/valve/index_controller";
+    const SIMPLE: &str = "/interaction_profiles/khr/simple_controller";
+
+    fn suggest(instance: &mut SimulatedInstance, profile: &str, sources: &[&str]) {
+        let profile = instance.register_path_str(profile);
+        let bindings = sources
+            .iter()
+            .map(|source| ActionBinding::new(1, instance.register_path_str(source)))
+            .collect();
+        instance
+            .set_interaction_profile_bindings(profile, bindings)
+            .unwrap();
+    }
+
+    fn instance(profiles: &[&str]) -> SimulatedInstance {
+        let mut instance = SimulatedInstance::new(1, Default::default(), Default::default());
+        for profile in profiles {
+            suggest(
+                &mut instance,
+                profile,
+                &[
+                    "/user/hand/left/input/select/click",
+                    "/user/hand/right/input/select/click",
+                ],
+            );
+        }
+        instance
+    }
+
+    fn picked(instance: &SimulatedInstance, preferred: &str, user_path: &str) -> Option<String> {
+        let (profiles, _) = resolve_bindings(instance, preferred);
+        profiles
+            .get(user_path)
+            .map(|profile| instance.paths[profile].clone())
+    }
+
+    #[test]
+    fn user_paths_are_split_off() {
+        assert_eq!(
+            top_level_user_path("/user/hand/left/input/trigger/value"),
+            "/user/hand/left"
+        );
+        assert_eq!(
+            top_level_user_path("/user/hand/right/output/haptic"),
+            "/user/hand/right"
+        );
+        assert_eq!(top_level_user_path("/user/head"), "/user/head");
+    }
+
+    #[test]
+    fn preferred_profile_wins() {
+        let instance = instance(&[TOUCH, INDEX, SIMPLE]);
+
+        for preferred in [TOUCH, INDEX, SIMPLE] {
+            assert_eq!(
+                picked(&instance, preferred, "/user/hand/left").as_deref(),
+                Some(preferred)
+            );
+        }
+    }
+
+    #[test]
+    fn first_suggested_profile_is_the_fallback() {
+        for profiles in [[TOUCH, INDEX], [INDEX, TOUCH]] {
+            let instance = instance(&profiles);
+
+            // resolving again mustn't pick differently
+            for _ in 0..8 {
+                assert_eq!(
+                    picked(&instance, SIMPLE, "/user/hand/right").as_deref(),
+                    Some(profiles[0])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn suggesting_again_keeps_the_order() {
+        let mut instance = instance(&[TOUCH, INDEX]);
+        suggest(&mut instance, TOUCH, &["/user/hand/left/input/a/click"]);
+
+        assert_eq!(
+            picked(&instance, SIMPLE, "/user/hand/left").as_deref(),
+            Some(TOUCH)
+        );
+    }
+
+    #[test]
+    fn profiles_without_the_user_path_are_skipped() {
+        let instance = instance(&["/interaction_profiles/microsoft/xbox_controller", TOUCH]);
+
+        assert_eq!(
+            picked(&instance, SIMPLE, "/user/hand/left").as_deref(),
+            Some(TOUCH)
+        );
+        assert_eq!(
+            picked(&instance, SIMPLE, "/user/gamepad").as_deref(),
+            Some("/interaction_profiles/microsoft/xbox_controller")
+        );
+        assert_eq!(picked(&instance, SIMPLE, "/user/head"), None);
+    }
+
+    #[test]
+    fn bindings_follow_the_picked_profile() {
+        let mut instance = instance(&[]);
+        suggest(
+            &mut instance,
+            TOUCH,
+            &[
+                "/user/hand/left/input/x/click",
+                "/user/hand/right/input/a/click",
+            ],
+        );
+        suggest(&mut instance, INDEX, &["/user/hand/left/input/a/click"]);
+
+        let sources = |preferred| {
+            let (_, bindings) = resolve_bindings(&instance, preferred);
+            let mut sources = bindings
+                .iter()
+                .map(|binding| (binding.profile.path, binding.source.clone()))
+                .collect::<Vec<_>>();
+            sources.sort();
+            sources
+        };
+
+        // both hands use the preferred profile, even where it has no bindings
+        assert_eq!(
+            sources(INDEX),
+            [(INDEX, "/user/hand/left/input/a/click".to_string())]
+        );
+        assert_eq!(
+            sources(TOUCH),
+            [
+                (TOUCH, "/user/hand/left/input/x/click".to_string()),
+                (TOUCH, "/user/hand/right/input/a/click".to_string()),
+            ]
+        );
+    }
+}
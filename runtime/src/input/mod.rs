@@ -2,3 +2,4 @@ pub mod action;
 pub mod action_set;
 pub mod action_state;
 pub mod interaction_profile;
+pub mod profiles;
@@ -0,0 +1,522 @@
+use crate::prelude::*;
+
+use ComponentType::{Boolean, Float, Haptic, Pose, Vector2f};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentType {
+    Boolean,
+    Float,
+    Vector2f,
+    Pose,
+    Haptic,
+}
+
+impl ComponentType {
+    /// whether a source of this type can be bound to an action of `action_type`, booleans and
+    /// floats convert to each other
+    pub fn can_bind(&self, action_type: xr::ActionType) -> bool {
+        matches!(
+            (action_type, self),
+            (
+                xr::ActionType::BOOLEAN_INPUT | xr::ActionType::FLOAT_INPUT,
+                Self::Boolean | Self::Float
+            ) | (xr::ActionType::VECTOR2F_INPUT, Self::Vector2f)
+                | (xr::ActionType::POSE_INPUT, Self::Pose)
+                | (xr::ActionType::VIBRATION_OUTPUT, Self::Haptic)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct Component {
+    pub user_paths: &'static [&'static str],
+    /// path relative to the user path, e.g. `/input/trigger/value`
+    pub path: &'static str,
+    pub ty: ComponentType,
+}
+
+#[derive(Debug)]
+pub struct InteractionProfile {
+    pub path: &'static str,
+    pub localized_name: &'static str,
+    pub components: &'static [Component],
+}
+
+impl InteractionProfile {
+    pub fn has_user_path(&self, user_path: &str) -> bool {
+        self.components
+            .iter()
+            .any(|component| component.user_paths.contains(&user_path))
+    }
+
+    /// Sources a binding path can refer to: the component itself, the components under it when
+    /// the identifier is left out (`.../input/trigger`), or one axis of a 2D component
+    /// (`.../input/thumbstick/x`). Returns the full path and type of each.
+    pub fn binding_sources(&self, binding: &str) -> Vec<(String, ComponentType)> {
+        let mut sources = Vec::new();
+
+        for component in self.components {
+            for user_path in component.user_paths {
+                let Some(path) = binding.strip_prefix(user_path) else {
+                    continue;
+                };
+
+                if path == component.path {
+                    sources.push((binding.to_string(), component.ty));
+                } else if let Some(identifier) = component
+                    .path
+                    .strip_prefix(path)
+                    .and_then(|rest| rest.strip_prefix('/'))
+                {
+                    if !identifier.contains('/') {
+                        sources.push((format!("{user_path}{}", component.path), component.ty));
+                    }
+                } else if component.ty == ComponentType::Vector2f
+                    && matches!(path.strip_prefix(component.path), Some("/x" | "/y"))
+                {
+                    sources.push((binding.to_string(), ComponentType::Float));
+                }
+            }
+        }
+
+        sources
+    }
+
+    pub fn binding_types(&self, binding: &str) -> Vec<ComponentType> {
+        self.binding_sources(binding)
+            .into_iter()
+            .map(|(_, ty)| ty)
+            .collect()
+    }
+
+    /// `ERROR_PATH_UNSUPPORTED` when the profile has no source at `binding` or none that can
+    /// drive an action of `action_type`.
+    pub fn validate_binding(&self, binding: &str, action_type: xr::ActionType) -> Result<()> {
+        let types = self.binding_types(binding);
+
+        if types.is_empty() {
+            log::error!("{} has no component {binding}", self.path);
+            return Err(xr::Result::ERROR_PATH_UNSUPPORTED.into());
+        }
+
+        if !types.iter().any(|ty| ty.can_bind(action_type)) {
+            log::error!(
+                "{binding} of {} can't be bound to a {action_type:?} action",
+                self.path
+            );
+            return Err(xr::Result::ERROR_PATH_UNSUPPORTED.into());
+        }
+
+        Ok(())
+    }
+}
+
+pub fn find_profile(path: &str) -> Option<&'static InteractionProfile> {
+    INTERACTION_PROFILES
+        .iter()
+        .find(|profile| profile.path == path)
+}
+
+/// English name of a top level user path.
+pub fn localized_user_path_name(user_path: &str) -> Option<&'static str> {
+    USER_PATH_NAMES
+        .iter()
+        .find(|(path, _)| *path == user_path)
+        .map(|(_, name)| *name)
+}
+
+/// English name of a component path relative to its user path, e.g. `/input/thumbstick/touch` ->
+/// `Thumbstick Touch`.
+pub fn localized_component_name(component_path: &str) -> Option<String> {
+    let path = component_path
+        .strip_prefix("/input/")
+        .or_else(|| component_path.strip_prefix("/output/"))?;
+
+    let mut parts = path.split('/');
+    let component = parts.next()?;
+
+    let mut name = COMPONENT_NAMES
+        .iter()
+        .find(|(path, _)| *path == component)
+        .map(|(_, name)| name.to_string())?;
+
+    for identifier in parts {
+        let (_, identifier_name) = IDENTIFIER_NAMES
+            .iter()
+            .find(|(path, _)| *path == identifier)?;
+
+        if !identifier_name.is_empty() {
+            name.push(' ');
+            name.push_str(identifier_name);
+        }
+    }
+
+    Some(name)
+}
+
+pub const TOP_LEVEL_USER_PATHS: &[&str] = &[
+    "/user/hand/left",
+    "/user/hand/right",
+    "/user/head",
+    "/user/gamepad",
+];
+
+const USER_PATH_NAMES: &[(&str, &str)] = &[
+    ("/user/hand/left", "Left Hand"),
+    ("/user/hand/right", "Right Hand"),
+    ("/user/head", "Head"),
+    ("/user/gamepad", "Gamepad"),
+];
+
+const COMPONENT_NAMES: &[(&str, &str)] = &[
+    ("select", "Select Button"),
+    ("menu", "Menu Button"),
+    ("system", "System Button"),
+    ("back", "Back Button"),
+    ("view", "View Button"),
+    ("a", "A Button"),
+    ("b", "B Button"),
+    ("x", "X Button"),
+    ("y", "Y Button"),
+    ("squeeze", "Squeeze"),
+    ("trigger", "Trigger"),
+    ("trackpad", "Trackpad"),
+    ("thumbstick", "Thumbstick"),
+    ("thumbrest", "Thumbrest"),
+    ("grip", "Grip"),
+    ("aim", "Aim"),
+    ("haptic", "Haptics"),
+    ("volume_up", "Volume Up Button"),
+    ("volume_down", "Volume Down Button"),
+    ("mute_mic", "Mute Microphone Button"),
+    ("dpad_up", "D-Pad Up"),
+    ("dpad_down", "D-Pad Down"),
+    ("dpad_left", "D-Pad Left"),
+    ("dpad_right", "D-Pad Right"),
+    ("shoulder_left", "Left Shoulder Button"),
+    ("shoulder_right", "Right Shoulder Button"),
+    ("thumbstick_left", "Left Thumbstick"),
+    ("thumbstick_right", "Right Thumbstick"),
+    ("trigger_left", "Left Trigger"),
+    ("trigger_right", "Right Trigger"),
+    ("haptic_left", "Left Haptics"),
+    ("haptic_right", "Right Haptics"),
+    ("haptic_left_trigger", "Left Trigger Haptics"),
+    ("haptic_right_trigger", "Right Trigger Haptics"),
+];
+
+/// names of the identifiers after a component, empty ones are left out of the component name
+const IDENTIFIER_NAMES: &[(&str, &str)] = &[
+    ("click", ""),
+    ("value", ""),
+    ("touch", "Touch"),
+    ("force", "Force"),
+    ("pose", "Pose"),
+    ("x", "X Axis"),
+    ("y", "Y Axis"),
+];
+
+const HANDS: &[&str] = &["/user/hand/left", "/user/hand/right"];
+const LEFT_HAND: &[&str] = &["/user/hand/left"];
+const RIGHT_HAND: &[&str] = &["/user/hand/right"];
+const HEAD: &[&str] = &["/user/head"];
+const GAMEPAD: &[&str] = &["/user/gamepad"];
+
+const fn component(
+    user_paths: &'static [&'static str],
+    path: &'static str,
+    ty: ComponentType,
+) -> Component {
+    Component {
+        user_paths,
+        path,
+        ty,
+    }
+}
+
+pub const INTERACTION_PROFILES: &[InteractionProfile] = &[
+    InteractionProfile {
+        path: "/interaction_profiles/khr/simple_controller",
+        localized_name: "Simple Controller",
+        components: &[
+            component(HANDS, "/input/select/click", Boolean),
+            component(HANDS, "/input/menu/click", Boolean),
+            component(HANDS, "/input/grip/pose", Pose),
+            component(HANDS, "/input/aim/pose", Pose),
+            component(HANDS, "/output/haptic", Haptic),
+        ],
+    },
+    InteractionProfile {
+        path: "/interaction_profiles/google/daydream_controller",
+        localized_name: "Daydream Controller",
+        components: &[
+            component(HANDS, "/input/select/click", Boolean),
+            component(HANDS, "/input/trackpad", Vector2f),
+            component(HANDS, "/input/trackpad/click", Boolean),
+            component(HANDS, "/input/trackpad/touch", Boolean),
+            component(HANDS, "/input/grip/pose", Pose),
+            component(HANDS, "/input/aim/pose", Pose),
+        ],
+    },
+    InteractionProfile {
+        path: "/interaction_profiles/htc/vive_controller",
+        localized_name: "Vive Controller",
+        components: &[
+            component(HANDS, "/input/system/click", Boolean),
+            component(HANDS, "/input/squeeze/click", Boolean),
+            component(HANDS, "/input/menu/click", Boolean),
+            component(HANDS, "/input/trigger/click", Boolean),
+            component(HANDS, "/input/trigger/value", Float),
+            component(HANDS, "/input/trackpad", Vector2f),
+            component(HANDS, "/input/trackpad/click", Boolean),
+            component(HANDS, "/input/trackpad/touch", Boolean),
+            component(HANDS, "/input/grip/pose", Pose),
+            component(HANDS, "/input/aim/pose", Pose),
+            component(HANDS, "/output/haptic", Haptic),
+        ],
+    },
+    InteractionProfile {
+        path: "/interaction_profiles/htc/vive_pro",
+        localized_name: "Vive Pro",
+        components: &[
+            component(HEAD, "/input/system/click", Boolean),
+            component(HEAD, "/input/volume_up/click", Boolean),
+            component(HEAD, "/input/volume_down/click", Boolean),
+            component(HEAD, "/input/mute_mic/click", Boolean),
+        ],
+    },
+    InteractionProfile {
+        path: "/interaction_profiles/microsoft/motion_controller",
+        localized_name: "Windows Mixed Reality Controller",
+        components: &[
+            component(HANDS, "/input/menu/click", Boolean),
+            component(HANDS, "/input/squeeze/click", Boolean),
+            component(HANDS, "/input/trigger/value", Float),
+            component(HANDS, "/input/thumbstick", Vector2f),
+            component(HANDS, "/input/thumbstick/click", Boolean),
+            component(HANDS, "/input/trackpad", Vector2f),
+            component(HANDS, "/input/trackpad/click", Boolean),
+            component(HANDS, "/input/trackpad/touch", Boolean),
+            component(HANDS, "/input/grip/pose", Pose),
+            component(HANDS, "/input/aim/pose", Pose),
+            component(HANDS, "/output/haptic", Haptic),
+        ],
+    },
+    InteractionProfile {
+        path: "/interaction_profiles/microsoft/xbox_controller",
+        localized_name: "Xbox Controller",
+        components: &[
+            component(GAMEPAD, "/input/menu/click", Boolean),
+            component(GAMEPAD, "/input/view/click", Boolean),
+            component(GAMEPAD, "/input/a/click", Boolean),
+            component(GAMEPAD, "/input/b/click", Boolean),
+            component(GAMEPAD, "/input/x/click", Boolean),
+            component(GAMEPAD, "/input/y/click", Boolean),
+            component(GAMEPAD, "/input/dpad_down/click", Boolean),
+            component(GAMEPAD, "/input/dpad_right/click", Boolean),
+            component(GAMEPAD, "/input/dpad_up/click", Boolean),
+            component(GAMEPAD, "/input/dpad_left/click", Boolean),
+            component(GAMEPAD, "/input/shoulder_left/click", Boolean),
+            component(GAMEPAD, "/input/shoulder_right/click", Boolean),
+            component(GAMEPAD, "/input/thumbstick_left/click", Boolean),
+            component(GAMEPAD, "/input/thumbstick_right/click", Boolean),
+            component(GAMEPAD, "/input/trigger_left/value", Float),
+            component(GAMEPAD, "/input/trigger_right/value", Float),
+            component(GAMEPAD, "/input/thumbstick_left", Vector2f),
+            component(GAMEPAD, "/input/thumbstick_right", Vector2f),
+            component(GAMEPAD, "/output/haptic_left", Haptic),
+            component(GAMEPAD, "/output/haptic_right", Haptic),
+            component(GAMEPAD, "/output/haptic_left_trigger", Haptic),
+            component(GAMEPAD, "/output/haptic_right_trigger", Haptic),
+        ],
+    },
+    InteractionProfile {
+        path: "/interaction_profiles/oculus/go_controller",
+        localized_name: "Oculus Go Controller",
+        components: &[
+            component(HANDS, "/input/system/click", Boolean),
+            component(HANDS, "/input/trigger/click", Boolean),
+            component(HANDS, "/input/back/click", Boolean),
+            component(HANDS, "/input/trackpad", Vector2f),
+            component(HANDS, "/input/trackpad/click", Boolean),
+            component(HANDS, "/input/trackpad/touch", Boolean),
+            component(HANDS, "/input/grip/pose", Pose),
+            component(HANDS, "/input/aim/pose", Pose),
+        ],
+    },
+    InteractionProfile {
+        path: "/interaction_profiles/oculus/touch_controller",
+        localized_name: "Oculus Touch Controller",
+        components: &[
+            component(LEFT_HAND, "/input/x/click", Boolean),
+            component(LEFT_HAND, "/input/x/touch", Boolean),
+            component(LEFT_HAND, "/input/y/click", Boolean),
+            component(LEFT_HAND, "/input/y/touch", Boolean),
+            component(LEFT_HAND, "/input/menu/click", Boolean),
+            component(RIGHT_HAND, "/input/a/click", Boolean),
+            component(RIGHT_HAND, "/input/a/touch", Boolean),
+            component(RIGHT_HAND, "/input/b/click", Boolean),
+            component(RIGHT_HAND, "/input/b/touch", Boolean),
+            component(RIGHT_HAND, "/input/system/click", Boolean),
+            component(HANDS, "/input/squeeze/value", Float),
+            component(HANDS, "/input/trigger/value", Float),
+            component(HANDS, "/input/trigger/touch", Boolean),
+            component(HANDS, "/input/thumbstick", Vector2f),
+            component(HANDS, "/input/thumbstick/click", Boolean),
+            component(HANDS, "/input/thumbstick/touch", Boolean),
+            component(HANDS, "/input/thumbrest/touch", Boolean),
+            component(HANDS, "/input/grip/pose", Pose),
+            component(HANDS, "/input/aim/pose", Pose),
+            component(HANDS, "/output/haptic", Haptic),
+        ],
+    },
+    InteractionProfile {
+        path: "/interaction_profiles/valve/index_controller",
+        localized_name: "Index Controller",
+        components: &[
+            component(HANDS, "/input/system/click", Boolean),
+            component(HANDS, "/input/system/touch", Boolean),
+            component(HANDS, "/input/a/click", Boolean),
+            component(HANDS, "/input/a/touch", Boolean),
+            component(HANDS, "/input/b/click", Boolean),
+            component(HANDS, "/input/b/touch", Boolean),
+            component(HANDS, "/input/squeeze/value", Float),
+            component(HANDS, "/input/squeeze/force", Float),
+            component(HANDS, "/input/trigger/click", Boolean),
+            component(HANDS, "/input/trigger/value", Float),
+            component(HANDS, "/input/trigger/touch", Boolean),
+            component(HANDS, "/input/thumbstick", Vector2f),
+            component(HANDS, "/input/thumbstick/click", Boolean),
+            component(HANDS, "/input/thumbstick/touch", Boolean),
+            component(HANDS, "/input/trackpad", Vector2f),
+            component(HANDS, "/input/trackpad/force", Float),
+            component(HANDS, "/input/trackpad/touch", Boolean),
+            component(HANDS, "/input/grip/pose", Pose),
+            component(HANDS, "/input/aim/pose", Pose),
+            component(HANDS, "/output/haptic", Haptic),
+        ],
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch_controller() -> &'static InteractionProfile {
+        find_profile("/interaction_profiles/oculus/touch_controller").unwrap()
+    }
+
+    fn is_unsupported(res: Result<()>) -> bool {
+        matches!(
+            res,
+            Err(Error::XrResult(xr::Result::ERROR_PATH_UNSUPPORTED))
+        )
+    }
+
+    #[test]
+    fn unknown_profiles_arent_found() {
+        assert!(find_profile("/interaction_profiles/khr/simple_controller").is_some());
+        assert!(find_profile("/interaction_profiles/khr/simple_controllr").is_none());
+    }
+
+    #[test]
+    fn bindings_must_name_a_component_of_the_profile() {
+        let profile = touch_controller();
+
+        assert!(
+            profile
+                .validate_binding(
+                    "/user/hand/left/input/x/click",
+                    xr::ActionType::BOOLEAN_INPUT
+                )
+                .is_ok()
+        );
+        // the X button is only on the left controller
+        assert!(is_unsupported(profile.validate_binding(
+            "/user/hand/right/input/x/click",
+            xr::ActionType::BOOLEAN_INPUT
+        )));
+        assert!(is_unsupported(profile.validate_binding(
+            "/user/hand/left/input/trackpad",
+            xr::ActionType::VECTOR2F_INPUT
+        )));
+    }
+
+    #[test]
+    fn bindings_must_fit_the_action_type() {
+        let profile = touch_controller();
+
+        assert!(
+            profile
+                .validate_binding(
+                    "/user/hand/left/input/trigger/value",
+                    xr::ActionType::BOOLEAN_INPUT
+                )
+                .is_ok()
+        );
+        assert!(is_unsupported(profile.validate_binding(
+            "/user/hand/left/input/grip/pose",
+            xr::ActionType::FLOAT_INPUT
+        )));
+        assert!(is_unsupported(profile.validate_binding(
+            "/user/hand/left/output/haptic",
+            xr::ActionType::BOOLEAN_INPUT
+        )));
+        assert!(
+            profile
+                .validate_binding(
+                    "/user/hand/left/output/haptic",
+                    xr::ActionType::VIBRATION_OUTPUT
+                )
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn components_can_be_bound_without_their_identifier() {
+        let sources = touch_controller().binding_sources("/user/hand/right/input/trigger");
+
+        assert_eq!(
+            sources,
+            vec![
+                (
+                    "/user/hand/right/input/trigger/value".to_string(),
+                    ComponentType::Float
+                ),
+                (
+                    "/user/hand/right/input/trigger/touch".to_string(),
+                    ComponentType::Boolean
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn axes_of_2d_components_are_floats() {
+        let profile = touch_controller();
+
+        assert_eq!(
+            profile.binding_types("/user/hand/left/input/thumbstick/x"),
+            vec![ComponentType::Float]
+        );
+        assert!(
+            profile
+                .binding_types("/user/hand/left/input/thumbstick/z")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn component_names() {
+        assert_eq!(
+            localized_component_name("/input/thumbstick/touch").as_deref(),
+            Some("Thumbstick Touch")
+        );
+        assert_eq!(
+            localized_component_name("/input/trigger/value").as_deref(),
+            Some("Trigger")
+        );
+        assert_eq!(localized_component_name("/input/nothing/click"), None);
+        assert_eq!(localized_user_path_name("/user/head"), Some("Head"));
+    }
+}
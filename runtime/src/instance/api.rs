@@ -1,22 +1,44 @@
 use std::{
     cell::UnsafeCell,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ffi::{CStr, c_char},
     sync::{LazyLock, Mutex, atomic},
+    time::Duration,
 };
 
 use crate::{
-    event::create_queue,
+    clock, control,
+    event::{Event, create_queue, destroy_queue, schedule_event},
+    handle::{self, Handle},
     prelude::*,
-    utils::{copy_str_to_cchar_ptr, copy_u8slice_to_cchar_arr, with_obj_instance},
+    profile::DeviceProfile,
+    utils::{MyTime, copy_str_to_cchar_ptr, copy_u8slice_to_cchar_arr, with_obj_instance},
 };
 
 use super::obj::SimulatedInstance;
 
-const SUPPORTED_EXTS: &[(&[u8], u32)] = &[(
-    xr::KHR_VULKAN_ENABLE_EXTENSION_NAME,
-    xr::KHR_vulkan_enable_SPEC_VERSION,
-)];
+const SUPPORTED_EXTS: &[(&[u8], u32)] = &[
+    (
+        xr::KHR_VULKAN_ENABLE_EXTENSION_NAME,
+        xr::KHR_vulkan_enable_SPEC_VERSION,
+    ),
+    (
+        xr::KHR_COMPOSITION_LAYER_CYLINDER_EXTENSION_NAME,
+        xr::KHR_composition_layer_cylinder_SPEC_VERSION,
+    ),
+    (
+        xr::KHR_COMPOSITION_LAYER_EQUIRECT2_EXTENSION_NAME,
+        xr::KHR_composition_layer_equirect2_SPEC_VERSION,
+    ),
+    (
+        xr::KHR_COMPOSITION_LAYER_CUBE_EXTENSION_NAME,
+        xr::KHR_composition_layer_cube_SPEC_VERSION,
+    ),
+    (
+        xr::KHR_COMPOSITION_LAYER_DEPTH_EXTENSION_NAME,
+        xr::KHR_composition_layer_depth_SPEC_VERSION,
+    ),
+];
 
 pub extern "system" fn enumerate_extension_properties(
     layer_name: *const c_char,
@@ -57,21 +79,33 @@ pub extern "system" fn create(
     create_info: *const xr::InstanceCreateInfo,
     xr_instance: *mut xr::Instance,
 ) -> xr::Result {
-    if xr_instance.is_null() {
+    if create_info.is_null() || xr_instance.is_null() {
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
+    let enabled_extensions = match enabled_extensions(unsafe { &*create_info }) {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
+    let profile = match DeviceProfile::load() {
+        Ok(profile) => profile,
+        Err(err) => return err.into(),
+    };
+
     let next_id = INSTANCE_COUNTER.fetch_add(1, atomic::Ordering::SeqCst);
-    INSTANCES
-        .lock()
-        .unwrap()
-        .insert(next_id, UnsafeCell::new(SimulatedInstance::new(next_id)));
+    INSTANCES.lock().unwrap().insert(
+        next_id,
+        UnsafeCell::new(SimulatedInstance::new(next_id, profile, enabled_extensions)),
+    );
 
     unsafe {
         *xr_instance = xr::Instance::from_raw(next_id);
     }
 
-    if let Err(err) = create_queue(next_id) {
+    if let Err(err) =
+        create_queue(next_id).and_then(|_| handle::register(Handle::Instance(next_id), None))
+    {
         return err.into();
     }
 
@@ -79,28 +113,42 @@ pub extern "system" fn create(
         *create_info
     });
 
+    control::start();
+
     xr::Result::SUCCESS
 }
 
-pub extern "system" fn destroy(xr_obj: xr::Instance) -> xr::Result {
-    if xr_obj == xr::Instance::NULL {
-        return xr::Result::ERROR_HANDLE_INVALID;
+/// names of the extensions the app enabled, all of them must be supported
+fn enabled_extensions(create_info: &xr::InstanceCreateInfo) -> Result<HashSet<String>> {
+    if create_info.enabled_extension_count > 0 && create_info.enabled_extension_names.is_null() {
+        return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
     }
 
-    let instance_id = xr_obj.into_raw();
+    let mut enabled_extensions = HashSet::new();
 
-    if INSTANCES
-        .lock()
-        .expect("couldn't acquire instances")
-        .remove(&instance_id)
-        .is_some()
-    {
-        log::debug!("destroyed {instance_id}");
-    } else {
-        log::debug!("instance {instance_id} not found");
+    for i in 0..create_info.enabled_extension_count as usize {
+        let name = unsafe { CStr::from_ptr(*create_info.enabled_extension_names.add(i)) };
+
+        if !SUPPORTED_EXTS
+            .iter()
+            .any(|(ext_name, _)| CStr::from_bytes_with_nul(ext_name).ok() == Some(name))
+        {
+            log::error!("extension not supported: {name:?}");
+            return Err(xr::Result::ERROR_EXTENSION_NOT_PRESENT.into());
+        }
+
+        enabled_extensions.insert(name.to_str()?.to_owned());
     }
 
-    xr::Result::SUCCESS
+    Ok(enabled_extensions)
+}
+
+pub extern "system" fn destroy(xr_obj: xr::Instance) -> xr::Result {
+    if xr_obj == xr::Instance::NULL {
+        return xr::Result::ERROR_HANDLE_INVALID;
+    }
+
+    handle::destroy(Handle::Instance(xr_obj.into_raw())).into_xr_result()
 }
 
 pub extern "system" fn create_from_instance(
@@ -191,18 +239,157 @@ type SharedSimulatedInstance = UnsafeCell<SimulatedInstance>;
 static INSTANCES: LazyLock<Mutex<HashMap<u64, SharedSimulatedInstance>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
+/// runtime time each instance the control interface is losing is lost at
+static LOST_INSTANCES: LazyLock<Mutex<HashMap<u64, Duration>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Simulates the runtime going away, every instance gets an `XrEventDataInstanceLossPending`
+/// and is lost once `delay` has passed. Apps have to destroy them and start over.
+pub fn lose_all_instances(delay: Duration) -> Result<()> {
+    let loss_time = clock::after(delay).ok_or("the instance loss delay is too long")?;
+    let xr_loss_time = MyTime::from(loss_time);
+
+    let instances = INSTANCES.lock()?;
+    if instances.is_empty() {
+        return Err("no active instance".into());
+    }
+
+    let mut lost_instances = LOST_INSTANCES.lock()?;
+
+    for &instance_id in instances.keys() {
+        if lost_instances.contains_key(&instance_id) {
+            continue;
+        }
+
+        log::info!("[{instance_id}] instance lost at {loss_time:?}");
+        lost_instances.insert(instance_id, loss_time);
+        schedule_event(
+            instance_id,
+            &Event::InstanceLossPending {
+                loss_time: xr_loss_time,
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Fails with `ERROR_INSTANCE_LOST` once the loss time of the instance has passed.
+pub fn check_instance_lost(instance_id: u64) -> Result<()> {
+    let loss_time = LOST_INSTANCES.lock()?.get(&instance_id).copied();
+    match loss_time {
+        Some(loss_time) if clock::now() >= loss_time => Err(xr::Result::ERROR_INSTANCE_LOST.into()),
+        _ => Ok(()),
+    }
+}
+
 pub fn with_instance<T, F>(xr_instance_id: u64, f: F) -> Result<T>
 where
     F: FnMut(&mut SimulatedInstance) -> Result<T>,
 {
+    check_instance_lost(xr_instance_id)?;
+
     match with_obj_instance(&INSTANCES, xr_instance_id, f) {
         Ok(res) => Ok(res),
         Err(err) => match err {
             Error::ExpectedSome(err) => {
                 log::error!("error: {err}");
-                Err(openxr_sys::Result::ERROR_INSTANCE_LOST.into())
+                Err(openxr_sys::Result::ERROR_HANDLE_INVALID.into())
             }
             _ => Err(err),
         },
     }
 }
+
+/// Like `with_instance` but also reaches lost instances, for releasing the objects that belong to
+/// them.
+pub fn with_instance_unchecked<T, F>(instance_id: u64, f: F) -> Result<T>
+where
+    F: FnMut(&mut SimulatedInstance) -> Result<T>,
+{
+    with_obj_instance(&INSTANCES, instance_id, f)
+}
+
+/// Releases a destroyed instance with its event queue.
+pub fn remove(instance_id: u64) -> Result<()> {
+    LOST_INSTANCES.lock()?.remove(&instance_id);
+    destroy_queue(instance_id)?;
+
+    if INSTANCES.lock()?.remove(&instance_id).is_none() {
+        return Err(xr::Result::ERROR_HANDLE_INVALID.into());
+    }
+
+    log::debug!("destroyed {instance_id}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::with_event_queue;
+
+    /// Every test passes its own id, the instances are shared with the whole process.
+    fn add_instance(id: u64) {
+        INSTANCES.lock().unwrap().insert(
+            id,
+            UnsafeCell::new(SimulatedInstance::new(
+                id,
+                Default::default(),
+                Default::default(),
+            )),
+        );
+        create_queue(id).unwrap();
+    }
+
+    fn queued_events(id: u64) -> usize {
+        with_event_queue(id, |queue| Ok(queue.len())).unwrap()
+    }
+
+    #[test]
+    fn instances_are_lost_after_the_delay() {
+        let id = u64::MAX - 201;
+        add_instance(id);
+        let delay = Duration::from_secs(60 * 60);
+
+        lose_all_instances(delay).unwrap();
+
+        let loss_time = LOST_INSTANCES.lock().unwrap()[&id];
+        assert!(loss_time > clock::now() + delay - Duration::from_secs(60));
+        assert_eq!(queued_events(id), 1);
+        check_instance_lost(id).unwrap();
+        with_instance(id, |_| Ok(())).unwrap();
+
+        // the first loss time stays and the event isn't queued again
+        lose_all_instances(Duration::ZERO).unwrap();
+        assert_eq!(LOST_INSTANCES.lock().unwrap()[&id], loss_time);
+        assert_eq!(queued_events(id), 1);
+
+        remove(id).unwrap();
+        assert!(!LOST_INSTANCES.lock().unwrap().contains_key(&id));
+    }
+
+    #[test]
+    fn lost_instances_fail_until_destroyed() {
+        let id = u64::MAX - 202;
+        add_instance(id);
+        LOST_INSTANCES.lock().unwrap().insert(id, Duration::ZERO);
+
+        assert!(matches!(
+            with_instance(id, |_| Ok(())),
+            Err(Error::XrResult(xr::Result::ERROR_INSTANCE_LOST))
+        ));
+        with_instance_unchecked(id, |_| Ok(())).unwrap();
+
+        remove(id).unwrap();
+        assert!(matches!(
+            with_instance(id, |_| Ok(())),
+            Err(Error::XrResult(xr::Result::ERROR_HANDLE_INVALID))
+        ));
+    }
+
+    #[test]
+    fn loss_times_must_fit_xr_time() {
+        assert!(lose_all_instances(Duration::MAX).is_err());
+        assert!(clock::after(clock::MAX_TIME).is_none());
+    }
+}
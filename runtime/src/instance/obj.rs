@@ -1,10 +1,10 @@
 use std::{
     collections::{HashMap, HashSet},
     ffi::CStr,
-    sync::atomic,
+    sync::{Arc, atomic},
 };
 
-use crate::{prelude::*, utils::copy_str_to_cchar_arr};
+use crate::{prelude::*, profile::DeviceProfile, utils::copy_str_to_cchar_arr};
 
 static COUNTER: atomic::AtomicU64 = atomic::AtomicU64::new(1);
 
@@ -16,7 +16,6 @@ pub enum InstanceState {
     ActionSetCreated,
 }
 
-#[allow(dead_code)]
 #[derive(Debug)]
 pub struct ActionBinding {
     pub(crate) action: u64,
@@ -32,22 +31,29 @@ impl ActionBinding {
 #[derive(Debug)]
 pub struct SimulatedInstance {
     pub(crate) id: u64,
+    pub(crate) profile: Arc<DeviceProfile>,
+    pub(crate) enabled_extensions: Arc<HashSet<String>>,
     pub(crate) state: InstanceState,
     pub(crate) session_id: Option<u64>,
     pub(crate) action_set_ids: HashSet<u64>,
     pub(crate) paths: HashMap<u64, String>,
     pub(crate) interaction_profile_bindings: HashMap<u64, Vec<ActionBinding>>,
+    /// interaction profiles in the order the app first suggested bindings for them
+    pub(crate) suggested_profiles: Vec<u64>,
 }
 
 impl SimulatedInstance {
-    pub fn new(id: u64) -> Self {
+    pub fn new(id: u64, profile: DeviceProfile, enabled_extensions: HashSet<String>) -> Self {
         Self {
             id,
+            profile: Arc::new(profile),
+            enabled_extensions: Arc::new(enabled_extensions),
             state: InstanceState::Created,
             session_id: None,
             action_set_ids: HashSet::new(),
             paths: HashMap::new(),
             interaction_profile_bindings: HashMap::new(),
+            suggested_profiles: Vec::new(),
         }
     }
 
@@ -65,18 +71,26 @@ impl SimulatedInstance {
     }
 
     pub fn register_path(&mut self, path: &CStr) -> Result<u64> {
+        Ok(self.register_path_str(path.to_str()?))
+    }
+
+    pub fn register_path_str(&mut self, path: &str) -> u64 {
+        if let Some(path_id) = self.find_path(path) {
+            return path_id;
+        }
+
         let new_id = COUNTER.fetch_add(1, atomic::Ordering::SeqCst);
-        self.paths.insert(new_id, path.to_str()?.into());
+        self.paths.insert(new_id, path.into());
         log::debug!(
             "[{}] registered path {} at {}",
             self.id,
             &self.paths[&new_id],
             new_id
         );
-        Ok(new_id)
+        new_id
     }
 
-    pub fn get_path_string(&mut self, path_id: u64) -> Result<&String> {
+    pub fn get_path_string(&self, path_id: u64) -> Result<&String> {
         if let Some(path) = self.paths.get(&path_id) {
             Ok(path)
         } else {
@@ -84,6 +98,13 @@ impl SimulatedInstance {
         }
     }
 
+    pub fn find_path(&self, path: &str) -> Option<u64> {
+        self.paths
+            .iter()
+            .find(|(_, value)| *value == path)
+            .map(|(path_id, _)| *path_id)
+    }
+
     pub fn set_session(&mut self, session_id: u64) -> Result<()> {
         if let InstanceState::Created = self.state {
             self.session_id = Some(session_id);
@@ -96,6 +117,13 @@ impl SimulatedInstance {
         }
     }
 
+    pub fn remove_session(&mut self, session_id: u64) {
+        if self.session_id == Some(session_id) {
+            self.session_id = None;
+            self.state = InstanceState::Created;
+        }
+    }
+
     pub fn add_action_set(&mut self, action_set_id: u64) -> Result<()> {
         if let InstanceState::SessionCreated = self.state {
             self.action_set_ids.insert(action_set_id);
@@ -112,8 +140,13 @@ impl SimulatedInstance {
         interaction_profile: u64,
         bindings: Vec<ActionBinding>,
     ) -> Result<()> {
-        self.interaction_profile_bindings
-            .insert(interaction_profile, bindings);
+        if self
+            .interaction_profile_bindings
+            .insert(interaction_profile, bindings)
+            .is_none()
+        {
+            self.suggested_profiles.push(interaction_profile);
+        }
         log::debug!(
             "set interaction profile bindings {:?}",
             &self.interaction_profile_bindings[&interaction_profile]
@@ -1,14 +1,22 @@
+mod clock;
+mod control;
+mod device;
 pub mod error;
 mod event;
+mod handle;
 mod haptics;
 mod input;
 mod instance;
 mod loader;
+mod math;
 mod path;
+mod profile;
 mod rendering;
 mod session;
 mod spaces;
 mod system;
+#[cfg(test)]
+mod test_utils;
 mod utils;
 mod view;
 mod vulkan;
@@ -0,0 +1,115 @@
+pub fn quat_multiply(a: &xr::Quaternionf, b: &xr::Quaternionf) -> xr::Quaternionf {
+    xr::Quaternionf {
+        x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+        y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+        z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+    }
+}
+
+pub fn quat_conjugate(q: &xr::Quaternionf) -> xr::Quaternionf {
+    xr::Quaternionf {
+        x: -q.x,
+        y: -q.y,
+        z: -q.z,
+        w: q.w,
+    }
+}
+
+pub fn quat_normalize(q: &xr::Quaternionf) -> xr::Quaternionf {
+    let len = (q.x * q.x + q.y * q.y + q.z * q.z + q.w * q.w).sqrt();
+    if len <= f32::EPSILON {
+        return xr::Quaternionf {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        };
+    }
+
+    xr::Quaternionf {
+        x: q.x / len,
+        y: q.y / len,
+        z: q.z / len,
+        w: q.w / len,
+    }
+}
+
+pub fn quat_rotate(q: &xr::Quaternionf, v: &xr::Vector3f) -> xr::Vector3f {
+    // v' = v + 2w(u x v) + 2u x (u x v), with u the vector part of q
+    let u = xr::Vector3f {
+        x: q.x,
+        y: q.y,
+        z: q.z,
+    };
+    let uv = vec3_cross(&u, v);
+    let uuv = vec3_cross(&u, &uv);
+
+    xr::Vector3f {
+        x: v.x + 2.0 * (q.w * uv.x + uuv.x),
+        y: v.y + 2.0 * (q.w * uv.y + uuv.y),
+        z: v.z + 2.0 * (q.w * uv.z + uuv.z),
+    }
+}
+
+pub fn vec3_add(a: &xr::Vector3f, b: &xr::Vector3f) -> xr::Vector3f {
+    xr::Vector3f {
+        x: a.x + b.x,
+        y: a.y + b.y,
+        z: a.z + b.z,
+    }
+}
+
+pub fn vec3_sub(a: &xr::Vector3f, b: &xr::Vector3f) -> xr::Vector3f {
+    xr::Vector3f {
+        x: a.x - b.x,
+        y: a.y - b.y,
+        z: a.z - b.z,
+    }
+}
+
+pub fn vec3_cross(a: &xr::Vector3f, b: &xr::Vector3f) -> xr::Vector3f {
+    xr::Vector3f {
+        x: a.y * b.z - a.z * b.y,
+        y: a.z * b.x - a.x * b.z,
+        z: a.x * b.y - a.y * b.x,
+    }
+}
+
+/// Composes two poses, `a * b`: `b` is expressed in the space of `a`, the result is expressed in
+/// the space `a` is expressed in.
+pub fn pose_multiply(a: &xr::Posef, b: &xr::Posef) -> xr::Posef {
+    xr::Posef {
+        orientation: quat_normalize(&quat_multiply(&a.orientation, &b.orientation)),
+        position: vec3_add(&a.position, &quat_rotate(&a.orientation, &b.position)),
+    }
+}
+
+pub fn pose_inverse(pose: &xr::Posef) -> xr::Posef {
+    let orientation = quat_conjugate(&pose.orientation);
+    let position = quat_rotate(&orientation, &pose.position);
+
+    xr::Posef {
+        orientation,
+        position: xr::Vector3f {
+            x: -position.x,
+            y: -position.y,
+            z: -position.z,
+        },
+    }
+}
+
+pub fn vec3_dot(a: &xr::Vector3f, b: &xr::Vector3f) -> f32 {
+    a.x * b.x + a.y * b.y + a.z * b.z
+}
+
+/// Poses passed by apps must have a unit quaternion, give or take rounding errors.
+pub fn is_pose_valid(pose: &xr::Posef) -> bool {
+    let q = &pose.orientation;
+    let len = (q.x * q.x + q.y * q.y + q.z * q.z + q.w * q.w).sqrt();
+
+    (len - 1.0).abs() <= 0.01
+        && [pose.position.x, pose.position.y, pose.position.z]
+            .iter()
+            .all(|value| value.is_finite())
+}
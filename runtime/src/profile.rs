@@ -0,0 +1,408 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{input::profiles::find_profile, prelude::*};
+
+/// Path of a device profile to load, takes precedence over the one next to the runtime manifest.
+pub const PROFILE_PATH_ENV: &str = "OPENXR_DEVICE_SIMULATOR_PROFILE";
+
+const MANIFEST_PATH_ENV: &str = "XR_RUNTIME_JSON";
+const MANIFEST_PROFILE_NAMES: &[&str] = &["device_profile.toml", "device_profile.json"];
+
+const DEFAULT_INTERACTION_PROFILE: &str = "/interaction_profiles/khr/simple_controller";
+
+/// refresh rates in Hz a profile can ask for
+const REFRESH_RATES: std::ops::RangeInclusive<f32> = 1.0..=1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    Opaque,
+    Additive,
+    AlphaBlend,
+}
+
+impl From<BlendMode> for xr::EnvironmentBlendMode {
+    fn from(value: BlendMode) -> Self {
+        match value {
+            BlendMode::Opaque => Self::OPAQUE,
+            BlendMode::Additive => Self::ADDITIVE,
+            BlendMode::AlphaBlend => Self::ALPHA_BLEND,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GraphicsProfile {
+    pub max_swapchain_image_width: u32,
+    pub max_swapchain_image_height: u32,
+    pub max_layer_count: u32,
+}
+
+impl Default for GraphicsProfile {
+    fn default() -> Self {
+        Self {
+            max_swapchain_image_width: 1024,
+            max_swapchain_image_height: 1024,
+            max_layer_count: xr::MIN_COMPOSITION_LAYERS_SUPPORTED as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackingProfile {
+    pub orientation: bool,
+    pub position: bool,
+}
+
+impl Default for TrackingProfile {
+    fn default() -> Self {
+        Self {
+            orientation: true,
+            position: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ViewProfile {
+    pub recommended_width: u32,
+    pub recommended_height: u32,
+    pub max_width: u32,
+    pub max_height: u32,
+    pub recommended_sample_count: u32,
+    pub max_sample_count: u32,
+}
+
+impl Default for ViewProfile {
+    fn default() -> Self {
+        Self {
+            recommended_width: 1024,
+            recommended_height: 1024,
+            max_width: 1024,
+            max_height: 1024,
+            recommended_sample_count: 1,
+            max_sample_count: 1,
+        }
+    }
+}
+
+impl ViewProfile {
+    pub fn to_xr(&self, ty: xr::StructureType) -> xr::ViewConfigurationView {
+        xr::ViewConfigurationView {
+            ty,
+            next: std::ptr::null_mut(),
+            recommended_image_rect_width: self.recommended_width,
+            max_image_rect_width: self.max_width,
+            recommended_image_rect_height: self.recommended_height,
+            max_image_rect_height: self.max_height,
+            recommended_swapchain_sample_count: self.recommended_sample_count,
+            max_swapchain_sample_count: self.max_sample_count,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionProfile {
+    /// how long after creation sessions become ready, in milliseconds of runtime time
+    pub ready_delay_ms: u64,
+}
+
+/// Description of the simulated headset, every field is optional in the profile file and
+/// defaults to the built-in device.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceProfile {
+    pub system_name: String,
+    pub vendor_id: u32,
+    /// display refresh rate in Hz
+    pub refresh_rate: f32,
+    pub blend_modes: Vec<BlendMode>,
+    /// interaction profile of the simulated controllers
+    pub interaction_profile: String,
+    pub graphics: GraphicsProfile,
+    pub tracking: TrackingProfile,
+    pub session: SessionProfile,
+    /// left and right eye views of the stereo configuration
+    pub views: [ViewProfile; 2],
+}
+
+impl Default for DeviceProfile {
+    fn default() -> Self {
+        Self {
+            system_name: "openxr-device-simulator".into(),
+            vendor_id: 0x079c98d4,
+            refresh_rate: 60.0,
+            blend_modes: vec![BlendMode::Opaque],
+            interaction_profile: DEFAULT_INTERACTION_PROFILE.into(),
+            graphics: GraphicsProfile::default(),
+            tracking: TrackingProfile::default(),
+            session: SessionProfile::default(),
+            views: Default::default(),
+        }
+    }
+}
+
+impl DeviceProfile {
+    /// Loads the profile selected by `$OPENXR_DEVICE_SIMULATOR_PROFILE`, or the one next to the
+    /// runtime manifest, falling back to the built-in device when there's none.
+    pub fn load() -> Result<Self> {
+        let Some(path) = find_profile_path() else {
+            log::info!("no device profile found, using the built-in one");
+            return Ok(Self::default());
+        };
+
+        let profile = Self::load_from(&path)?;
+        log::info!("loaded device profile {}: {profile:?}", path.display());
+
+        Ok(profile)
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|err| {
+            log::error!("could not read device profile {}: {err}", path.display());
+            xr::Result::ERROR_FILE_ACCESS_ERROR
+        })?;
+
+        let parsed = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str::<Self>(&contents).map_err(|err| err.to_string())
+        } else {
+            toml::from_str::<Self>(&contents).map_err(|err| err.to_string())
+        };
+
+        let profile = parsed.and_then(|profile| profile.validate().map(|_| profile));
+
+        profile.map_err(|err| {
+            log::error!("invalid device profile {}: {err}", path.display());
+            xr::Result::ERROR_FILE_CONTENTS_INVALID.into()
+        })
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.system_name.len() + 1 > xr::MAX_SYSTEM_NAME_SIZE {
+            return Err("system_name is too long".into());
+        }
+
+        if !REFRESH_RATES.contains(&self.refresh_rate) {
+            return Err(format!(
+                "refresh_rate must be between {} and {} Hz",
+                REFRESH_RATES.start(),
+                REFRESH_RATES.end()
+            ));
+        }
+
+        if self.blend_modes.is_empty() {
+            return Err("at least one blend mode is required".into());
+        }
+
+        if find_profile(&self.interaction_profile).is_none() {
+            return Err(format!(
+                "unknown interaction profile {}",
+                self.interaction_profile
+            ));
+        }
+
+        if (self.graphics.max_layer_count as usize) < xr::MIN_COMPOSITION_LAYERS_SUPPORTED {
+            return Err(format!(
+                "max_layer_count must be at least {}",
+                xr::MIN_COMPOSITION_LAYERS_SUPPORTED
+            ));
+        }
+
+        for (eye, view) in self.views.iter().enumerate() {
+            if view.recommended_width == 0
+                || view.recommended_height == 0
+                || view.recommended_width > view.max_width
+                || view.recommended_height > view.max_height
+            {
+                return Err(format!("view {eye}: invalid resolution"));
+            }
+
+            if view.max_width > self.graphics.max_swapchain_image_width
+                || view.max_height > self.graphics.max_swapchain_image_height
+            {
+                return Err(format!("view {eye}: larger than the max swapchain size"));
+            }
+
+            if view.recommended_sample_count == 0
+                || view.recommended_sample_count > view.max_sample_count
+            {
+                return Err(format!("view {eye}: invalid sample count"));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn display_period(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.refresh_rate as f64)
+    }
+
+    pub fn ready_delay(&self) -> Duration {
+        Duration::from_millis(self.session.ready_delay_ms)
+    }
+
+    pub fn environment_blend_modes(&self) -> Vec<xr::EnvironmentBlendMode> {
+        self.blend_modes.iter().map(|&mode| mode.into()).collect()
+    }
+}
+
+fn find_profile_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(PROFILE_PATH_ENV) {
+        return Some(path.into());
+    }
+
+    let manifest = PathBuf::from(std::env::var_os(MANIFEST_PATH_ENV)?);
+    let manifest_dir = manifest.parent()?;
+
+    MANIFEST_PROFILE_NAMES
+        .iter()
+        .map(|name| manifest_dir.join(name))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// writes `contents` to a file only the calling test uses
+    fn profile_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "openxr-device-simulator-{}-{name}",
+            std::process::id()
+        ));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn load_error(name: &str, contents: &str) -> xr::Result {
+        let path = profile_file(name, contents);
+        let res = DeviceProfile::load_from(&path);
+        std::fs::remove_file(path).unwrap();
+
+        match res {
+            Err(Error::XrResult(res)) => res,
+            res => panic!("expected an xr error, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn built_in_profile_is_valid() {
+        assert_eq!(DeviceProfile::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn refresh_rates_must_be_sane() {
+        for refresh_rate in [1.0, 72.0, 1000.0] {
+            let profile = DeviceProfile {
+                refresh_rate,
+                ..Default::default()
+            };
+            assert_eq!(profile.validate(), Ok(()));
+            assert!(profile.display_period() > Duration::ZERO);
+        }
+
+        for refresh_rate in [1e-30, 0.0, -60.0, 1000.5, f32::INFINITY, f32::NAN] {
+            let profile = DeviceProfile {
+                refresh_rate,
+                ..Default::default()
+            };
+            assert!(profile.validate().is_err(), "{refresh_rate} is valid");
+        }
+    }
+
+    #[test]
+    fn views_must_fit_their_limits() {
+        let mut profile = DeviceProfile::default();
+        profile.views[1].recommended_width = profile.views[1].max_width + 1;
+        assert!(profile.validate().is_err());
+
+        let mut profile = DeviceProfile::default();
+        profile.views[0].max_height = profile.graphics.max_swapchain_image_height + 1;
+        assert!(profile.validate().is_err());
+
+        let mut profile = DeviceProfile::default();
+        profile.views[0].recommended_sample_count = 2;
+        assert!(profile.validate().is_err());
+    }
+
+    #[test]
+    fn toml_profiles_override_the_defaults() {
+        let path = profile_file(
+            "override.toml",
+            r#"
+                system_name = "Test Headset"
+                refresh_rate = 90.0
+                blend_modes = ["additive", "opaque"]
+
+                [tracking]
+                position = false
+            "#,
+        );
+        let profile = DeviceProfile::load_from(&path);
+        std::fs::remove_file(path).unwrap();
+        let profile = profile.unwrap();
+
+        assert_eq!(profile.system_name, "Test Headset");
+        assert_eq!(profile.refresh_rate, 90.0);
+        assert_eq!(
+            profile.environment_blend_modes(),
+            [
+                xr::EnvironmentBlendMode::ADDITIVE,
+                xr::EnvironmentBlendMode::OPAQUE
+            ]
+        );
+        assert!(profile.tracking.orientation && !profile.tracking.position);
+        assert_eq!(profile.vendor_id, DeviceProfile::default().vendor_id);
+    }
+
+    #[test]
+    fn json_profiles_are_loaded() {
+        let path = profile_file("override.json", r#"{ "refresh_rate": 120.0 }"#);
+        let profile = DeviceProfile::load_from(&path);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            profile.unwrap().display_period(),
+            Duration::from_secs_f64(1.0 / 120.0)
+        );
+    }
+
+    #[test]
+    fn invalid_profiles_are_rejected() {
+        assert_eq!(
+            load_error("unknown.toml", "refresh_rat = 90.0"),
+            xr::Result::ERROR_FILE_CONTENTS_INVALID
+        );
+        assert_eq!(
+            load_error("syntax.json", "{ \"refresh_rate\": }"),
+            xr::Result::ERROR_FILE_CONTENTS_INVALID
+        );
+        assert_eq!(
+            load_error("slow.toml", "refresh_rate = 1e-30"),
+            xr::Result::ERROR_FILE_CONTENTS_INVALID
+        );
+        assert_eq!(
+            load_error("blend.toml", "blend_modes = []"),
+            xr::Result::ERROR_FILE_CONTENTS_INVALID
+        );
+    }
+
+    #[test]
+    fn missing_profiles_cant_be_read() {
+        let path = std::env::temp_dir().join("openxr-device-simulator-missing-profile.toml");
+
+        assert!(matches!(
+            DeviceProfile::load_from(&path),
+            Err(Error::XrResult(xr::Result::ERROR_FILE_ACCESS_ERROR))
+        ));
+    }
+}
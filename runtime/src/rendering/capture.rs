@@ -0,0 +1,1018 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use crate::{
+    prelude::*,
+    rendering::swapchain::{
+        OffscreenImage, aspect_mask, find_memory_type_index, layout_access, submit_commands,
+    },
+    session::GraphicsBinding,
+};
+
+/// Directory every submitted frame is written to as PNG, capturing starts with the session.
+pub const CAPTURE_DIR_ENV: &str = "OPENXR_DEVICE_SIMULATOR_CAPTURE_DIR";
+
+const EYE_NAMES: &[&str] = &["left", "right"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Ppm,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Ppm => "ppm",
+        }
+    }
+
+    /// depth is written as grayscale, which netpbm keeps in PGM files
+    pub fn depth_extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Ppm => "pgm",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CaptureTarget {
+    pub(crate) dir: PathBuf,
+    pub(crate) format: ImageFormat,
+}
+
+type CaptureSender = mpsc::Sender<std::result::Result<Vec<PathBuf>, String>>;
+
+/// Which of the frames a session submits get written to disk.
+#[derive(Debug)]
+pub struct FrameCapture {
+    /// every frame is written here while set
+    pub(crate) continuous: Option<CaptureTarget>,
+    /// one-off captures of the next frame and where to send the written files
+    pending: Vec<(CaptureTarget, CaptureSender)>,
+    frame_index: u64,
+}
+
+impl FrameCapture {
+    pub fn new() -> Self {
+        let continuous = std::env::var_os(CAPTURE_DIR_ENV).map(|dir| CaptureTarget {
+            dir: dir.into(),
+            format: ImageFormat::Png,
+        });
+
+        Self {
+            continuous,
+            pending: Vec::new(),
+            frame_index: 0,
+        }
+    }
+
+    /// Captures the next submitted frame, the receiver gets the written files once it's done.
+    pub fn request(
+        &mut self,
+        target: CaptureTarget,
+    ) -> mpsc::Receiver<std::result::Result<Vec<PathBuf>, String>> {
+        let (sender, receiver) = mpsc::channel();
+        self.pending.push((target, sender));
+        receiver
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.continuous.is_some() || !self.pending.is_empty()
+    }
+
+    /// Writes the views of a frame, and the depth submitted with them, to every requested target.
+    pub fn write_frame(&mut self, views: &[CapturedView], depths: &[CapturedDepth]) {
+        let frame_index = self.frame_index;
+        self.frame_index += 1;
+
+        if let Some(target) = &self.continuous {
+            if let Err(err) = write_views(target, frame_index, views, depths) {
+                log::error!("could not capture frame {frame_index}: {err}");
+            }
+        }
+
+        for (target, sender) in self.pending.drain(..) {
+            let res =
+                write_views(&target, frame_index, views, depths).map_err(|err| err.to_string());
+            sender.send(res).ok();
+        }
+    }
+}
+
+/// The composed pixels one eye sees as RGBA8.
+#[derive(Debug)]
+pub struct CapturedView {
+    pub(crate) view: usize,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) rgba: Vec<u8>,
+}
+
+/// The depth buffer submitted with a projection view, values in [0, 1].
+#[derive(Debug)]
+pub struct CapturedDepth {
+    pub(crate) view: usize,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) depth: Vec<f32>,
+}
+
+fn write_views(
+    target: &CaptureTarget,
+    frame_index: u64,
+    views: &[CapturedView],
+    depths: &[CapturedDepth],
+) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(&target.dir)?;
+
+    let mut files = Vec::with_capacity(views.len() + depths.len());
+
+    for view in views {
+        let eye = EYE_NAMES.get(view.view).copied().unwrap_or("view");
+        let path = target.dir.join(format!(
+            "frame-{frame_index:06}-{eye}.{}",
+            target.format.extension()
+        ));
+
+        write_image(&path, target.format, view)?;
+        log::debug!("captured {}", path.display());
+        files.push(path);
+    }
+
+    for depth in depths {
+        let eye = EYE_NAMES.get(depth.view).copied().unwrap_or("view");
+        let path = target.dir.join(format!(
+            "frame-{frame_index:06}-{eye}-depth.{}",
+            target.format.depth_extension()
+        ));
+
+        write_depth_image(&path, target.format, depth)?;
+        log::debug!("captured {}", path.display());
+        files.push(path);
+    }
+
+    Ok(files)
+}
+
+fn write_image(path: &Path, format: ImageFormat, view: &CapturedView) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match format {
+        ImageFormat::Png => {
+            let mut encoder = png::Encoder::new(writer, view.width, view.height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+
+            encoder
+                .write_header()
+                .and_then(|mut writer| writer.write_image_data(&view.rgba))
+                .map_err(|err| Error::Generic(format!("PNG error: {err}")))?;
+        }
+        ImageFormat::Ppm => {
+            write!(writer, "P6\n{} {}\n255\n", view.width, view.height)?;
+            for pixel in view.rgba.chunks_exact(4) {
+                writer.write_all(&pixel[..3])?;
+            }
+            writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the depth as 16 bit grayscale, big endian as both formats store it.
+fn write_depth_image(path: &Path, format: ImageFormat, depth: &CapturedDepth) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    let samples = depth
+        .depth
+        .iter()
+        .flat_map(|value| ((value.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16).to_be_bytes())
+        .collect::<Vec<_>>();
+
+    match format {
+        ImageFormat::Png => {
+            let mut encoder = png::Encoder::new(writer, depth.width, depth.height);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Sixteen);
+
+            encoder
+                .write_header()
+                .and_then(|mut writer| writer.write_image_data(&samples))
+                .map_err(|err| Error::Generic(format!("PNG error: {err}")))?;
+        }
+        ImageFormat::Ppm => {
+            write!(writer, "P5\n{} {}\n65535\n", depth.width, depth.height)?;
+            writer.write_all(&samples)?;
+            writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// bytes per pixel of the formats that can be captured, only the depth of depth formats is read
+fn texel_size(format: ash::vk::Format) -> Option<usize> {
+    use ash::vk::Format;
+
+    match format {
+        Format::D16_UNORM => Some(2),
+        Format::D24_UNORM_S8_UINT | Format::D32_SFLOAT | Format::D32_SFLOAT_S8_UINT => Some(4),
+        Format::R8G8B8_UNORM
+        | Format::R8G8B8_SNORM
+        | Format::R8G8B8_UINT
+        | Format::R8G8B8_SINT
+        | Format::R8G8B8_SRGB => Some(3),
+        Format::R8G8B8A8_UNORM
+        | Format::R8G8B8A8_SNORM
+        | Format::R8G8B8A8_UINT
+        | Format::R8G8B8A8_SINT
+        | Format::R8G8B8A8_SRGB
+        | Format::B8G8R8A8_UNORM
+        | Format::B8G8R8A8_SRGB
+        | Format::A2B10G10R10_UNORM_PACK32 => Some(4),
+        Format::R16G16B16A16_SFLOAT => Some(8),
+        _ => None,
+    }
+}
+
+fn is_integer_format(format: ash::vk::Format) -> bool {
+    use ash::vk::Format;
+
+    matches!(
+        format,
+        Format::R8G8B8_UINT | Format::R8G8B8_SINT | Format::R8G8B8A8_UINT | Format::R8G8B8A8_SINT
+    )
+}
+
+/// Converts texels to RGBA8, signed formats are remapped from [-1, 1] to [0, 1].
+fn to_rgba8(format: ash::vk::Format, texels: &[u8]) -> Vec<u8> {
+    use ash::vk::Format;
+
+    let texel_size = texel_size(format).unwrap_or(4);
+    let signed = matches!(
+        format,
+        Format::R8G8B8_SNORM | Format::R8G8B8_SINT | Format::R8G8B8A8_SNORM | Format::R8G8B8A8_SINT
+    );
+
+    let mut rgba = Vec::with_capacity(texels.len() / texel_size * 4);
+
+    match format {
+        Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => {
+            for texel in texels.chunks_exact(texel_size) {
+                rgba.extend([texel[2], texel[1], texel[0], texel[3]]);
+            }
+            return rgba;
+        }
+        Format::A2B10G10R10_UNORM_PACK32 => {
+            for texel in texels.chunks_exact(texel_size) {
+                let value = u32::from_ne_bytes([texel[0], texel[1], texel[2], texel[3]]);
+                let channel = |shift: u32| ((value >> shift) & 0x3ff) as u16 >> 2;
+                let alpha = (value >> 30) as u16 * 0x55;
+                rgba.extend([channel(0), channel(10), channel(20), alpha].map(|value| value as u8));
+            }
+            return rgba;
+        }
+        // float formats are linear, captures are sRGB encoded like the 8 bit sRGB formats
+        Format::R16G16B16A16_SFLOAT => {
+            for texel in texels.chunks_exact(texel_size) {
+                let channel = |index: usize| {
+                    f16_to_f32(u16::from_ne_bytes([texel[index * 2], texel[index * 2 + 1]]))
+                };
+                let alpha = (channel(3).clamp(0.0, 1.0) * 255.0).round() as u8;
+                rgba.extend([
+                    linear_to_srgb8(channel(0)),
+                    linear_to_srgb8(channel(1)),
+                    linear_to_srgb8(channel(2)),
+                    alpha,
+                ]);
+            }
+            return rgba;
+        }
+        _ => (),
+    }
+
+    for texel in texels.chunks_exact(texel_size) {
+        for channel in 0..4 {
+            let value = match texel.get(channel) {
+                Some(&value) if signed => (value as i8 as i16 + 128) as u8,
+                Some(&value) => value,
+                None => u8::MAX,
+            };
+            rgba.push(value);
+        }
+    }
+
+    rgba
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn linear_to_srgb8(value: f32) -> u8 {
+    let value = if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(0.0, 1.0)
+    };
+
+    let encoded = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+
+    (encoded * 255.0).round() as u8
+}
+
+/// Converts the depth texels to values in [0, 1].
+fn to_depth(format: ash::vk::Format, texels: &[u8]) -> Vec<f32> {
+    use ash::vk::Format;
+
+    match format {
+        Format::D16_UNORM => texels
+            .chunks_exact(2)
+            .map(|texel| u16::from_ne_bytes([texel[0], texel[1]]) as f32 / u16::MAX as f32)
+            .collect(),
+        // the depth is in the low 24 bits
+        Format::D24_UNORM_S8_UINT => texels
+            .chunks_exact(4)
+            .map(|texel| {
+                let value = u32::from_ne_bytes([texel[0], texel[1], texel[2], texel[3]]);
+                (value & 0x00ff_ffff) as f32 / 0x00ff_ffff as f32
+            })
+            .collect(),
+        _ => texels
+            .chunks_exact(4)
+            .map(|texel| f32::from_ne_bytes([texel[0], texel[1], texel[2], texel[3]]))
+            .collect(),
+    }
+}
+
+/// Reads back a rect of a color swapchain image as RGBA8.
+pub fn read_image(
+    graphics_binding: &GraphicsBinding,
+    image: &OffscreenImage,
+    rect: &xr::Rect2Di,
+    array_index: u32,
+) -> Result<(u32, u32, Vec<u8>)> {
+    if aspect_mask(image.format) != ash::vk::ImageAspectFlags::COLOR {
+        return Err(format!("{:?} is not a color format", image.format)
+            .as_str()
+            .into());
+    }
+
+    // vkCmdResolveImage only takes float and normalized formats
+    if image.samples != ash::vk::SampleCountFlags::TYPE_1 && is_integer_format(image.format) {
+        return Err("can't capture multisampled integer images".into());
+    }
+
+    let (width, height, texels) = read_texels(graphics_binding, image, rect, array_index)?;
+    Ok((width, height, to_rgba8(image.format, &texels)))
+}
+
+/// Reads back the depth of a rect of a depth swapchain image.
+pub fn read_depth_image(
+    graphics_binding: &GraphicsBinding,
+    image: &OffscreenImage,
+    rect: &xr::Rect2Di,
+    array_index: u32,
+) -> Result<(u32, u32, Vec<f32>)> {
+    if !aspect_mask(image.format).contains(ash::vk::ImageAspectFlags::DEPTH) {
+        return Err(format!("{:?} is not a depth format", image.format)
+            .as_str()
+            .into());
+    }
+
+    if image.samples != ash::vk::SampleCountFlags::TYPE_1 {
+        return Err("can't capture multisampled depth images".into());
+    }
+
+    let (width, height, texels) = read_texels(graphics_binding, image, rect, array_index)?;
+    Ok((width, height, to_depth(image.format, &texels)))
+}
+
+/// Reads back a rect of a swapchain image through a host visible buffer. The copy is submitted to
+/// the app's queue after the rendering it released the image with, the image is expected in the
+/// layout it was handed out in and is returned to it.
+fn read_texels(
+    graphics_binding: &GraphicsBinding,
+    image: &OffscreenImage,
+    rect: &xr::Rect2Di,
+    array_index: u32,
+) -> Result<(u32, u32, Vec<u8>)> {
+    let Some(texel_size) = texel_size(image.format) else {
+        return Err(format!("can't capture images of format {:?}", image.format)
+            .as_str()
+            .into());
+    };
+
+    if rect.offset.x < 0
+        || rect.offset.y < 0
+        || rect.extent.width <= 0
+        || rect.extent.height <= 0
+        || (rect.offset.x + rect.extent.width) as u32 > image.width
+        || (rect.offset.y + rect.extent.height) as u32 > image.height
+    {
+        return Err(format!("sub image {rect:?} is out of bounds")
+            .as_str()
+            .into());
+    }
+
+    let (width, height) = (rect.extent.width as u32, rect.extent.height as u32);
+    let size = width as u64 * height as u64 * texel_size as u64;
+
+    let device = graphics_binding.device.as_ref();
+
+    let memory_properties = unsafe {
+        graphics_binding
+            .instance
+            .get_physical_device_memory_properties(graphics_binding.physical_device)
+    };
+
+    let buffer = unsafe {
+        device.create_buffer(
+            &ash::vk::BufferCreateInfo {
+                size,
+                usage: ash::vk::BufferUsageFlags::TRANSFER_DST,
+                sharing_mode: ash::vk::SharingMode::EXCLUSIVE,
+                ..Default::default()
+            },
+            None,
+        )?
+    };
+
+    let mem_req = unsafe { device.get_buffer_memory_requirements(buffer) };
+    let Some(mem_type_index) = find_memory_type_index(
+        &mem_req,
+        &memory_properties,
+        ash::vk::MemoryPropertyFlags::HOST_VISIBLE | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
+    ) else {
+        unsafe { device.destroy_buffer(buffer, None) };
+        return Err("no host visible memory for the capture buffer".into());
+    };
+
+    let res = unsafe {
+        device.allocate_memory(
+            &ash::vk::MemoryAllocateInfo {
+                allocation_size: mem_req.size,
+                memory_type_index: mem_type_index,
+                ..Default::default()
+            },
+            None,
+        )
+    };
+
+    let memory = match res {
+        Ok(memory) => memory,
+        Err(err) => {
+            unsafe { device.destroy_buffer(buffer, None) };
+            return Err(err.into());
+        }
+    };
+
+    let res = unsafe { device.bind_buffer_memory(buffer, memory, 0) }
+        .map_err(Error::from)
+        .and_then(|_| {
+            if image.samples == ash::vk::SampleCountFlags::TYPE_1 {
+                return copy_image_to_buffer(
+                    graphics_binding,
+                    image,
+                    rect,
+                    array_index,
+                    None,
+                    buffer,
+                );
+            }
+
+            let resolve_image = ResolveImage::new(
+                graphics_binding,
+                &memory_properties,
+                image.format,
+                width,
+                height,
+            )?;
+            let res = copy_image_to_buffer(
+                graphics_binding,
+                image,
+                rect,
+                array_index,
+                Some(resolve_image.image),
+                buffer,
+            );
+            resolve_image.cleanup(device);
+            res
+        })
+        .and_then(|_| unsafe {
+            let data = device.map_memory(memory, 0, size, ash::vk::MemoryMapFlags::empty())?;
+            let texels = std::slice::from_raw_parts(data as *const u8, size as usize).to_vec();
+            device.unmap_memory(memory);
+            Ok(texels)
+        });
+
+    unsafe {
+        device.free_memory(memory, None);
+        device.destroy_buffer(buffer, None);
+    }
+
+    Ok((width, height, res?))
+}
+
+/// Single sampled image a multisampled one is resolved into before being copied.
+struct ResolveImage {
+    image: ash::vk::Image,
+    memory: ash::vk::DeviceMemory,
+}
+
+impl ResolveImage {
+    fn new(
+        graphics_binding: &GraphicsBinding,
+        memory_properties: &ash::vk::PhysicalDeviceMemoryProperties,
+        format: ash::vk::Format,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let device = graphics_binding.device.as_ref();
+
+        let image = unsafe {
+            device.create_image(
+                &ash::vk::ImageCreateInfo {
+                    image_type: ash::vk::ImageType::TYPE_2D,
+                    format,
+                    extent: ash::vk::Extent3D {
+                        width,
+                        height,
+                        depth: 1,
+                    },
+                    mip_levels: 1,
+                    array_layers: 1,
+                    samples: ash::vk::SampleCountFlags::TYPE_1,
+                    usage: ash::vk::ImageUsageFlags::TRANSFER_DST
+                        | ash::vk::ImageUsageFlags::TRANSFER_SRC,
+                    ..Default::default()
+                },
+                None,
+            )?
+        };
+
+        let mem_req = unsafe { device.get_image_memory_requirements(image) };
+        let Some(mem_type_index) = find_memory_type_index(
+            &mem_req,
+            memory_properties,
+            ash::vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        ) else {
+            unsafe { device.destroy_image(image, None) };
+            return Err("no device local memory for the resolve image".into());
+        };
+
+        let res = unsafe {
+            device.allocate_memory(
+                &ash::vk::MemoryAllocateInfo {
+                    allocation_size: mem_req.size,
+                    memory_type_index: mem_type_index,
+                    ..Default::default()
+                },
+                None,
+            )
+        };
+
+        let memory = match res {
+            Ok(memory) => memory,
+            Err(err) => {
+                unsafe { device.destroy_image(image, None) };
+                return Err(err.into());
+            }
+        };
+
+        let resolve_image = Self { image, memory };
+
+        if let Err(err) = unsafe { device.bind_image_memory(image, memory, 0) } {
+            resolve_image.cleanup(device);
+            return Err(err.into());
+        }
+
+        Ok(resolve_image)
+    }
+
+    fn cleanup(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+fn copy_image_to_buffer(
+    graphics_binding: &GraphicsBinding,
+    image: &OffscreenImage,
+    rect: &xr::Rect2Di,
+    array_index: u32,
+    resolve_image: Option<ash::vk::Image>,
+    buffer: ash::vk::Buffer,
+) -> Result<()> {
+    submit_commands(graphics_binding, |device, command_buffer| {
+        // the image goes back to the layout the app left it in, only the depth of depth images is
+        // copied
+        let aspect_mask = aspect_mask(image.format);
+        let attachment_layout = image.layout;
+        let (attachment_access, attachment_stage) = layout_access(attachment_layout);
+        let copy_aspect = if aspect_mask == ash::vk::ImageAspectFlags::COLOR {
+            ash::vk::ImageAspectFlags::COLOR
+        } else {
+            ash::vk::ImageAspectFlags::DEPTH
+        };
+
+        let subresource_range = ash::vk::ImageSubresourceRange {
+            aspect_mask,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: array_index,
+            layer_count: 1,
+        };
+
+        let to_transfer = ash::vk::ImageMemoryBarrier {
+            src_access_mask: attachment_access,
+            dst_access_mask: ash::vk::AccessFlags::TRANSFER_READ,
+            old_layout: attachment_layout,
+            new_layout: ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            src_queue_family_index: ash::vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: ash::vk::QUEUE_FAMILY_IGNORED,
+            image: image.image,
+            subresource_range,
+            ..Default::default()
+        };
+
+        let to_attachment = ash::vk::ImageMemoryBarrier {
+            src_access_mask: ash::vk::AccessFlags::TRANSFER_READ,
+            dst_access_mask: attachment_access,
+            old_layout: ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            new_layout: attachment_layout,
+            ..to_transfer
+        };
+
+        let to_host = ash::vk::BufferMemoryBarrier {
+            src_access_mask: ash::vk::AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: ash::vk::AccessFlags::HOST_READ,
+            src_queue_family_index: ash::vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: ash::vk::QUEUE_FAMILY_IGNORED,
+            buffer,
+            offset: 0,
+            size: ash::vk::WHOLE_SIZE,
+            ..Default::default()
+        };
+
+        let subresource = ash::vk::ImageSubresourceLayers {
+            aspect_mask: copy_aspect,
+            mip_level: 0,
+            base_array_layer: array_index,
+            layer_count: 1,
+        };
+        let offset = ash::vk::Offset3D {
+            x: rect.offset.x,
+            y: rect.offset.y,
+            z: 0,
+        };
+        let extent = ash::vk::Extent3D {
+            width: rect.extent.width as u32,
+            height: rect.extent.height as u32,
+            depth: 1,
+        };
+
+        // a resolved copy of the rect sits at the origin of its first layer
+        let (copy_image, copy_subresource, copy_offset) = match resolve_image {
+            Some(resolve_image) => (
+                resolve_image,
+                ash::vk::ImageSubresourceLayers {
+                    base_array_layer: 0,
+                    ..subresource
+                },
+                ash::vk::Offset3D::default(),
+            ),
+            None => (image.image, subresource, offset),
+        };
+
+        let region = ash::vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: copy_subresource,
+            image_offset: copy_offset,
+            image_extent: extent,
+        };
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                attachment_stage,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_transfer],
+            );
+
+            if let Some(resolve_image) = resolve_image {
+                let resolve_range = ash::vk::ImageSubresourceRange {
+                    base_array_layer: 0,
+                    ..subresource_range
+                };
+
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    ash::vk::PipelineStageFlags::TOP_OF_PIPE,
+                    ash::vk::PipelineStageFlags::TRANSFER,
+                    ash::vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[ash::vk::ImageMemoryBarrier {
+                        src_access_mask: ash::vk::AccessFlags::empty(),
+                        dst_access_mask: ash::vk::AccessFlags::TRANSFER_WRITE,
+                        old_layout: ash::vk::ImageLayout::UNDEFINED,
+                        new_layout: ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        image: resolve_image,
+                        subresource_range: resolve_range,
+                        ..to_transfer
+                    }],
+                );
+
+                device.cmd_resolve_image(
+                    command_buffer,
+                    image.image,
+                    ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    resolve_image,
+                    ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[ash::vk::ImageResolve {
+                        src_subresource: subresource,
+                        src_offset: offset,
+                        dst_subresource: copy_subresource,
+                        dst_offset: copy_offset,
+                        extent,
+                    }],
+                );
+
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    ash::vk::PipelineStageFlags::TRANSFER,
+                    ash::vk::PipelineStageFlags::TRANSFER,
+                    ash::vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[ash::vk::ImageMemoryBarrier {
+                        src_access_mask: ash::vk::AccessFlags::TRANSFER_WRITE,
+                        dst_access_mask: ash::vk::AccessFlags::TRANSFER_READ,
+                        old_layout: ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        new_layout: ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        image: resolve_image,
+                        subresource_range: resolve_range,
+                        ..to_transfer
+                    }],
+                );
+            }
+
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                copy_image,
+                ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                buffer,
+                &[region],
+            );
+
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::TRANSFER,
+                attachment_stage | ash::vk::PipelineStageFlags::HOST,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[to_host],
+                &[to_attachment],
+            );
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Format;
+
+    /// an empty directory only the calling test writes to
+    fn capture_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "openxr-device-simulator-capture-{}-{name}",
+            std::process::id()
+        ));
+        std::fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    type CaptureReceiver = mpsc::Receiver<std::result::Result<Vec<PathBuf>, String>>;
+
+    fn capture(name: &str, format: ImageFormat) -> (FrameCapture, CaptureReceiver, PathBuf) {
+        let dir = capture_dir(name);
+        let mut capture = FrameCapture {
+            continuous: None,
+            pending: Vec::new(),
+            frame_index: 0,
+        };
+        let receiver = capture.request(CaptureTarget {
+            dir: dir.clone(),
+            format,
+        });
+        (capture, receiver, dir)
+    }
+
+    fn view(view: usize) -> CapturedView {
+        CapturedView {
+            view,
+            width: 2,
+            height: 1,
+            rgba: vec![255, 0, 0, 255, 0, 128, 255, 0],
+        }
+    }
+
+    #[test]
+    fn requested_frames_are_written_once() {
+        let (mut capture, receiver, dir) = capture("once", ImageFormat::Ppm);
+        assert!(capture.is_requested());
+
+        capture.write_frame(&[view(0), view(1)], &[]);
+        capture.write_frame(&[view(0), view(1)], &[]);
+
+        let files = receiver.recv().unwrap().unwrap();
+        assert_eq!(
+            files,
+            [
+                dir.join("frame-000000-left.ppm"),
+                dir.join("frame-000000-right.ppm")
+            ]
+        );
+        assert!(!capture.is_requested());
+        assert!(!dir.join("frame-000001-left.ppm").exists());
+
+        // PPM has no alpha
+        assert_eq!(
+            std::fs::read(&files[0]).unwrap(),
+            [b"P6\n2 1\n255\n".as_slice(), &[255, 0, 0, 0, 128, 255]].concat()
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pngs_keep_the_pixels() {
+        let (mut capture, receiver, dir) = capture("png", ImageFormat::Png);
+
+        capture.write_frame(&[view(0)], &[]);
+
+        let files = receiver.recv().unwrap().unwrap();
+        let mut reader = png::Decoder::new(File::open(&files[0]).unwrap())
+            .read_info()
+            .unwrap();
+        let mut rgba = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut rgba).unwrap();
+
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert_eq!(rgba, view(0).rgba);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn depth_is_written_as_16_bit_grayscale() {
+        let (mut capture, receiver, dir) = capture("depth", ImageFormat::Ppm);
+
+        capture.write_frame(
+            &[view(1)],
+            &[CapturedDepth {
+                view: 1,
+                width: 3,
+                height: 1,
+                depth: vec![0.0, 1.0, 2.0],
+            }],
+        );
+
+        let files = receiver.recv().unwrap().unwrap();
+        assert_eq!(
+            files,
+            [
+                dir.join("frame-000000-right.ppm"),
+                dir.join("frame-000000-right-depth.pgm")
+            ]
+        );
+        assert_eq!(
+            std::fs::read(&files[1]).unwrap(),
+            [b"P5\n3 1\n65535\n".as_slice(), &[0, 0, 255, 255, 255, 255]].concat()
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn half_floats_are_widened() {
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3800), 0.5);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(f16_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn linear_values_are_srgb_encoded() {
+        assert_eq!(linear_to_srgb8(0.0), 0);
+        assert_eq!(linear_to_srgb8(1.0), 255);
+        assert_eq!(linear_to_srgb8(0.5), 188);
+        assert_eq!(linear_to_srgb8(-1.0), 0);
+        assert_eq!(linear_to_srgb8(2.0), 255);
+        assert_eq!(linear_to_srgb8(f32::NAN), 0);
+    }
+
+    #[test]
+    fn bgra_is_swizzled() {
+        let texels = [1, 2, 3, 4, 5, 6, 7, 8];
+
+        assert_eq!(
+            to_rgba8(Format::B8G8R8A8_UNORM, &texels),
+            [3, 2, 1, 4, 7, 6, 5, 8]
+        );
+        assert_eq!(to_rgba8(Format::R8G8B8A8_UNORM, &texels), texels);
+    }
+
+    #[test]
+    fn packed_10_bit_channels_are_narrowed() {
+        let pack = |r: u32, g: u32, b: u32, a: u32| (r | g << 10 | b << 20 | a << 30).to_ne_bytes();
+        let texels = [pack(0x3ff, 0x200, 0, 3), pack(0, 0x3, 0x4, 1)].concat();
+
+        assert_eq!(
+            to_rgba8(Format::A2B10G10R10_UNORM_PACK32, &texels),
+            [255, 128, 0, 255, 0, 0, 1, 85]
+        );
+    }
+
+    #[test]
+    fn missing_alpha_is_opaque_and_signed_values_are_remapped() {
+        assert_eq!(
+            to_rgba8(Format::R8G8B8_UNORM, &[10, 20, 30]),
+            [10, 20, 30, 255]
+        );
+        assert_eq!(
+            to_rgba8(Format::R8G8B8A8_SNORM, &[0x80, 0xff, 0, 0x7f]),
+            [0, 127, 128, 255]
+        );
+    }
+
+    #[test]
+    fn half_float_colors_are_srgb_encoded() {
+        let texels = [0x3c00u16, 0x0000, 0x3800, 0x3800]
+            .map(u16::to_ne_bytes)
+            .concat();
+
+        assert_eq!(
+            to_rgba8(Format::R16G16B16A16_SFLOAT, &texels),
+            [255, 0, 188, 128]
+        );
+    }
+
+    #[test]
+    fn depth_is_normalized() {
+        assert_eq!(
+            to_depth(
+                Format::D16_UNORM,
+                &[u16::MAX, 0].map(u16::to_ne_bytes).concat()
+            ),
+            [1.0, 0.0]
+        );
+        assert_eq!(
+            to_depth(Format::D24_UNORM_S8_UINT, &0xff80_0000u32.to_ne_bytes()),
+            [0x0080_0000 as f32 / 0x00ff_ffff as f32]
+        );
+        assert_eq!(to_depth(Format::D32_SFLOAT, &0.25f32.to_ne_bytes()), [0.25]);
+        assert_eq!(texel_size(Format::D32_SFLOAT_S8_UINT), Some(4));
+        assert_eq!(texel_size(Format::R16G16B16A16_SFLOAT), Some(8));
+        assert_eq!(texel_size(Format::BC1_RGB_UNORM_BLOCK), None);
+    }
+}
@@ -0,0 +1,648 @@
+use std::collections::HashMap;
+
+use crate::{
+    device::with_device,
+    math::{pose_inverse, pose_multiply, quat_rotate, vec3_add, vec3_dot},
+    prelude::*,
+    rendering::{
+        capture::{CapturedDepth, CapturedView, read_depth_image, read_image},
+        layer::{FrameLayer, LayerImage, LayerShape},
+        swapchain::{OffscreenImage, with_swapchain},
+    },
+    session::SimulatedSession,
+    spaces::SpaceSnapshot,
+};
+
+/// RGBA8 pixels read back from a layer image.
+struct LayerPixels {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl LayerPixels {
+    /// nearest texel at normalized coordinates, as RGBA in [0, 1]
+    fn sample(&self, u: f32, v: f32) -> [f32; 4] {
+        let x = ((u * self.width as f32) as u32).min(self.width - 1) as usize;
+        let y = ((v * self.height as f32) as u32).min(self.height - 1) as usize;
+        let offset = (y * self.width as usize + x) * 4;
+
+        let texel = &self.rgba[offset..offset + 4];
+        [texel[0], texel[1], texel[2], texel[3]].map(|value| value as f32 / 255.0)
+    }
+}
+
+/// A layer with its pixels and the pose of its space in the tracking origin.
+struct ComposedLayer<'a> {
+    layer: &'a FrameLayer,
+    space_pose: xr::Posef,
+    images: Vec<LayerPixels>,
+}
+
+/// The eye the frame is composed for.
+struct EyeView {
+    pose: xr::Posef,
+    fov: xr::Fovf,
+    width: u32,
+    height: u32,
+}
+
+/// Renders the layers of a frame into one image per eye, the way a headset would show them.
+///
+/// The eyes are the views of the first projection layer, so the app's own rendering maps to the
+/// output texel by texel, or the current eyes of the simulated head when there is none. Every
+/// other layer is ray cast from the eye and sampled at the nearest texel.
+pub fn compose(
+    session: &SimulatedSession,
+    layers: &[FrameLayer],
+    spaces: &HashMap<u64, SpaceSnapshot>,
+) -> Vec<CapturedView> {
+    let mut composed_layers = Vec::with_capacity(layers.len());
+
+    for layer in layers {
+        let Some(space_state) = spaces
+            .get(&layer.space)
+            .and_then(|space| space.state(session))
+        else {
+            continue;
+        };
+
+        let images = layer
+            .images
+            .iter()
+            .map(|image| read_layer_image(session, image))
+            .collect::<Result<Vec<_>>>();
+
+        match images {
+            Ok(images) => composed_layers.push(ComposedLayer {
+                layer,
+                space_pose: space_state.pose,
+                images,
+            }),
+            Err(err) => log::error!("[{}] could not read layer images: {err}", session.id),
+        }
+    }
+
+    (0..2)
+        .map(|eye| {
+            let eye_view = eye_view(session, &composed_layers, eye);
+            compose_eye(&composed_layers, eye, &eye_view)
+        })
+        .collect()
+}
+
+/// Reads the depth submitted with the views of the first projection layer that has any.
+pub fn read_depths(session: &SimulatedSession, layers: &[FrameLayer]) -> Vec<CapturedDepth> {
+    let Some(layer) = layers
+        .iter()
+        .find(|layer| layer.depths.iter().any(Option::is_some))
+    else {
+        return Vec::new();
+    };
+
+    let mut depths = Vec::with_capacity(layer.depths.len());
+
+    for (view, depth) in layer.depths.iter().enumerate() {
+        let Some(depth) = depth else {
+            continue;
+        };
+
+        let res = with_swapchain(depth.image.swapchain, |swapchain| {
+            let Some(offscreen_image) = swapchain.released_image() else {
+                return Err(xr::Result::ERROR_CALL_ORDER_INVALID.into());
+            };
+
+            read_depth_image(
+                &session.graphics_binding,
+                offscreen_image,
+                &image_rect(&depth.image, offscreen_image),
+                depth.image.array_index,
+            )
+        });
+
+        // stretched over the range the app uses so it's visible
+        let range = depth.max_depth - depth.min_depth;
+
+        match res {
+            Ok((width, height, values)) => depths.push(CapturedDepth {
+                view,
+                width,
+                height,
+                depth: values
+                    .iter()
+                    .map(|value| (value - depth.min_depth) / range)
+                    .collect(),
+            }),
+            Err(err) => log::error!("[{}] could not read depth: {err}", session.id),
+        }
+    }
+
+    depths
+}
+
+/// the sub image rect of a layer image, the whole image when it has none
+fn image_rect(image: &LayerImage, offscreen_image: &OffscreenImage) -> xr::Rect2Di {
+    image.rect.unwrap_or(xr::Rect2Di {
+        offset: xr::Offset2Di { x: 0, y: 0 },
+        extent: xr::Extent2Di {
+            width: offscreen_image.width as i32,
+            height: offscreen_image.height as i32,
+        },
+    })
+}
+
+fn read_layer_image(session: &SimulatedSession, image: &LayerImage) -> Result<LayerPixels> {
+    let (width, height, rgba) = with_swapchain(image.swapchain, |swapchain| {
+        let Some(offscreen_image) = swapchain.released_image() else {
+            return Err(xr::Result::ERROR_CALL_ORDER_INVALID.into());
+        };
+
+        read_image(
+            &session.graphics_binding,
+            offscreen_image,
+            &image_rect(image, offscreen_image),
+            image.array_index,
+        )
+    })?;
+
+    Ok(LayerPixels {
+        width,
+        height,
+        rgba,
+    })
+}
+
+fn eye_view(session: &SimulatedSession, layers: &[ComposedLayer], eye: usize) -> EyeView {
+    for composed in layers {
+        if let LayerShape::Projection { views } = &composed.layer.shape {
+            let (pose, fov) = &views[eye];
+            let image = &composed.images[eye];
+
+            return EyeView {
+                pose: pose_multiply(&composed.space_pose, pose),
+                fov: *fov,
+                width: image.width,
+                height: image.height,
+            };
+        }
+    }
+
+    let view_profile = &session.profile.views[eye];
+    let head = with_device(|device| device.head.clone());
+
+    EyeView {
+        pose: head.eye_pose(eye),
+        fov: head.fov[eye],
+        width: view_profile.recommended_width,
+        height: view_profile.recommended_height,
+    }
+}
+
+fn compose_eye(layers: &[ComposedLayer], eye: usize, eye_view: &EyeView) -> CapturedView {
+    // the eye position and ray directions are moved into each layer's own frame
+    let locals = layers
+        .iter()
+        .filter(|composed| composed.layer.is_visible(eye))
+        .map(|composed| {
+            let local_pose = match &composed.layer.shape {
+                LayerShape::Projection { views } => views[eye].0,
+                LayerShape::Quad { pose, .. }
+                | LayerShape::Cylinder { pose, .. }
+                | LayerShape::Equirect { pose, .. } => *pose,
+                LayerShape::Cube { orientation } => xr::Posef {
+                    orientation: *orientation,
+                    position: Default::default(),
+                },
+            };
+            let to_local = pose_inverse(&pose_multiply(&composed.space_pose, &local_pose));
+            let origin = vec3_add(
+                &to_local.position,
+                &quat_rotate(&to_local.orientation, &eye_view.pose.position),
+            );
+
+            (composed, to_local.orientation, origin)
+        })
+        .collect::<Vec<_>>();
+
+    let fov = &eye_view.fov;
+    let (tan_left, tan_right) = (fov.angle_left.tan(), fov.angle_right.tan());
+    let (tan_up, tan_down) = (fov.angle_up.tan(), fov.angle_down.tan());
+
+    let mut rgba = Vec::with_capacity(eye_view.width as usize * eye_view.height as usize * 4);
+
+    for y in 0..eye_view.height {
+        let v = (y as f32 + 0.5) / eye_view.height as f32;
+
+        for x in 0..eye_view.width {
+            let u = (x as f32 + 0.5) / eye_view.width as f32;
+            let view_dir = xr::Vector3f {
+                x: tan_left + u * (tan_right - tan_left),
+                y: tan_up - v * (tan_up - tan_down),
+                z: -1.0,
+            };
+            let dir = quat_rotate(&eye_view.pose.orientation, &view_dir);
+
+            let mut color = [0.0; 4];
+
+            for (composed, orientation, origin) in &locals {
+                let local_dir = quat_rotate(orientation, &dir);
+
+                if let Some((image, u, v)) = hit(&composed.layer.shape, eye, origin, &local_dir) {
+                    blend(
+                        &mut color,
+                        composed.images[image].sample(u, v),
+                        composed.layer.flags,
+                    );
+                }
+            }
+
+            rgba.extend(color.map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8));
+        }
+    }
+
+    CapturedView {
+        view: eye,
+        width: eye_view.width,
+        height: eye_view.height,
+        rgba,
+    }
+}
+
+/// Where a ray from `origin` along `dir`, both in the layer's frame, meets the layer: the index of
+/// the image and the normalized coordinates in it.
+fn hit(
+    shape: &LayerShape,
+    eye: usize,
+    origin: &xr::Vector3f,
+    dir: &xr::Vector3f,
+) -> Option<(usize, f32, f32)> {
+    let in_range = |u: f32, v: f32| (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v);
+
+    match shape {
+        LayerShape::Projection { views } => {
+            if dir.z >= 0.0 {
+                return None;
+            }
+
+            let fov = &views[eye].1;
+            let (tan_left, tan_right) = (fov.angle_left.tan(), fov.angle_right.tan());
+            let (tan_up, tan_down) = (fov.angle_up.tan(), fov.angle_down.tan());
+
+            let u = (dir.x / -dir.z - tan_left) / (tan_right - tan_left);
+            let v = (tan_up - dir.y / -dir.z) / (tan_up - tan_down);
+
+            in_range(u, v).then_some((eye, u, v))
+        }
+        LayerShape::Quad { size, .. } => {
+            if dir.z.abs() <= f32::EPSILON || size.width <= 0.0 || size.height <= 0.0 {
+                return None;
+            }
+
+            let t = -origin.z / dir.z;
+            if t <= 0.0 {
+                return None;
+            }
+
+            let u = (origin.x + t * dir.x) / size.width + 0.5;
+            let v = 0.5 - (origin.y + t * dir.y) / size.height;
+
+            in_range(u, v).then_some((0, u, v))
+        }
+        LayerShape::Cylinder {
+            radius,
+            central_angle,
+            aspect_ratio,
+            ..
+        } => {
+            if *central_angle <= 0.0 {
+                return None;
+            }
+
+            let (origin, radius) = infinite_at_unit(origin, *radius);
+            let height = radius * central_angle / aspect_ratio;
+
+            // only the horizontal part of the ray moves it towards the cylinder's wall
+            let a = dir.x * dir.x + dir.z * dir.z;
+            let b = 2.0 * (origin.x * dir.x + origin.z * dir.z);
+            let c = origin.x * origin.x + origin.z * origin.z - radius * radius;
+
+            intersections(a, b, c).into_iter().flatten().find_map(|t| {
+                let point = point_at(&origin, dir, t);
+                let u = point.x.atan2(-point.z) / central_angle + 0.5;
+                let v = 0.5 - point.y / height;
+
+                in_range(u, v).then_some((0, u, v))
+            })
+        }
+        LayerShape::Equirect {
+            radius,
+            central_horizontal_angle,
+            upper_vertical_angle,
+            lower_vertical_angle,
+            ..
+        } => {
+            if *central_horizontal_angle <= 0.0 || upper_vertical_angle <= lower_vertical_angle {
+                return None;
+            }
+
+            let (origin, radius) = infinite_at_unit(origin, *radius);
+
+            let a = vec3_dot(dir, dir);
+            let b = 2.0 * vec3_dot(&origin, dir);
+            let c = vec3_dot(&origin, &origin) - radius * radius;
+
+            intersections(a, b, c).into_iter().flatten().find_map(|t| {
+                let point = point_at(&origin, dir, t);
+                let longitude = point.x.atan2(-point.z);
+                let latitude = point.y.atan2(point.x.hypot(point.z));
+
+                let u = longitude / central_horizontal_angle + 0.5;
+                let v = (upper_vertical_angle - latitude)
+                    / (upper_vertical_angle - lower_vertical_angle);
+
+                in_range(u, v).then_some((0, u, v))
+            })
+        }
+        LayerShape::Cube { .. } => {
+            let (x, y, z) = (dir.x, dir.y, dir.z);
+            let (abs_x, abs_y, abs_z) = (x.abs(), y.abs(), z.abs());
+
+            // face selection and orientation follow Vulkan's cube map sampling
+            let (face, s, t, major) = if abs_x >= abs_y && abs_x >= abs_z {
+                if x > 0.0 {
+                    (0, -z, -y, abs_x)
+                } else {
+                    (1, z, -y, abs_x)
+                }
+            } else if abs_y >= abs_z {
+                if y > 0.0 {
+                    (2, x, z, abs_y)
+                } else {
+                    (3, x, -z, abs_y)
+                }
+            } else if z > 0.0 {
+                (4, x, -y, abs_z)
+            } else {
+                (5, -x, -y, abs_z)
+            };
+
+            if major <= f32::EPSILON {
+                return None;
+            }
+
+            Some((face, (s / major + 1.0) / 2.0, (t / major + 1.0) / 2.0))
+        }
+    }
+}
+
+/// A radius of 0 puts the layer at infinity, which is the same as a unit one around the eye.
+fn infinite_at_unit(origin: &xr::Vector3f, radius: f32) -> (xr::Vector3f, f32) {
+    if radius == 0.0 {
+        (xr::Vector3f::default(), 1.0)
+    } else {
+        (*origin, radius)
+    }
+}
+
+/// positive solutions of `a·t² + b·t + c = 0`, nearest first
+fn intersections(a: f32, b: f32, c: f32) -> [Option<f32>; 2] {
+    if a <= f32::EPSILON {
+        return [None, None];
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return [None, None];
+    }
+
+    let root = discriminant.sqrt();
+    [(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)].map(|t| (t > 0.0).then_some(t))
+}
+
+fn point_at(origin: &xr::Vector3f, dir: &xr::Vector3f, t: f32) -> xr::Vector3f {
+    xr::Vector3f {
+        x: origin.x + t * dir.x,
+        y: origin.y + t * dir.y,
+        z: origin.z + t * dir.z,
+    }
+}
+
+/// Draws `src` over `dst`, `dst` holds premultiplied colors.
+fn blend(dst: &mut [f32; 4], src: [f32; 4], flags: xr::CompositionLayerFlags) {
+    if !flags.contains(xr::CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA) {
+        *dst = [src[0], src[1], src[2], 1.0];
+        return;
+    }
+
+    let alpha = src[3];
+    let scale = if flags.contains(xr::CompositionLayerFlags::UNPREMULTIPLIED_ALPHA) {
+        alpha
+    } else {
+        1.0
+    };
+
+    for channel in 0..3 {
+        dst[channel] = src[channel] * scale + dst[channel] * (1.0 - alpha);
+    }
+    dst[3] = alpha + dst[3] * (1.0 - alpha);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    use super::*;
+    use crate::utils::create_identity_pose;
+
+    fn vec3(x: f32, y: f32, z: f32) -> xr::Vector3f {
+        xr::Vector3f { x, y, z }
+    }
+
+    fn assert_hit(actual: Option<(usize, f32, f32)>, expected: (usize, f32, f32)) {
+        let (image, u, v) = actual.expect("the ray missed");
+        assert!(
+            image == expected.0 && (u - expected.1).abs() < 1e-5 && (v - expected.2).abs() < 1e-5,
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn projection_views_cover_their_fov() {
+        let fov = xr::Fovf {
+            angle_left: -FRAC_PI_4,
+            angle_right: FRAC_PI_4,
+            angle_up: FRAC_PI_4,
+            angle_down: -FRAC_PI_4,
+        };
+        let shape = LayerShape::Projection {
+            views: vec![(create_identity_pose(), fov); 2],
+        };
+        let origin = vec3(0.0, 0.0, 0.0);
+
+        assert_hit(
+            hit(&shape, 1, &origin, &vec3(0.0, 0.0, -1.0)),
+            (1, 0.5, 0.5),
+        );
+        assert_hit(
+            hit(&shape, 0, &origin, &vec3(-0.5, 0.5, -1.0)),
+            (0, 0.25, 0.25),
+        );
+        assert_eq!(hit(&shape, 0, &origin, &vec3(0.0, 0.0, 1.0)), None);
+        assert_eq!(hit(&shape, 0, &origin, &vec3(2.0, 0.0, -1.0)), None);
+    }
+
+    #[test]
+    fn quads_are_hit_in_front_of_the_eye() {
+        let shape = LayerShape::Quad {
+            pose: create_identity_pose(),
+            size: xr::Extent2Df {
+                width: 2.0,
+                height: 1.0,
+            },
+        };
+        // the eye is 2m in front of the quad
+        let origin = vec3(0.0, 0.0, 2.0);
+
+        assert_hit(
+            hit(&shape, 0, &origin, &vec3(0.0, 0.0, -1.0)),
+            (0, 0.5, 0.5),
+        );
+        assert_hit(
+            hit(&shape, 0, &origin, &vec3(0.25, 0.125, -1.0)),
+            (0, 0.75, 0.25),
+        );
+        assert_eq!(hit(&shape, 0, &origin, &vec3(0.0, 0.0, 1.0)), None);
+        assert_eq!(hit(&shape, 0, &origin, &vec3(1.0, 0.0, 0.0)), None);
+        assert_eq!(hit(&shape, 0, &origin, &vec3(1.0, 0.0, -1.0)), None);
+    }
+
+    #[test]
+    fn cylinders_are_hit_from_inside() {
+        let shape = LayerShape::Cylinder {
+            pose: create_identity_pose(),
+            radius: 2.0,
+            central_angle: FRAC_PI_2,
+            aspect_ratio: 1.0,
+        };
+        let origin = vec3(0.0, 0.0, 0.0);
+
+        assert_hit(
+            hit(&shape, 0, &origin, &vec3(0.0, 0.0, -1.0)),
+            (0, 0.5, 0.5),
+        );
+        // the right edge is a quarter turn away from the left one
+        assert_hit(
+            hit(&shape, 0, &origin, &vec3(1.0, 0.0, -1.0)),
+            (0, 1.0, 0.5),
+        );
+        assert_eq!(hit(&shape, 0, &origin, &vec3(1.0, 0.0, 0.0)), None);
+        assert_eq!(hit(&shape, 0, &origin, &vec3(0.0, 1.0, 0.0)), None);
+    }
+
+    #[test]
+    fn infinite_equirects_ignore_the_eye_position() {
+        let shape = LayerShape::Equirect {
+            pose: create_identity_pose(),
+            radius: 0.0,
+            central_horizontal_angle: PI,
+            upper_vertical_angle: FRAC_PI_4,
+            lower_vertical_angle: -FRAC_PI_4,
+        };
+
+        for origin in [vec3(0.0, 0.0, 0.0), vec3(5.0, 1.0, -3.0)] {
+            assert_hit(
+                hit(&shape, 0, &origin, &vec3(0.0, 0.0, -1.0)),
+                (0, 0.5, 0.5),
+            );
+            assert_hit(
+                hit(&shape, 0, &origin, &vec3(-1.0, 0.0, 0.0)),
+                (0, 0.0, 0.5),
+            );
+            assert_eq!(hit(&shape, 0, &origin, &vec3(0.0, 0.0, 1.0)), None);
+            assert_eq!(hit(&shape, 0, &origin, &vec3(0.0, 1.0, -0.5)), None);
+        }
+    }
+
+    #[test]
+    fn cube_faces_follow_vulkan() {
+        let shape = LayerShape::Cube {
+            orientation: create_identity_pose().orientation,
+        };
+        let origin = vec3(0.0, 0.0, 0.0);
+
+        for (face, dir) in [
+            vec3(1.0, 0.0, 0.0),
+            vec3(-1.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            vec3(0.0, -1.0, 0.0),
+            vec3(0.0, 0.0, 1.0),
+            vec3(0.0, 0.0, -1.0),
+        ]
+        .iter()
+        .enumerate()
+        {
+            assert_hit(hit(&shape, 0, &origin, dir), (face, 0.5, 0.5));
+        }
+
+        assert_hit(
+            hit(&shape, 0, &origin, &vec3(0.5, 0.5, -1.0)),
+            (5, 0.25, 0.25),
+        );
+    }
+
+    #[test]
+    fn only_positive_intersections_count() {
+        // from inside a unit sphere only the far side is hit
+        assert_eq!(intersections(1.0, 0.0, -1.0), [None, Some(1.0)]);
+        assert_eq!(intersections(1.0, -4.0, 3.0), [Some(1.0), Some(3.0)]);
+        assert_eq!(intersections(1.0, 0.0, 1.0), [None, None]);
+        assert_eq!(intersections(0.0, 1.0, -1.0), [None, None]);
+    }
+
+    #[test]
+    fn opaque_layers_replace_what_is_below() {
+        let mut color = [0.2, 0.4, 0.6, 1.0];
+        blend(
+            &mut color,
+            [1.0, 0.0, 0.0, 0.0],
+            xr::CompositionLayerFlags::EMPTY,
+        );
+
+        assert_eq!(color, [1.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn alpha_is_blended_premultiplied_or_not() {
+        let below = [0.0, 0.0, 1.0, 1.0];
+
+        let mut color = below;
+        blend(
+            &mut color,
+            [0.5, 0.0, 0.0, 0.5],
+            xr::CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA,
+        );
+        assert_eq!(color, [0.5, 0.0, 0.5, 1.0]);
+
+        let mut color = below;
+        blend(
+            &mut color,
+            [1.0, 0.0, 0.0, 0.5],
+            xr::CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA
+                | xr::CompositionLayerFlags::UNPREMULTIPLIED_ALPHA,
+        );
+        assert_eq!(color, [0.5, 0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn samples_are_clamped_to_the_image() {
+        let pixels = LayerPixels {
+            width: 2,
+            height: 1,
+            rgba: vec![255, 0, 0, 255, 0, 0, 255, 255],
+        };
+
+        assert_eq!(pixels.sample(0.0, 0.0), [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(pixels.sample(0.75, 0.5), [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(pixels.sample(1.0, 1.0), [0.0, 0.0, 1.0, 1.0]);
+    }
+}
@@ -1,7 +1,20 @@
-use std::{collections::HashSet, mem::transmute, thread, time::Duration};
+use std::{
+    collections::{HashMap, HashSet, hash_map::Entry},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
 
 use crate::{
-    loader::START_TIME, prelude::*, rendering::swapchain::with_swapchain, session::with_session,
+    clock,
+    prelude::*,
+    rendering::{
+        compositor::{compose, read_depths},
+        layer::FrameLayer,
+        swapchain::with_swapchain,
+    },
+    session::with_session,
+    spaces::SpaceSnapshot,
     utils::MyTime,
 };
 
@@ -27,15 +40,28 @@ pub extern "system" fn wait(
         }
     }
 
-    with_session(xr_session.into_raw(), |session| {
+    // the session stays unlocked while waiting so begin and end can be called from other threads
+    let res = with_session(xr_session.into_raw(), |session| {
         if !session.is_running {
             return Err(xr::Result::ERROR_SESSION_NOT_RUNNING.into());
         }
         log::debug!("[{}] wait_frame ({info:?})", session.id);
         session.synchronize()?;
-        session.frame.wait(info, frame_state)
-    })
-    .into_xr_result()
+        Ok((
+            session.frame.clone(),
+            session.profile.display_period(),
+            session.is_visible(),
+        ))
+    });
+
+    let (frame, display_period, should_render) = match res {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
+    frame
+        .wait(info, frame_state, display_period, should_render)
+        .into_xr_result()
 }
 
 pub extern "system" fn begin(
@@ -64,126 +90,240 @@ pub extern "system" fn end(xr_session: xr::Session, info: *const xr::FrameEndInf
     }
 
     let info = unsafe { &*info };
-    if info.ty != xr::StructureType::FRAME_END_INFO || info.layers.is_null() {
+    if info.ty != xr::StructureType::FRAME_END_INFO
+        || (info.layer_count > 0 && info.layers.is_null())
+    {
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
+    if info.display_time.as_nanos() <= 0 {
+        return xr::Result::ERROR_TIME_INVALID;
+    }
+
+    let layers: &[*const xr::CompositionLayerBaseHeader] = if info.layer_count > 0 {
+        unsafe { std::slice::from_raw_parts(info.layers, info.layer_count as usize) }
+    } else {
+        &[]
+    };
+
+    // locating action spaces needs their actions, so the layers are copied with their spaces
+    // before locking the session
+    let res = layers
+        .iter()
+        .map(|&layer| {
+            if layer.is_null() {
+                return Err(xr::Result::ERROR_LAYER_INVALID.into());
+            }
+            FrameLayer::from_raw(unsafe { &*layer })
+        })
+        .collect::<Result<Vec<_>>>()
+        .and_then(|layers| {
+            let mut spaces = HashMap::new();
+            for layer in &layers {
+                if let Entry::Vacant(entry) = spaces.entry(layer.space) {
+                    entry.insert(SpaceSnapshot::new(layer.space)?);
+                }
+            }
+            Ok((layers, spaces))
+        });
+
+    let (layers, spaces) = match res {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
     with_session(xr_session.into_raw(), |session| {
         if !session.is_running {
             return Err(xr::Result::ERROR_SESSION_NOT_RUNNING.into());
         }
-        if !session.frame.can_end() {
+        if !session.frame.can_end()? {
             return Err(xr::Result::ERROR_CALL_ORDER_INVALID.into());
         }
 
-        let layers: Option<&[&xr::CompositionLayerBaseHeader]> = if info.layer_count > 0 {
-            unsafe {
-                Some(std::slice::from_raw_parts(
-                    info.layers as *const &xr::CompositionLayerBaseHeader,
-                    info.layer_count as usize,
-                ))
+        log::debug!("[{}] end_frame ({info:?}), layers: {layers:?}", session.id);
+
+        if info.layer_count > session.profile.graphics.max_layer_count {
+            return Err(xr::Result::ERROR_LAYER_LIMIT_EXCEEDED.into());
+        }
+
+        if !session
+            .profile
+            .environment_blend_modes()
+            .contains(&info.environment_blend_mode)
+        {
+            return Err(xr::Result::ERROR_ENVIRONMENT_BLEND_MODE_UNSUPPORTED.into());
+        }
+
+        if spaces.values().any(|space| space.session_id != session.id) {
+            return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+        }
+
+        for layer in &layers {
+            layer.validate(session)?;
+        }
+
+        let is_capture_requested = session.capture.is_requested();
+
+        // the mirror window shows the same composed views a capture writes
+        #[cfg(feature = "mirror")]
+        let is_mirrored = crate::rendering::mirror::is_enabled();
+        #[cfg(not(feature = "mirror"))]
+        let is_mirrored = false;
+
+        if is_capture_requested || is_mirrored {
+            let views = compose(session, &layers, &spaces);
+
+            if is_capture_requested {
+                let depths = read_depths(session, &layers);
+                session.capture.write_frame(&views, &depths);
             }
-        } else {
-            None
-        };
-
-        log::debug!("[{}] end_frame ({info:?})", session.id);
-
-        let mut release_swapchains = HashSet::with_capacity(2);
-
-        for layer in layers.unwrap_or_default() {
-            match layer.ty {
-                xr::StructureType::COMPOSITION_LAYER_PROJECTION => {
-                    let layer = unsafe {
-                        transmute::<
-                            &&xr::CompositionLayerBaseHeader,
-                            &&xr::CompositionLayerProjection,
-                        >(layer)
-                    };
-
-                    if layer.view_count != 2 {
-                        return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
-                    }
-
-                    let views = unsafe {
-                        std::slice::from_raw_parts(layer.views, layer.view_count as usize)
-                    };
-
-                    log::debug!(
-                        "[{}] end_frame, layer: {layer:?}, views: {views:?}",
-                        session.id
-                    );
-
-                    for view in views {
-                        release_swapchains.insert(view.sub_image.swapchain.into_raw());
-                    }
-                }
-                _ => return Err(xr::Result::ERROR_RUNTIME_FAILURE.into()),
+
+            #[cfg(feature = "mirror")]
+            if is_mirrored {
+                crate::rendering::mirror::submit(&views);
             }
         }
 
+        let release_swapchains = layers
+            .iter()
+            .flat_map(|layer| layer.swapchains())
+            .collect::<HashSet<_>>();
+
         for swapchain_id in release_swapchains {
             with_swapchain(swapchain_id, |swapchain| swapchain.free_image())?;
         }
 
-        session.frame.end()
+        session.frame.end()?;
+        session.frame_ended()
     })
     .into_xr_result()
 }
 
+/// Vsync the next frame is displayed at, `now` and `period` in nanoseconds. Rendering gets one
+/// whole period, a late app skips the vsyncs it missed.
+fn next_display_vsync(now: u64, period: u64, last_vsync: Option<u64>) -> u64 {
+    (now / period + 2).max(last_vsync.map_or(0, |vsync| vsync + 1))
+}
+
 #[derive(Debug, Default)]
+struct FrameTiming {
+    /// a frame was waited but not begun yet, the next wait blocks until it is
+    is_waited: bool,
+    is_began: bool,
+    /// vsync the last waited frame will be displayed at, counted from the runtime's epoch
+    display_vsync: Option<u64>,
+}
+
+/// Paces the frame loop of a session to the vsyncs of the simulated display. It's shared with the
+/// threads calling wait, begin and end so they don't need the session locked.
+#[derive(Debug, Default, Clone)]
 pub struct SessionFrame {
-    pub(crate) waiting_begin: bool,
-    pub(crate) is_waited: bool,
-    pub(crate) is_began: bool,
+    inner: Arc<(Mutex<FrameTiming>, Condvar)>,
 }
 
 impl SessionFrame {
+    /// Blocks until the previous frame was begun and the display is ready for a new one, one
+    /// period before the frame will be displayed. On a virtual clock it returns right away,
+    /// moving the clock to that point instead.
     pub fn wait(
-        &mut self,
+        &self,
         _info: Option<&xr::FrameWaitInfo>,
         frame_state: &mut xr::FrameState,
+        display_period: Duration,
+        should_render: bool,
     ) -> Result<()> {
-        #[allow(clippy::while_immutable_condition)]
-        while self.waiting_begin {}
+        let (timing, began) = &*self.inner;
+        let mut timing = began.wait_while(timing.lock()?, |timing| timing.is_waited)?;
 
-        // throttle to 2 fps
-        thread::sleep(Duration::from_millis(500));
+        let period = (display_period.as_nanos() as u64).max(1);
+        let now = clock::now().as_nanos() as u64;
 
-        frame_state.predicted_display_time =
-            MyTime::from(START_TIME.elapsed() + Duration::from_millis(1)).into();
-        frame_state.predicted_display_period = Duration::from_millis(16).try_into().unwrap();
-        frame_state.should_render = xr::TRUE;
+        let display_vsync = next_display_vsync(now, period, timing.display_vsync);
+
+        timing.is_waited = true;
+        timing.display_vsync = Some(display_vsync);
+        drop(timing);
+
+        let wake_up = Duration::from_nanos((display_vsync - 1) * period);
+        if clock::is_virtual() {
+            clock::advance_to(wake_up)?;
+        } else if let Some(remaining) = wake_up.checked_sub(clock::now()) {
+            thread::sleep(remaining);
+        }
 
-        self.is_waited = true;
-        self.waiting_begin = true;
+        frame_state.predicted_display_time =
+            MyTime::from(Duration::from_nanos(display_vsync * period)).into();
+        frame_state.predicted_display_period = xr::Duration::from_nanos(period as i64);
+        frame_state.should_render = should_render.into();
 
         Ok(())
     }
 
-    pub fn begin(&mut self, _info: Option<&xr::FrameBeginInfo>) -> Result<xr::Result> {
-        if !self.is_waited {
+    pub fn begin(&self, _info: Option<&xr::FrameBeginInfo>) -> Result<xr::Result> {
+        let (timing, began) = &*self.inner;
+        let mut timing = timing.lock()?;
+
+        if !timing.is_waited {
             return Err(xr::Result::ERROR_CALL_ORDER_INVALID.into());
         }
 
-        self.waiting_begin = false;
-        self.is_waited = false;
+        timing.is_waited = false;
+        began.notify_all();
 
-        if self.is_began {
+        if timing.is_began {
             return Ok(xr::Result::FRAME_DISCARDED);
         }
 
-        self.is_began = true;
+        timing.is_began = true;
 
         Ok(xr::Result::SUCCESS)
     }
 
-    pub fn can_end(&mut self) -> bool {
-        self.is_began
+    pub fn can_end(&self) -> Result<bool> {
+        Ok(self.inner.0.lock()?.is_began)
     }
 
-    pub fn end(&mut self) -> Result<()> {
-        self.is_began = false;
+    pub fn end(&self) -> Result<()> {
+        self.inner.0.lock()?.is_began = false;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: u64 = 11_111_111;
+
+    #[test]
+    fn frames_are_displayed_two_vsyncs_ahead() {
+        assert_eq!(next_display_vsync(0, PERIOD, None), 2);
+        assert_eq!(
+            next_display_vsync(10 * PERIOD + PERIOD / 2, PERIOD, None),
+            12
+        );
+    }
+
+    #[test]
+    fn each_frame_gets_its_own_vsync() {
+        // an app waiting again within the same period is paced to the next vsync
+        assert_eq!(next_display_vsync(10 * PERIOD, PERIOD, Some(12)), 13);
+        assert_eq!(next_display_vsync(10 * PERIOD, PERIOD, Some(15)), 16);
+    }
+
+    #[test]
+    fn late_frames_skip_the_missed_vsyncs() {
+        assert_eq!(next_display_vsync(20 * PERIOD + 1, PERIOD, Some(12)), 22);
+    }
+
+    #[test]
+    fn frames_are_begun_after_being_waited() {
+        let frame = SessionFrame::default();
+
+        assert!(matches!(
+            frame.begin(None),
+            Err(Error::XrResult(xr::Result::ERROR_CALL_ORDER_INVALID))
+        ));
+        assert!(!frame.can_end().unwrap());
+    }
+}
@@ -0,0 +1,555 @@
+use std::{
+    f32::consts::{FRAC_PI_2, PI},
+    ffi::CStr,
+};
+
+use crate::{
+    math::is_pose_valid, prelude::*, rendering::swapchain::with_swapchain,
+    session::SimulatedSession, utils::find_in_next_chain,
+};
+
+const CUBE_FACE_COUNT: u32 = 6;
+
+/// The part of a swapchain image a layer shows.
+#[derive(Debug, Clone, Copy)]
+pub struct LayerImage {
+    pub(crate) swapchain: u64,
+    /// `None` for the whole image
+    pub(crate) rect: Option<xr::Rect2Di>,
+    pub(crate) array_index: u32,
+}
+
+impl From<&xr::SwapchainSubImage> for LayerImage {
+    fn from(value: &xr::SwapchainSubImage) -> Self {
+        Self {
+            swapchain: value.swapchain.into_raw(),
+            rect: Some(value.image_rect),
+            array_index: value.image_array_index,
+        }
+    }
+}
+
+/// Depth buffer submitted with a projection view.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct LayerDepth {
+    pub(crate) image: LayerImage,
+    pub(crate) min_depth: f32,
+    pub(crate) max_depth: f32,
+    pub(crate) near_z: f32,
+    pub(crate) far_z: f32,
+}
+
+impl LayerDepth {
+    fn from_raw(depth_info: &xr::CompositionLayerDepthInfoKHR) -> Result<Self> {
+        if !((0.0..=1.0).contains(&depth_info.min_depth)
+            && (0.0..=1.0).contains(&depth_info.max_depth)
+            && depth_info.min_depth < depth_info.max_depth
+            && depth_info.near_z != depth_info.far_z
+            && !depth_info.near_z.is_nan()
+            && !depth_info.far_z.is_nan())
+        {
+            return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+        }
+
+        Ok(Self {
+            image: LayerImage::from(&depth_info.sub_image),
+            min_depth: depth_info.min_depth,
+            max_depth: depth_info.max_depth,
+            near_z: depth_info.near_z,
+            far_z: depth_info.far_z,
+        })
+    }
+}
+
+/// Geometry of a layer, poses are in the layer's space.
+#[derive(Debug, Clone)]
+pub enum LayerShape {
+    /// pose and fov of each eye's view, in the same order as the images
+    Projection { views: Vec<(xr::Posef, xr::Fovf)> },
+    Quad {
+        pose: xr::Posef,
+        size: xr::Extent2Df,
+    },
+    /// a radius of 0 is infinite
+    Cylinder {
+        pose: xr::Posef,
+        radius: f32,
+        central_angle: f32,
+        aspect_ratio: f32,
+    },
+    /// a radius of 0 is infinite
+    Equirect {
+        pose: xr::Posef,
+        radius: f32,
+        central_horizontal_angle: f32,
+        upper_vertical_angle: f32,
+        lower_vertical_angle: f32,
+    },
+    /// the images are the faces, ordered +X, -X, +Y, -Y, +Z, -Z
+    Cube { orientation: xr::Quaternionf },
+}
+
+impl LayerShape {
+    /// the extension that must be enabled for the layer to be submitted
+    fn extension(&self) -> Option<&'static [u8]> {
+        match self {
+            Self::Projection { .. } | Self::Quad { .. } => None,
+            Self::Cylinder { .. } => Some(xr::KHR_COMPOSITION_LAYER_CYLINDER_EXTENSION_NAME),
+            Self::Equirect { .. } => Some(xr::KHR_COMPOSITION_LAYER_EQUIRECT2_EXTENSION_NAME),
+            Self::Cube { .. } => Some(xr::KHR_COMPOSITION_LAYER_CUBE_EXTENSION_NAME),
+        }
+    }
+}
+
+/// Copy of a layer submitted with `xrEndFrame`.
+#[derive(Debug, Clone)]
+pub struct FrameLayer {
+    pub(crate) flags: xr::CompositionLayerFlags,
+    pub(crate) space: u64,
+    pub(crate) eye_visibility: xr::EyeVisibility,
+    pub(crate) shape: LayerShape,
+    pub(crate) images: Vec<LayerImage>,
+    /// depth of each projection view, from `XR_KHR_composition_layer_depth`
+    pub(crate) depths: Vec<Option<LayerDepth>>,
+}
+
+impl FrameLayer {
+    /// Copies a layer, checking the fields that don't depend on the session.
+    pub fn from_raw(layer: &xr::CompositionLayerBaseHeader) -> Result<Self> {
+        let layer_ptr = layer as *const xr::CompositionLayerBaseHeader;
+
+        let mut depths = Vec::new();
+
+        let (eye_visibility, shape, images) = match layer.ty {
+            xr::StructureType::COMPOSITION_LAYER_PROJECTION => {
+                let layer = unsafe { &*(layer_ptr as *const xr::CompositionLayerProjection) };
+
+                if layer.view_count != 2 || layer.views.is_null() {
+                    return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+                }
+
+                let views =
+                    unsafe { std::slice::from_raw_parts(layer.views, layer.view_count as usize) };
+
+                for view in views {
+                    if view.ty != xr::StructureType::COMPOSITION_LAYER_PROJECTION_VIEW {
+                        return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+                    }
+                    if !is_pose_valid(&view.pose) {
+                        return Err(xr::Result::ERROR_POSE_INVALID.into());
+                    }
+
+                    let depth_info = find_in_next_chain::<xr::CompositionLayerDepthInfoKHR>(
+                        view.next,
+                        xr::StructureType::COMPOSITION_LAYER_DEPTH_INFO_KHR,
+                    );
+                    depths.push(depth_info.map(LayerDepth::from_raw).transpose()?);
+                }
+
+                (
+                    xr::EyeVisibility::BOTH,
+                    LayerShape::Projection {
+                        views: views.iter().map(|view| (view.pose, view.fov)).collect(),
+                    },
+                    views
+                        .iter()
+                        .map(|view| LayerImage::from(&view.sub_image))
+                        .collect(),
+                )
+            }
+            xr::StructureType::COMPOSITION_LAYER_QUAD => {
+                let layer = unsafe { &*(layer_ptr as *const xr::CompositionLayerQuad) };
+
+                if !is_pose_valid(&layer.pose) {
+                    return Err(xr::Result::ERROR_POSE_INVALID.into());
+                }
+                if !(layer.size.width >= 0.0 && layer.size.height >= 0.0) {
+                    return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+                }
+
+                (
+                    layer.eye_visibility,
+                    LayerShape::Quad {
+                        pose: layer.pose,
+                        size: layer.size,
+                    },
+                    vec![LayerImage::from(&layer.sub_image)],
+                )
+            }
+            xr::StructureType::COMPOSITION_LAYER_CYLINDER_KHR => {
+                let layer = unsafe { &*(layer_ptr as *const xr::CompositionLayerCylinderKHR) };
+
+                if !is_pose_valid(&layer.pose) {
+                    return Err(xr::Result::ERROR_POSE_INVALID.into());
+                }
+                if !(layer.radius >= 0.0
+                    && (0.0..=2.0 * PI).contains(&layer.central_angle)
+                    && layer.aspect_ratio > 0.0)
+                {
+                    return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+                }
+
+                (
+                    layer.eye_visibility,
+                    LayerShape::Cylinder {
+                        pose: layer.pose,
+                        radius: layer.radius,
+                        central_angle: layer.central_angle,
+                        aspect_ratio: layer.aspect_ratio,
+                    },
+                    vec![LayerImage::from(&layer.sub_image)],
+                )
+            }
+            xr::StructureType::COMPOSITION_LAYER_EQUIRECT2_KHR => {
+                let layer = unsafe { &*(layer_ptr as *const xr::CompositionLayerEquirect2KHR) };
+
+                if !is_pose_valid(&layer.pose) {
+                    return Err(xr::Result::ERROR_POSE_INVALID.into());
+                }
+                let vertical_range = -FRAC_PI_2..=FRAC_PI_2;
+                if !(layer.radius >= 0.0
+                    && (0.0..=2.0 * PI).contains(&layer.central_horizontal_angle)
+                    && vertical_range.contains(&layer.upper_vertical_angle)
+                    && vertical_range.contains(&layer.lower_vertical_angle)
+                    && layer.lower_vertical_angle <= layer.upper_vertical_angle)
+                {
+                    return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+                }
+
+                (
+                    layer.eye_visibility,
+                    LayerShape::Equirect {
+                        pose: layer.pose,
+                        radius: layer.radius,
+                        central_horizontal_angle: layer.central_horizontal_angle,
+                        upper_vertical_angle: layer.upper_vertical_angle,
+                        lower_vertical_angle: layer.lower_vertical_angle,
+                    },
+                    vec![LayerImage::from(&layer.sub_image)],
+                )
+            }
+            xr::StructureType::COMPOSITION_LAYER_CUBE_KHR => {
+                let layer = unsafe { &*(layer_ptr as *const xr::CompositionLayerCubeKHR) };
+
+                if !is_pose_valid(&xr::Posef {
+                    orientation: layer.orientation,
+                    position: Default::default(),
+                }) {
+                    return Err(xr::Result::ERROR_POSE_INVALID.into());
+                }
+
+                (
+                    layer.eye_visibility,
+                    LayerShape::Cube {
+                        orientation: layer.orientation,
+                    },
+                    (0..CUBE_FACE_COUNT)
+                        .map(|face| LayerImage {
+                            swapchain: layer.swapchain.into_raw(),
+                            rect: None,
+                            array_index: layer.image_array_index * CUBE_FACE_COUNT + face,
+                        })
+                        .collect(),
+                )
+            }
+            _ => return Err(xr::Result::ERROR_LAYER_INVALID.into()),
+        };
+
+        if !matches!(
+            eye_visibility,
+            xr::EyeVisibility::BOTH | xr::EyeVisibility::LEFT | xr::EyeVisibility::RIGHT
+        ) {
+            return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+        }
+
+        Ok(Self {
+            flags: layer.layer_flags,
+            space: layer.space.into_raw(),
+            eye_visibility,
+            shape,
+            images,
+            depths,
+        })
+    }
+
+    /// Checks the layer against the session it's submitted to.
+    pub fn validate(&self, session: &SimulatedSession) -> Result<()> {
+        if let Some(extension) = self.shape.extension() {
+            if !is_extension_enabled(session, extension) {
+                return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+            }
+        }
+
+        for image in &self.images {
+            let face_count = validate_image(session, image)?;
+
+            if matches!(self.shape, LayerShape::Cube { .. }) && face_count != CUBE_FACE_COUNT {
+                return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+            }
+        }
+
+        if self.depths.iter().any(Option::is_some) {
+            if !is_extension_enabled(session, xr::KHR_COMPOSITION_LAYER_DEPTH_EXTENSION_NAME) {
+                return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+            }
+
+            for depth in self.depths.iter().flatten() {
+                validate_image(session, &depth.image)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// every swapchain the layer reads, each must have an image released
+    pub fn swapchains(&self) -> impl Iterator<Item = u64> + '_ {
+        self.images
+            .iter()
+            .chain(self.depths.iter().flatten().map(|depth| &depth.image))
+            .map(|image| image.swapchain)
+    }
+
+    pub fn is_visible(&self, eye: usize) -> bool {
+        match self.eye_visibility {
+            xr::EyeVisibility::LEFT => eye == 0,
+            xr::EyeVisibility::RIGHT => eye == 1,
+            _ => true,
+        }
+    }
+}
+
+fn is_extension_enabled(session: &SimulatedSession, extension: &[u8]) -> bool {
+    let name = CStr::from_bytes_with_nul(extension)
+        .ok()
+        .and_then(|name| name.to_str().ok())
+        .unwrap_or_default();
+
+    if session.enabled_extensions.contains(name) {
+        return true;
+    }
+
+    log::error!("[{}] layer of a disabled extension {name}", session.id);
+    false
+}
+
+/// Checks a layer image refers to a released image of a swapchain of the session, returns the
+/// swapchain's face count.
+fn validate_image(session: &SimulatedSession, image: &LayerImage) -> Result<u32> {
+    if !session.swapchain_ids.contains(&image.swapchain) {
+        return Err(xr::Result::ERROR_HANDLE_INVALID.into());
+    }
+
+    with_swapchain(image.swapchain, |swapchain| {
+        let Some(released_image) = swapchain.released_image() else {
+            log::error!("[{}] no image released of {}", session.id, image.swapchain);
+            return Err(xr::Result::ERROR_LAYER_INVALID.into());
+        };
+
+        check_image_bounds(
+            image,
+            swapchain.array_layer_count(),
+            released_image.width,
+            released_image.height,
+        )?;
+
+        Ok(swapchain.face_count())
+    })
+}
+
+/// Checks the array layer and rect of a layer image fit the swapchain's images.
+fn check_image_bounds(
+    image: &LayerImage,
+    array_layer_count: u32,
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    // projection views of multiview renderers share a swapchain, one layer each
+    if image.array_index >= array_layer_count {
+        return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+    }
+
+    if let Some(rect) = &image.rect {
+        if !is_rect_within(rect, width, height) {
+            return Err(xr::Result::ERROR_SWAPCHAIN_RECT_INVALID.into());
+        }
+    }
+
+    Ok(())
+}
+
+fn is_rect_within(rect: &xr::Rect2Di, width: u32, height: u32) -> bool {
+    rect.offset.x >= 0
+        && rect.offset.y >= 0
+        && rect.extent.width > 0
+        && rect.extent.height > 0
+        && rect.offset.x as i64 + rect.extent.width as i64 <= width as i64
+        && rect.offset.y as i64 + rect.extent.height as i64 <= height as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_identity_pose;
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> xr::Rect2Di {
+        xr::Rect2Di {
+            offset: xr::Offset2Di { x, y },
+            extent: xr::Extent2Di { width, height },
+        }
+    }
+
+    fn image(rect: Option<xr::Rect2Di>, array_index: u32) -> LayerImage {
+        LayerImage {
+            swapchain: 1,
+            rect,
+            array_index,
+        }
+    }
+
+    fn quad(sub_image_rect: xr::Rect2Di) -> xr::CompositionLayerQuad {
+        xr::CompositionLayerQuad {
+            ty: xr::StructureType::COMPOSITION_LAYER_QUAD,
+            next: std::ptr::null(),
+            layer_flags: xr::CompositionLayerFlags::EMPTY,
+            space: xr::Space::from_raw(1),
+            eye_visibility: xr::EyeVisibility::BOTH,
+            sub_image: xr::SwapchainSubImage {
+                swapchain: xr::Swapchain::from_raw(1),
+                image_rect: sub_image_rect,
+                image_array_index: 0,
+            },
+            pose: create_identity_pose(),
+            size: xr::Extent2Df {
+                width: 1.0,
+                height: 1.0,
+            },
+        }
+    }
+
+    fn from_raw<T>(layer: &T) -> Result<FrameLayer> {
+        FrameLayer::from_raw(unsafe {
+            &*(layer as *const T as *const xr::CompositionLayerBaseHeader)
+        })
+    }
+
+    fn error_of<T: std::fmt::Debug>(res: Result<T>) -> xr::Result {
+        match res {
+            Err(Error::XrResult(res)) => res,
+            res => panic!("expected an xr error, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn rects_must_lie_within_the_image() {
+        assert!(is_rect_within(&rect(0, 0, 640, 480), 640, 480));
+        assert!(is_rect_within(&rect(320, 0, 320, 480), 640, 480));
+        assert!(!is_rect_within(&rect(321, 0, 320, 480), 640, 480));
+        assert!(!is_rect_within(&rect(-1, 0, 320, 480), 640, 480));
+        assert!(!is_rect_within(&rect(0, 0, 0, 480), 640, 480));
+        assert!(!is_rect_within(&rect(i32::MAX, 0, i32::MAX, 480), 640, 480));
+    }
+
+    #[test]
+    fn images_must_fit_the_swapchain() {
+        assert!(check_image_bounds(&image(Some(rect(0, 0, 64, 64)), 1), 2, 64, 64).is_ok());
+        assert!(check_image_bounds(&image(None, 0), 1, 64, 64).is_ok());
+
+        assert_eq!(
+            error_of(check_image_bounds(&image(None, 2), 2, 64, 64)),
+            xr::Result::ERROR_VALIDATION_FAILURE
+        );
+        assert_eq!(
+            error_of(check_image_bounds(
+                &image(Some(rect(0, 0, 65, 64)), 0),
+                1,
+                64,
+                64
+            )),
+            xr::Result::ERROR_SWAPCHAIN_RECT_INVALID
+        );
+    }
+
+    #[test]
+    fn quads_are_copied() {
+        let layer = from_raw(&quad(rect(0, 0, 32, 16))).unwrap();
+
+        assert!(matches!(layer.shape, LayerShape::Quad { size, .. } if size.width == 1.0));
+        assert_eq!(layer.images.len(), 1);
+        assert_eq!(layer.images[0].rect, Some(rect(0, 0, 32, 16)));
+        assert!(layer.is_visible(0) && layer.is_visible(1));
+    }
+
+    #[test]
+    fn invalid_quads_are_rejected() {
+        let mut layer = quad(rect(0, 0, 32, 16));
+        layer.size.width = -1.0;
+        assert_eq!(
+            error_of(from_raw(&layer)),
+            xr::Result::ERROR_VALIDATION_FAILURE
+        );
+
+        let mut layer = quad(rect(0, 0, 32, 16));
+        layer.pose.orientation.w = 0.0;
+        assert_eq!(error_of(from_raw(&layer)), xr::Result::ERROR_POSE_INVALID);
+
+        let mut layer = quad(rect(0, 0, 32, 16));
+        layer.eye_visibility = xr::EyeVisibility::from_raw(3);
+        assert_eq!(
+            error_of(from_raw(&layer)),
+            xr::Result::ERROR_VALIDATION_FAILURE
+        );
+    }
+
+    #[test]
+    fn cube_faces_are_consecutive_array_layers() {
+        let layer = xr::CompositionLayerCubeKHR {
+            ty: xr::StructureType::COMPOSITION_LAYER_CUBE_KHR,
+            next: std::ptr::null(),
+            layer_flags: xr::CompositionLayerFlags::EMPTY,
+            space: xr::Space::from_raw(1),
+            eye_visibility: xr::EyeVisibility::LEFT,
+            swapchain: xr::Swapchain::from_raw(1),
+            image_array_index: 2,
+            orientation: create_identity_pose().orientation,
+        };
+
+        let layer = from_raw(&layer).unwrap();
+
+        assert_eq!(
+            layer
+                .images
+                .iter()
+                .map(|image| image.array_index)
+                .collect::<Vec<_>>(),
+            (12..18).collect::<Vec<_>>()
+        );
+        assert!(layer.images.iter().all(|image| image.rect.is_none()));
+        assert!(layer.is_visible(0) && !layer.is_visible(1));
+    }
+
+    #[test]
+    fn projections_need_a_view_per_eye() {
+        let layer = xr::CompositionLayerProjection {
+            ty: xr::StructureType::COMPOSITION_LAYER_PROJECTION,
+            next: std::ptr::null(),
+            layer_flags: xr::CompositionLayerFlags::EMPTY,
+            space: xr::Space::from_raw(1),
+            view_count: 1,
+            views: std::ptr::null(),
+        };
+
+        assert_eq!(
+            error_of(from_raw(&layer)),
+            xr::Result::ERROR_VALIDATION_FAILURE
+        );
+    }
+
+    #[test]
+    fn unknown_layers_are_invalid() {
+        let mut layer = quad(rect(0, 0, 32, 16));
+        layer.ty = xr::StructureType::SWAPCHAIN_CREATE_INFO;
+
+        assert_eq!(error_of(from_raw(&layer)), xr::Result::ERROR_LAYER_INVALID);
+    }
+}
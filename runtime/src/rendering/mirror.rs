@@ -0,0 +1,556 @@
+use std::{
+    sync::{Arc, LazyLock, Mutex, mpsc},
+    thread,
+};
+
+mod vk {
+    pub use vulkano::{
+        Validated, VulkanError, VulkanLibrary,
+        buffer::{Buffer, BufferCreateInfo, BufferUsage},
+        command_buffer::{
+            AutoCommandBufferBuilder, BlitImageInfo, CommandBufferUsage, CopyBufferToImageInfo,
+            allocator::StandardCommandBufferAllocator,
+        },
+        device::{
+            Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
+            physical::{PhysicalDevice, PhysicalDeviceType},
+        },
+        format::Format,
+        image::{Image, ImageCreateInfo, ImageType, ImageUsage, sampler::Filter},
+        instance::{Instance, InstanceCreateFlags, InstanceCreateInfo},
+        memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+        swapchain::{
+            PresentMode, Surface, Swapchain, SwapchainCreateInfo, SwapchainPresentInfo,
+            acquire_next_image,
+        },
+        sync::{self, GpuFuture},
+    };
+}
+use vulkano::sync::GpuFuture as _;
+use winit::{
+    application::ApplicationHandler,
+    dpi::LogicalSize,
+    event::WindowEvent,
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy},
+    window::{Window, WindowId},
+};
+
+use crate::{prelude::*, rendering::capture::CapturedView};
+
+/// Which eyes the mirror window shows: `both` (the default), `left`, `right` or `off`.
+pub const MIRROR_ENV: &str = "OPENXR_DEVICE_SIMULATOR_MIRROR";
+
+const WIDTH: u32 = 1024;
+const HEIGHT: u32 = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MirrorMode {
+    Both,
+    Left,
+    Right,
+}
+
+static MIRROR_MODE: LazyLock<Option<MirrorMode>> =
+    LazyLock::new(|| match std::env::var(MIRROR_ENV).as_deref() {
+        Ok("off") => None,
+        Ok("left") => Some(MirrorMode::Left),
+        Ok("right") => Some(MirrorMode::Right),
+        Ok("both") | Err(_) => Some(MirrorMode::Both),
+        Ok(value) => {
+            log::error!("invalid {MIRROR_ENV} {value}, showing both eyes");
+            Some(MirrorMode::Both)
+        }
+    });
+
+/// proxy of the window's event loop, `None` once the window is gone or couldn't be opened
+static MIRROR: LazyLock<Mutex<Option<EventLoopProxy<MirrorFrame>>>> =
+    LazyLock::new(|| Mutex::new(start()));
+
+/// An RGBA8 image of the eyes the mirror shows, side by side.
+#[derive(Debug)]
+struct MirrorFrame {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl MirrorFrame {
+    fn compose(views: &[&CapturedView]) -> Option<Self> {
+        let width = views.iter().map(|view| view.width).sum::<u32>();
+        let height = views.iter().map(|view| view.height).max()?;
+
+        let mut rgba = vec![0; width as usize * height as usize * 4];
+        let mut x_offset = 0;
+
+        for view in views {
+            let row_size = view.width as usize * 4;
+            for (y, row) in view.rgba.chunks_exact(row_size).enumerate() {
+                let start = (y * width as usize + x_offset) * 4;
+                rgba[start..start + row_size].copy_from_slice(row);
+            }
+            x_offset += view.width as usize;
+        }
+
+        Some(Self {
+            width,
+            height,
+            rgba,
+        })
+    }
+}
+
+pub fn is_enabled() -> bool {
+    MIRROR_MODE.is_some()
+        && MIRROR
+            .lock()
+            .map(|mirror| mirror.is_some())
+            .unwrap_or(false)
+}
+
+/// Shows the composed views of a frame.
+pub fn submit(views: &[CapturedView]) {
+    let Some(mode) = *MIRROR_MODE else {
+        return;
+    };
+
+    let views = views
+        .iter()
+        .filter(|view| match mode {
+            MirrorMode::Both => true,
+            MirrorMode::Left => view.view == 0,
+            MirrorMode::Right => view.view == 1,
+        })
+        .collect::<Vec<_>>();
+
+    let Some(frame) = MirrorFrame::compose(&views) else {
+        return;
+    };
+
+    let Ok(mut mirror) = MIRROR.lock() else {
+        return;
+    };
+
+    if let Some(proxy) = mirror.as_ref() {
+        if proxy.send_event(frame).is_err() {
+            log::info!("mirror window closed");
+            *mirror = None;
+        }
+    }
+}
+
+/// Opens the window on its own thread, the app owns the main one.
+fn start() -> Option<EventLoopProxy<MirrorFrame>> {
+    MIRROR_MODE.as_ref()?;
+
+    if cfg!(target_os = "macos") {
+        log::error!("the mirror window needs the main thread on macOS, it won't be shown");
+        return None;
+    }
+
+    let (sender, receiver) = mpsc::sync_channel(1);
+
+    let res = thread::Builder::new()
+        .name("xrsim-mirror".into())
+        .spawn(move || {
+            let mut builder = EventLoop::<MirrorFrame>::with_user_event();
+            allow_any_thread(&mut builder);
+
+            let event_loop = match builder.build() {
+                Ok(event_loop) => event_loop,
+                Err(err) => {
+                    log::error!("could not create the mirror event loop: {err}");
+                    sender.send(None).ok();
+                    return;
+                }
+            };
+
+            event_loop.set_control_flow(ControlFlow::Wait);
+            sender.send(Some(event_loop.create_proxy())).ok();
+
+            let mut app = MirrorApp::default();
+            if let Err(err) = event_loop.run_app(&mut app) {
+                log::error!("mirror event loop failed: {err}");
+            }
+        });
+
+    if let Err(err) = res {
+        log::error!("could not start the mirror thread: {err}");
+        return None;
+    }
+
+    receiver.recv().ok().flatten()
+}
+
+/// winit wants the event loop on the main thread unless told otherwise, where the platform
+/// allows it.
+fn allow_any_thread(builder: &mut EventLoopBuilder<MirrorFrame>) {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        use winit::platform::{wayland::EventLoopBuilderExtWayland, x11::EventLoopBuilderExtX11};
+
+        // the backend is picked when the loop is built, so tell both
+        EventLoopBuilderExtWayland::with_any_thread(builder, true);
+        EventLoopBuilderExtX11::with_any_thread(builder, true);
+    }
+
+    #[cfg(windows)]
+    {
+        use winit::platform::windows::EventLoopBuilderExtWindows;
+
+        builder.with_any_thread(true);
+    }
+
+    #[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+    let _ = builder;
+}
+
+struct VulkanWindowRenderer {
+    swapchain: Arc<vk::Swapchain>,
+    images: Vec<Arc<vk::Image>>,
+}
+
+impl VulkanWindowRenderer {
+    fn new(vw: &VulkanWindow) -> Result<Self> {
+        let caps = vw
+            .physical_device
+            .surface_capabilities(&vw.surface, Default::default())
+            .map_err(|err| mirror_error("failed to get surface capabilities", err))?;
+
+        let composite_alpha = caps
+            .supported_composite_alpha
+            .into_iter()
+            .next()
+            .ok_or("the surface supports no composite alpha mode")?;
+
+        // the captured pixels are copied as they are, so a UNORM target keeps them unchanged
+        let formats = vw
+            .physical_device
+            .surface_formats(&vw.surface, Default::default())
+            .map_err(|err| mirror_error("failed to get surface formats", err))?;
+        let image_format = formats
+            .iter()
+            .find(|(format, _)| *format == vk::Format::B8G8R8A8_UNORM)
+            .or(formats.first())
+            .ok_or("the surface supports no format")?
+            .0;
+
+        let create_info = vk::SwapchainCreateInfo {
+            min_image_count: caps.min_image_count + 1,
+            image_format,
+            image_extent: vw.window.inner_size().into(),
+            image_usage: vk::ImageUsage::TRANSFER_DST | vk::ImageUsage::COLOR_ATTACHMENT,
+            composite_alpha,
+            present_mode: vk::PresentMode::Fifo,
+            ..Default::default()
+        };
+
+        let (swapchain, images) = if let Some(renderer) = vw.renderer.as_ref() {
+            renderer.swapchain.recreate(create_info)
+        } else {
+            vk::Swapchain::new(vw.device.clone(), vw.surface.clone(), create_info)
+        }
+        .map_err(|err| mirror_error("failed to create the swapchain", err))?;
+
+        Ok(VulkanWindowRenderer { swapchain, images })
+    }
+}
+
+struct VulkanWindow {
+    physical_device: Arc<vk::PhysicalDevice>,
+    device: Arc<vk::Device>,
+    queue: Arc<vk::Queue>,
+    surface: Arc<vk::Surface>,
+    memory_allocator: Arc<vk::StandardMemoryAllocator>,
+    command_buffer_allocator: Arc<vk::StandardCommandBufferAllocator>,
+    renderer: Option<VulkanWindowRenderer>,
+    recreate_swapchain: bool,
+    previous_frame_end: Option<Box<dyn vk::GpuFuture>>,
+    window: Arc<Window>,
+}
+
+impl VulkanWindow {
+    pub fn new(event_loop: &ActiveEventLoop) -> Result<Self> {
+        let window = Arc::new(
+            event_loop
+                .create_window(
+                    Window::default_attributes()
+                        .with_inner_size(LogicalSize::new(WIDTH, HEIGHT))
+                        .with_title("OpenXR Device Simulator")
+                        .with_decorations(true),
+                )
+                .map_err(|err| mirror_error("failed to create the window", err))?,
+        );
+
+        let library = vk::VulkanLibrary::new()
+            .map_err(|err| mirror_error("no local Vulkan library/DLL", err))?;
+        let required_extensions = vk::Surface::required_extensions(event_loop)
+            .map_err(|err| mirror_error("failed to get the surface extensions", err))?;
+
+        let instance = vk::Instance::new(
+            library,
+            vk::InstanceCreateInfo {
+                flags: vk::InstanceCreateFlags::ENUMERATE_PORTABILITY,
+                enabled_extensions: required_extensions,
+                ..Default::default()
+            },
+        )
+        .map_err(|err| mirror_error("failed to create instance", err))?;
+
+        let surface = vk::Surface::from_window(instance.clone(), window.clone())
+            .map_err(|err| mirror_error("failed to create the surface", err))?;
+
+        let device_extensions = vk::DeviceExtensions {
+            khr_swapchain: true,
+            ..vk::DeviceExtensions::empty()
+        };
+
+        let (physical_device, queue_family_index) = instance
+            .enumerate_physical_devices()
+            .map_err(|err| mirror_error("could not enumerate devices", err))?
+            .filter(|p| p.supported_extensions().contains(&device_extensions))
+            .filter_map(|p| {
+                p.queue_family_properties()
+                    .iter()
+                    .enumerate()
+                    .position(|(i, q)| {
+                        q.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+                            && p.surface_support(i as u32, &surface).unwrap_or(false)
+                    })
+                    .map(|q| (p, q as u32))
+            })
+            .min_by_key(|(p, _)| match p.properties().device_type {
+                vk::PhysicalDeviceType::DiscreteGpu => 0,
+                vk::PhysicalDeviceType::IntegratedGpu => 1,
+                vk::PhysicalDeviceType::VirtualGpu => 2,
+                vk::PhysicalDeviceType::Cpu => 3,
+                _ => 4,
+            })
+            .ok_or("no device available")?;
+
+        let (device, mut queues) = vk::Device::new(
+            physical_device.clone(),
+            vk::DeviceCreateInfo {
+                queue_create_infos: vec![vk::QueueCreateInfo {
+                    queue_family_index,
+                    ..Default::default()
+                }],
+                enabled_extensions: device_extensions,
+                ..Default::default()
+            },
+        )
+        .map_err(|err| mirror_error("failed to create device", err))?;
+
+        let queue = queues.next().ok_or("the device has no queue")?;
+
+        Ok(VulkanWindow {
+            memory_allocator: Arc::new(vk::StandardMemoryAllocator::new_default(device.clone())),
+            command_buffer_allocator: Arc::new(vk::StandardCommandBufferAllocator::new(
+                device.clone(),
+                Default::default(),
+            )),
+            physical_device,
+            previous_frame_end: Some(vk::sync::now(device.clone()).boxed()),
+            device,
+            queue,
+            surface,
+            renderer: None,
+            recreate_swapchain: false,
+            window,
+        })
+    }
+
+    fn update_renderer(&mut self) -> Result<()> {
+        if self.renderer.is_none() || self.recreate_swapchain {
+            log::debug!("update mirror renderer {}", self.recreate_swapchain);
+            self.renderer = Some(VulkanWindowRenderer::new(self)?);
+            self.recreate_swapchain = false;
+        }
+
+        Ok(())
+    }
+
+    pub fn set_window_resized(&mut self) {
+        self.recreate_swapchain = true;
+    }
+
+    /// Uploads the frame and scales it to the whole window.
+    pub fn render(&mut self, frame: &MirrorFrame) -> Result<()> {
+        let size = self.window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            return Ok(());
+        }
+
+        if let Some(previous_frame_end) = self.previous_frame_end.as_mut() {
+            previous_frame_end.cleanup_finished();
+        }
+
+        self.update_renderer()?;
+        let Some(renderer) = self.renderer.as_ref() else {
+            return Ok(());
+        };
+
+        let (image_i, suboptimal, acquire_future) =
+            match vk::acquire_next_image(renderer.swapchain.clone(), None)
+                .map_err(vk::Validated::unwrap)
+            {
+                Ok(r) => r,
+                Err(vk::VulkanError::OutOfDate) => {
+                    log::debug!("set recreate_swapchain");
+                    self.recreate_swapchain = true;
+                    return Ok(());
+                }
+                Err(err) => return Err(mirror_error("failed to acquire next image", err)),
+            };
+
+        if suboptimal {
+            self.recreate_swapchain = true;
+        }
+
+        let buffer = vk::Buffer::from_iter(
+            self.memory_allocator.clone(),
+            vk::BufferCreateInfo {
+                usage: vk::BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            vk::AllocationCreateInfo {
+                memory_type_filter: vk::MemoryTypeFilter::PREFER_HOST
+                    | vk::MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            frame.rgba.iter().copied(),
+        )
+        .map_err(|err| mirror_error("failed to create the upload buffer", err))?;
+
+        let image = vk::Image::new(
+            self.memory_allocator.clone(),
+            vk::ImageCreateInfo {
+                image_type: vk::ImageType::Dim2d,
+                format: vk::Format::R8G8B8A8_UNORM,
+                extent: [frame.width, frame.height, 1],
+                usage: vk::ImageUsage::TRANSFER_DST | vk::ImageUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            vk::AllocationCreateInfo::default(),
+        )
+        .map_err(|err| mirror_error("failed to create the frame image", err))?;
+
+        let mut builder = vk::AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.clone(),
+            self.queue.queue_family_index(),
+            vk::CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(|err| mirror_error("failed to create the command buffer", err))?;
+
+        builder
+            .copy_buffer_to_image(vk::CopyBufferToImageInfo::buffer_image(
+                buffer,
+                image.clone(),
+            ))
+            .map_err(|err| mirror_error("failed to record the upload", err))?
+            .blit_image(vk::BlitImageInfo {
+                filter: vk::Filter::Linear,
+                ..vk::BlitImageInfo::images(image, renderer.images[image_i as usize].clone())
+            })
+            .map_err(|err| mirror_error("failed to record the blit", err))?;
+
+        let command_buffer = builder
+            .build()
+            .map_err(|err| mirror_error("failed to build the command buffer", err))?;
+
+        let future = self
+            .previous_frame_end
+            .take()
+            .unwrap_or_else(|| vk::sync::now(self.device.clone()).boxed())
+            .join(acquire_future)
+            .then_execute(self.queue.clone(), command_buffer)
+            .map_err(|err| mirror_error("failed to execute the command buffer", err))?
+            .then_swapchain_present(
+                self.queue.clone(),
+                vk::SwapchainPresentInfo::swapchain_image_index(
+                    renderer.swapchain.clone(),
+                    image_i,
+                ),
+            )
+            .then_signal_fence_and_flush();
+
+        self.previous_frame_end = match future.map_err(vk::Validated::unwrap) {
+            Ok(future) => Some(future.boxed()),
+            Err(vk::VulkanError::OutOfDate) => {
+                log::debug!("VulkanError::OutOfDate");
+                self.recreate_swapchain = true;
+                Some(vk::sync::now(self.device.clone()).boxed())
+            }
+            Err(e) => {
+                log::error!("failed to flush future: {}", e);
+                Some(vk::sync::now(self.device.clone()).boxed())
+            }
+        };
+
+        Ok(())
+    }
+}
+
+fn mirror_error(context: &str, err: impl std::fmt::Display) -> Error {
+    Error::Generic(format!("{context}: {err}"))
+}
+
+/// Closes the window after it failed, frames aren't sent to it anymore.
+fn disable(event_loop: &ActiveEventLoop, err: Error) {
+    log::error!("mirror window disabled: {err}");
+
+    if let Ok(mut mirror) = MIRROR.lock() {
+        *mirror = None;
+    }
+
+    event_loop.exit();
+}
+
+#[derive(Default)]
+struct MirrorApp {
+    vulkan_window: Option<VulkanWindow>,
+    frame: Option<MirrorFrame>,
+}
+
+impl ApplicationHandler<MirrorFrame> for MirrorApp {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        log::debug!("mirror resumed");
+        if self.vulkan_window.is_none() {
+            match VulkanWindow::new(event_loop) {
+                Ok(vulkan_window) => self.vulkan_window = Some(vulkan_window),
+                Err(err) => disable(event_loop, err),
+            }
+        }
+    }
+
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, frame: MirrorFrame) {
+        self.frame = Some(frame);
+        if let Some(vulkan_window) = self.vulkan_window.as_ref() {
+            vulkan_window.window.request_redraw();
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
+        let Some(vulkan_window) = self.vulkan_window.as_mut() else {
+            return;
+        };
+
+        match event {
+            WindowEvent::CloseRequested => {
+                log::debug!("mirror window closed");
+                event_loop.exit();
+            }
+            WindowEvent::Resized(_) => {
+                vulkan_window.set_window_resized();
+            }
+            WindowEvent::RedrawRequested => {
+                if let Some(frame) = self.frame.as_ref() {
+                    if let Err(err) = vulkan_window.render(frame) {
+                        self.vulkan_window = None;
+                        disable(event_loop, err);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
@@ -1,6 +1,11 @@
 use crate::{instance::api::with_instance, prelude::*, system::HMD_SYSTEM_ID};
 
+pub mod capture;
+pub mod compositor;
 pub mod frame;
+pub mod layer;
+#[cfg(feature = "mirror")]
+pub mod mirror;
 pub mod swapchain;
 pub mod view;
 
@@ -24,13 +29,16 @@ pub extern "system" fn enumerate_blend_modes(
 
     let count_out = unsafe { &mut *count_out };
 
-    with_instance(xr_instance.into_raw(), |_instance| {
+    with_instance(xr_instance.into_raw(), |instance| {
+        let blend_modes = instance.profile.environment_blend_modes();
+
+        *count_out = blend_modes.len() as u32;
+
         if capacity_in == 0 {
-            *count_out = 1;
             return Ok(());
         }
 
-        if *count_out != 1 {
+        if (capacity_in as usize) < blend_modes.len() {
             return Err(xr::Result::ERROR_SIZE_INSUFFICIENT.into());
         }
 
@@ -38,7 +46,9 @@ pub extern "system" fn enumerate_blend_modes(
             return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
         }
 
-        unsafe { *blend_mode = xr::EnvironmentBlendMode::OPAQUE }
+        unsafe {
+            std::ptr::copy_nonoverlapping(blend_modes.as_ptr(), blend_mode, blend_modes.len())
+        }
 
         Ok(())
     })
@@ -1,30 +1,91 @@
 use std::{
     cell::UnsafeCell,
     collections::{HashMap, VecDeque},
-    sync::{LazyLock, Mutex, atomic},
+    sync::{Arc, LazyLock, Mutex, atomic},
 };
 
-use ash::vk::Handle;
+use ash::vk::Handle as _;
 
 use crate::{
+    handle::{self, Handle},
     prelude::*,
-    session::{GraphicsBinding, SimulatedSession, with_session},
+    session::{
+        GraphicsBinding, SimulatedSession, check_session_lost, with_session, with_session_unchecked,
+    },
     utils::with_obj_instance,
 };
 
-const SUPPORTED_SWAPCHAIN_FORMATS: &[i64] = &[
-    ash::vk::Format::R8G8B8_UNORM.as_raw() as i64,
-    ash::vk::Format::R8G8B8_SNORM.as_raw() as i64,
-    ash::vk::Format::R8G8B8_UINT.as_raw() as i64,
-    ash::vk::Format::R8G8B8_SINT.as_raw() as i64,
-    ash::vk::Format::R8G8B8_SRGB.as_raw() as i64,
-    ash::vk::Format::R8G8B8A8_UNORM.as_raw() as i64,
-    ash::vk::Format::R8G8B8A8_SNORM.as_raw() as i64,
-    ash::vk::Format::R8G8B8A8_UINT.as_raw() as i64,
-    ash::vk::Format::R8G8B8A8_SINT.as_raw() as i64,
-    ash::vk::Format::R8G8B8A8_SRGB.as_raw() as i64,
+/// offered when the device can render to them, in the order the runtime prefers them
+const COLOR_SWAPCHAIN_FORMATS: &[ash::vk::Format] = &[
+    ash::vk::Format::R8G8B8A8_SRGB,
+    ash::vk::Format::B8G8R8A8_SRGB,
+    ash::vk::Format::R8G8B8_SRGB,
+    ash::vk::Format::R8G8B8A8_UNORM,
+    ash::vk::Format::B8G8R8A8_UNORM,
+    ash::vk::Format::R8G8B8_UNORM,
+    ash::vk::Format::R16G16B16A16_SFLOAT,
+    ash::vk::Format::A2B10G10R10_UNORM_PACK32,
+    ash::vk::Format::R8G8B8A8_SNORM,
+    ash::vk::Format::R8G8B8_SNORM,
+    ash::vk::Format::R8G8B8A8_UINT,
+    ash::vk::Format::R8G8B8_UINT,
+    ash::vk::Format::R8G8B8A8_SINT,
+    ash::vk::Format::R8G8B8_SINT,
 ];
 
+/// offered when the device can use them as depth attachments, in the order the runtime prefers
+/// them
+const DEPTH_SWAPCHAIN_FORMATS: &[ash::vk::Format] = &[
+    ash::vk::Format::D32_SFLOAT,
+    ash::vk::Format::D24_UNORM_S8_UINT,
+    ash::vk::Format::D16_UNORM,
+    ash::vk::Format::D32_SFLOAT_S8_UINT,
+];
+
+fn format_features(
+    graphics_binding: &GraphicsBinding,
+    format: ash::vk::Format,
+) -> ash::vk::FormatFeatureFlags {
+    unsafe {
+        graphics_binding
+            .instance
+            .get_physical_device_format_properties(graphics_binding.physical_device, format)
+    }
+    .optimal_tiling_features
+}
+
+/// Formats the swapchains of a session can use, color formats first.
+pub fn supported_formats(graphics_binding: &GraphicsBinding) -> Vec<i64> {
+    let formats_with = |formats: &'static [ash::vk::Format], feature| {
+        formats
+            .iter()
+            .filter(move |&&format| format_features(graphics_binding, format).contains(feature))
+            .map(|format| format.as_raw() as i64)
+    };
+
+    formats_with(
+        COLOR_SWAPCHAIN_FORMATS,
+        ash::vk::FormatFeatureFlags::COLOR_ATTACHMENT,
+    )
+    .chain(formats_with(
+        DEPTH_SWAPCHAIN_FORMATS,
+        ash::vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+    ))
+    .collect()
+}
+
+pub fn aspect_mask(format: ash::vk::Format) -> ash::vk::ImageAspectFlags {
+    match format {
+        ash::vk::Format::D16_UNORM | ash::vk::Format::D32_SFLOAT => {
+            ash::vk::ImageAspectFlags::DEPTH
+        }
+        ash::vk::Format::D24_UNORM_S8_UINT | ash::vk::Format::D32_SFLOAT_S8_UINT => {
+            ash::vk::ImageAspectFlags::DEPTH | ash::vk::ImageAspectFlags::STENCIL
+        }
+        _ => ash::vk::ImageAspectFlags::COLOR,
+    }
+}
+
 pub extern "system" fn enumerate_formats(
     xr_session: xr::Session,
     capacity_in: u32,
@@ -35,13 +96,16 @@ pub extern "system" fn enumerate_formats(
 
     log::debug!("enumerate formats: {:?}", capacity_in);
 
-    with_session(xr_session.into_raw(), |_session| {
+    with_session(xr_session.into_raw(), |session| {
+        let supported_formats = supported_formats(&session.graphics_binding);
+
+        *count_out = supported_formats.len() as u32;
+
         if capacity_in == 0 {
-            *count_out = SUPPORTED_SWAPCHAIN_FORMATS.len() as u32;
             return Ok(());
         }
 
-        if *count_out != SUPPORTED_SWAPCHAIN_FORMATS.len() as u32 {
+        if capacity_in < *count_out {
             return Err(xr::Result::ERROR_SIZE_INSUFFICIENT.into());
         }
 
@@ -50,7 +114,7 @@ pub extern "system" fn enumerate_formats(
         }
 
         unsafe {
-            for (i, format) in SUPPORTED_SWAPCHAIN_FORMATS.iter().enumerate() {
+            for (i, format) in supported_formats.iter().enumerate() {
                 *formats.add(i) = *format;
             }
         }
@@ -89,6 +153,10 @@ pub extern "system" fn create(
 
         *xr_swapchain = xr::Swapchain::from_raw(next_id);
 
+        handle::register(
+            Handle::Swapchain(next_id),
+            Some(Handle::Session(session.id)),
+        )?;
         session.add_swapchain(next_id)
     })
     .into_xr_result()
@@ -165,12 +233,43 @@ pub extern "system" fn wait_image(
 
     let info = unsafe { &*info };
 
+    if info.ty != xr::StructureType::SWAPCHAIN_IMAGE_WAIT_INFO {
+        return xr::Result::ERROR_VALIDATION_FAILURE;
+    }
+
+    // the swapchains stay unlocked while the GPU finishes with the image
+    let res = with_swapchain(xr_swapchain.into_raw(), |swapchain| {
+        swapchain.acquired_image_fence()
+    });
+
+    let (device, fence) = match res {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
+    match unsafe { device.wait_for_fences(&[fence], true, fence_timeout(info.timeout)) } {
+        Ok(()) => (),
+        Err(ash::vk::Result::TIMEOUT) => {
+            log::debug!("[{}] wait_image timed out", xr_swapchain.into_raw());
+            return xr::Result::TIMEOUT_EXPIRED;
+        }
+        Err(err) => return Error::from(err).into(),
+    }
+
     with_swapchain(xr_swapchain.into_raw(), |swapchain| {
         swapchain.wait_image(info)
     })
     .into_xr_result()
 }
 
+/// Vulkan fence timeout for an `XrSwapchainImageWaitInfo` one, which can also be infinite.
+fn fence_timeout(timeout: xr::Duration) -> u64 {
+    match timeout {
+        xr::Duration::INFINITE => u64::MAX,
+        timeout => timeout.as_nanos().max(0) as u64,
+    }
+}
+
 #[allow(unreachable_code)]
 pub extern "system" fn release_image(
     xr_swapchain: xr::Swapchain,
@@ -189,27 +288,16 @@ pub extern "system" fn destroy(xr_obj: xr::Swapchain) -> xr::Result {
         return xr::Result::ERROR_HANDLE_INVALID;
     }
 
-    let instance_id = xr_obj.into_raw();
-
-    if INSTANCES
-        .lock()
-        .expect("couldn't acquire instances")
-        .remove(&instance_id)
-        .is_some()
-    {
-        log::debug!("destroyed {instance_id}");
-    } else {
-        log::debug!("instance {instance_id} not found");
-    }
-
-    xr::Result::SUCCESS
+    handle::destroy(Handle::Swapchain(xr_obj.into_raw())).into_xr_result()
 }
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct SimulatedSwapchain {
+    instance_id: u64,
     session_id: u64,
     id: u64,
+    graphics_binding: GraphicsBinding,
     create_flags: xr::SwapchainCreateFlags,
     usage_flags: xr::SwapchainUsageFlags,
     format: ash::vk::Format,
@@ -232,12 +320,22 @@ impl SimulatedSwapchain {
         id: u64,
         create_info: &xr::SwapchainCreateInfo,
     ) -> Result<Self> {
-        if !SUPPORTED_SWAPCHAIN_FORMATS.contains(&create_info.format) {
+        if !supported_formats(&session.graphics_binding).contains(&create_info.format) {
             return Err(xr::Result::ERROR_SWAPCHAIN_FORMAT_UNSUPPORTED.into());
         }
 
+        validate_create_info(session, create_info)?;
+
         let format = ash::vk::Format::from_raw(create_info.format as i32);
 
+        let features = format_features(&session.graphics_binding, format);
+        for (usage, feature) in USAGE_FORMAT_FEATURES {
+            if create_info.usage_flags.contains(*usage) && !features.contains(*feature) {
+                log::error!("[{}] {format:?} doesn't support {usage:?}", session.id);
+                return Err(xr::Result::ERROR_FEATURE_UNSUPPORTED.into());
+            }
+        }
+
         let num_images = if create_info
             .create_flags
             .contains(xr::SwapchainCreateFlags::STATIC_IMAGE)
@@ -250,13 +348,37 @@ impl SimulatedSwapchain {
         let mut available_images = VecDeque::with_capacity(num_images);
         let mut images = Vec::with_capacity(num_images);
         for i in 0..num_images {
-            images.push(OffscreenImage::new(&session.graphics_binding, create_info)?);
+            match OffscreenImage::new(&session.graphics_binding, create_info) {
+                Ok(image) => images.push(image),
+                Err(err) => {
+                    for image in &images {
+                        image.cleanup(&session.graphics_binding.device);
+                    }
+                    return Err(err);
+                }
+            }
             available_images.push_back(i);
         }
 
+        let res = submit_commands(&session.graphics_binding, |device, command_buffer| {
+            for image in &images {
+                image.transition_to_attachment(device, command_buffer);
+            }
+            Ok(())
+        });
+
+        if let Err(err) = res {
+            for image in &images {
+                image.cleanup(&session.graphics_binding.device);
+            }
+            return Err(err);
+        }
+
         Ok(Self {
+            instance_id: session.instance_id,
             session_id: session.id,
             id,
+            graphics_binding: session.graphics_binding.clone(),
             create_flags: create_info.create_flags,
             usage_flags: create_info.usage_flags,
             format,
@@ -285,6 +407,19 @@ impl SimulatedSwapchain {
         Ok(index)
     }
 
+    /// The fence of the image to be waited next, signaled once the GPU is done with the work
+    /// submitted before the image was last released.
+    pub fn acquired_image_fence(&self) -> Result<(Arc<ash::Device>, ash::vk::Fence)> {
+        let Some(&index) = self.acquired_images.front() else {
+            return Err(xr::Result::ERROR_CALL_ORDER_INVALID.into());
+        };
+
+        Ok((
+            self.graphics_binding.device.clone(),
+            self.images[index].release_fence,
+        ))
+    }
+
     pub fn wait_image(&mut self, info: &xr::SwapchainImageWaitInfo) -> Result<xr::Result> {
         log::debug!("[{}] wait_image ({info:?})", self.id);
         if self.acquired_images.front().is_none() {
@@ -302,16 +437,45 @@ impl SimulatedSwapchain {
         &mut self,
         info: Option<&xr::SwapchainImageReleaseInfo>,
     ) -> Result<xr::Result> {
-        let Some(index) = self.waited_images.pop_front() else {
+        if let Some(info) = info {
+            if info.ty != xr::StructureType::SWAPCHAIN_IMAGE_RELEASE_INFO {
+                return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+            }
+        }
+
+        let Some(&index) = self.waited_images.front() else {
             return Err(xr::Result::ERROR_CALL_ORDER_INVALID.into());
         };
 
+        // the app's rendering to the image is already submitted to its queue, an empty submission
+        // after it signals the fence once that work is done
+        let fence = self.images[index].release_fence;
+        unsafe { self.graphics_binding.device.reset_fences(&[fence])? };
+        self.graphics_binding.queue_submit(&[], fence)?;
+
+        self.waited_images.pop_front();
         self.released_images.push_back(index);
 
         log::debug!("[{}] release_image ({info:?}) -> {index}", self.id);
         Ok(xr::Result::SUCCESS)
     }
 
+    pub fn face_count(&self) -> u32 {
+        self.face_count
+    }
+
+    /// layers of the images, each array element has a layer per face
+    pub fn array_layer_count(&self) -> u32 {
+        self.array_size * self.face_count
+    }
+
+    /// the oldest released image, the one the compositor reads for the frame being ended
+    pub fn released_image(&self) -> Option<&OffscreenImage> {
+        self.released_images
+            .front()
+            .map(|&index| &self.images[index])
+    }
+
     pub fn free_image(&mut self) -> Result<()> {
         let Some(index) = self.released_images.pop_front() else {
             return Err(xr::Result::ERROR_CALL_ORDER_INVALID.into());
@@ -325,14 +489,111 @@ impl SimulatedSwapchain {
 
 impl Drop for SimulatedSwapchain {
     fn drop(&mut self) {
-        with_session(self.session_id, |session| {
-            for image in self.images.iter() {
-                image.cleanup(session.graphics_binding.device.as_ref());
-            }
-            Ok(())
-        })
-        .ok();
+        let device = self.graphics_binding.device.as_ref();
+
+        // the images may still be in use by the app's last submissions
+        let fences = self
+            .images
+            .iter()
+            .map(|image| image.release_fence)
+            .collect::<Vec<_>>();
+        if let Err(err) = unsafe { device.wait_for_fences(&fences, true, u64::MAX) } {
+            log::error!("[{}] couldn't wait for the images: {err}", self.id);
+        }
+
+        for image in self.images.iter() {
+            image.cleanup(device);
+        }
+    }
+}
+
+/// Checks the image properties against the limits of the device profile and the Vulkan device.
+fn validate_create_info(
+    session: &SimulatedSession,
+    create_info: &xr::SwapchainCreateInfo,
+) -> Result<()> {
+    let graphics = &session.profile.graphics;
+    let max_sample_count = session
+        .profile
+        .views
+        .iter()
+        .map(|view| view.max_sample_count)
+        .max()
+        .unwrap_or(1);
+
+    let limits = unsafe {
+        session
+            .graphics_binding
+            .instance
+            .get_physical_device_properties(session.graphics_binding.physical_device)
+    }
+    .limits;
+
+    let max_mip_count = u32::BITS - create_info.width.max(create_info.height).leading_zeros();
+
+    if create_info.width == 0
+        || create_info.height == 0
+        || create_info.width > graphics.max_swapchain_image_width
+        || create_info.height > graphics.max_swapchain_image_height
+    {
+        log::error!(
+            "[{}] swapchain size {}x{} out of range",
+            session.id,
+            create_info.width,
+            create_info.height
+        );
+        return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+    }
+
+    if !create_info.sample_count.is_power_of_two() || create_info.sample_count > max_sample_count {
+        log::error!(
+            "[{}] swapchain sample count {} unsupported, the maximum is {max_sample_count}",
+            session.id,
+            create_info.sample_count
+        );
+        return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+    }
+
+    if create_info.mip_count == 0 || create_info.mip_count > max_mip_count {
+        log::error!(
+            "[{}] swapchain mip count {} out of range",
+            session.id,
+            create_info.mip_count
+        );
+        return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+    }
+
+    // multisampled images can't have mip chains in Vulkan
+    if create_info.sample_count > 1 && create_info.mip_count > 1 {
+        return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
     }
+
+    match create_info.face_count {
+        1 => (),
+        6 if create_info.width == create_info.height => (),
+        _ => {
+            log::error!(
+                "[{}] swapchain face count {} unsupported",
+                session.id,
+                create_info.face_count
+            );
+            return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+        }
+    }
+
+    let array_layers = create_info.array_size.checked_mul(create_info.face_count);
+    if create_info.array_size == 0
+        || array_layers.is_none_or(|array_layers| array_layers > limits.max_image_array_layers)
+    {
+        log::error!(
+            "[{}] swapchain array size {} out of range",
+            session.id,
+            create_info.array_size
+        );
+        return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+    }
+
+    Ok(())
 }
 
 pub fn find_memory_type_index(
@@ -381,20 +642,125 @@ const USAGE_FLAGS_MAP: &[(xr::SwapchainUsageFlags, u32)] = &[
     ),
 ];
 
+/// format features the device must have for each usage
+const USAGE_FORMAT_FEATURES: &[(xr::SwapchainUsageFlags, ash::vk::FormatFeatureFlags)] = &[
+    (
+        xr::SwapchainUsageFlags::COLOR_ATTACHMENT,
+        ash::vk::FormatFeatureFlags::COLOR_ATTACHMENT,
+    ),
+    (
+        xr::SwapchainUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        ash::vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+    ),
+    (
+        xr::SwapchainUsageFlags::UNORDERED_ACCESS,
+        ash::vk::FormatFeatureFlags::STORAGE_IMAGE,
+    ),
+    (
+        xr::SwapchainUsageFlags::TRANSFER_SRC,
+        ash::vk::FormatFeatureFlags::TRANSFER_SRC,
+    ),
+    (
+        xr::SwapchainUsageFlags::TRANSFER_DST,
+        ash::vk::FormatFeatureFlags::TRANSFER_DST,
+    ),
+    (
+        xr::SwapchainUsageFlags::SAMPLED,
+        ash::vk::FormatFeatureFlags::SAMPLED_IMAGE,
+    ),
+];
+
+/// Records one-off commands and submits them to the app's queue, waiting for them to finish.
+pub fn submit_commands<F>(graphics_binding: &GraphicsBinding, record: F) -> Result<()>
+where
+    F: FnOnce(&ash::Device, ash::vk::CommandBuffer) -> Result<()>,
+{
+    let device = graphics_binding.device.as_ref();
+
+    let command_pool = unsafe {
+        device.create_command_pool(
+            &ash::vk::CommandPoolCreateInfo {
+                flags: ash::vk::CommandPoolCreateFlags::TRANSIENT,
+                queue_family_index: graphics_binding.queue_family_index,
+                ..Default::default()
+            },
+            None,
+        )?
+    };
+
+    let fence = match unsafe { device.create_fence(&ash::vk::FenceCreateInfo::default(), None) } {
+        Ok(fence) => fence,
+        Err(err) => {
+            unsafe { device.destroy_command_pool(command_pool, None) };
+            return Err(err.into());
+        }
+    };
+
+    let res = (|| -> Result<()> {
+        let command_buffer = unsafe {
+            device.allocate_command_buffers(&ash::vk::CommandBufferAllocateInfo {
+                command_pool,
+                level: ash::vk::CommandBufferLevel::PRIMARY,
+                command_buffer_count: 1,
+                ..Default::default()
+            })?[0]
+        };
+
+        unsafe {
+            device.begin_command_buffer(
+                command_buffer,
+                &ash::vk::CommandBufferBeginInfo {
+                    flags: ash::vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+                    ..Default::default()
+                },
+            )?;
+        }
+
+        record(device, command_buffer)?;
+
+        unsafe {
+            device.end_command_buffer(command_buffer)?;
+
+            let command_buffers = [command_buffer];
+            let submit_info = ash::vk::SubmitInfo::default().command_buffers(&command_buffers);
+
+            graphics_binding.queue_submit(&[submit_info], fence)?;
+            device.wait_for_fences(&[fence], true, u64::MAX)?;
+        }
+
+        Ok(())
+    })();
+
+    unsafe {
+        device.destroy_fence(fence, None);
+        device.destroy_command_pool(command_pool, None);
+    }
+
+    res
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct OffscreenImage {
     pub(crate) width: u32,
     pub(crate) height: u32,
     pub(crate) format: ash::vk::Format,
+    pub(crate) samples: ash::vk::SampleCountFlags,
+    pub(crate) array_layers: u32,
+    pub(crate) mip_count: u32,
+    /// layout the image is in while the app has it and when the compositor reads it
+    pub(crate) layout: ash::vk::ImageLayout,
     pub(crate) image: ash::vk::Image,
     pub(crate) image_memory: ash::vk::DeviceMemory,
     pub(crate) image_view: ash::vk::ImageView,
+    /// signaled once the GPU finished the work submitted before the image was released
+    pub(crate) release_fence: ash::vk::Fence,
 }
 
 impl OffscreenImage {
     pub fn cleanup(&self, device: &ash::Device) {
         unsafe {
+            device.destroy_fence(self.release_fence, None);
             device.destroy_image_view(self.image_view, None);
             device.free_memory(self.image_memory, None);
             device.destroy_image(self.image, None);
@@ -406,6 +772,7 @@ impl OffscreenImage {
         create_info: &xr::SwapchainCreateInfo,
     ) -> Result<Self> {
         let format = ash::vk::Format::from_raw(create_info.format as i32);
+        let samples = ash::vk::SampleCountFlags::from_raw(create_info.sample_count);
 
         let physical_device_memory_properties = unsafe {
             graphics_binding
@@ -414,7 +781,8 @@ impl OffscreenImage {
         };
 
         let (color_image, color_image_memory, color_image_view) = {
-            let mut usage = ash::vk::ImageUsageFlags::from_raw(0);
+            // the compositor copies from the images to capture them
+            let mut usage = ash::vk::ImageUsageFlags::TRANSFER_SRC;
             for (from, to) in USAGE_FLAGS_MAP {
                 if create_info
                     .usage_flags
@@ -424,7 +792,29 @@ impl OffscreenImage {
                 }
             }
 
+            // cube faces are consecutive layers of each array element
+            let array_layers = create_info.array_size * create_info.face_count;
+            let (flags, view_type) = match (create_info.face_count, create_info.array_size) {
+                (6, 1) => (
+                    ash::vk::ImageCreateFlags::CUBE_COMPATIBLE,
+                    ash::vk::ImageViewType::CUBE,
+                ),
+                (6, _) => (
+                    ash::vk::ImageCreateFlags::CUBE_COMPATIBLE,
+                    ash::vk::ImageViewType::CUBE_ARRAY,
+                ),
+                (_, 1) => (
+                    ash::vk::ImageCreateFlags::empty(),
+                    ash::vk::ImageViewType::TYPE_2D,
+                ),
+                _ => (
+                    ash::vk::ImageCreateFlags::empty(),
+                    ash::vk::ImageViewType::TYPE_2D_ARRAY,
+                ),
+            };
+
             let image_create_info = ash::vk::ImageCreateInfo {
+                flags,
                 image_type: ash::vk::ImageType::TYPE_2D,
                 format,
                 extent: ash::vk::Extent3D {
@@ -433,8 +823,8 @@ impl OffscreenImage {
                     depth: 1,
                 },
                 mip_levels: create_info.mip_count,
-                array_layers: 1,
-                samples: ash::vk::SampleCountFlags::TYPE_1,
+                array_layers,
+                samples,
                 usage,
                 ..Default::default()
             };
@@ -445,58 +835,196 @@ impl OffscreenImage {
                     .create_image(&image_create_info, None)?
             };
 
-            let mem_req = unsafe { graphics_binding.device.get_image_memory_requirements(image) };
-            let mem_type_index = find_memory_type_index(
-                &mem_req,
+            let memory = match allocate_image_memory(
+                graphics_binding,
                 &physical_device_memory_properties,
-                ash::vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            )
-            .expect("Failed to find suitable memory type for color image.");
-
-            let alloc_info = ash::vk::MemoryAllocateInfo {
-                allocation_size: mem_req.size,
-                memory_type_index: mem_type_index,
-                ..Default::default()
-            };
-
-            let memory = unsafe { graphics_binding.device.allocate_memory(&alloc_info, None)? };
-            unsafe {
-                graphics_binding
-                    .device
-                    .bind_image_memory(image, memory, 0)?
+                image,
+            ) {
+                Ok(memory) => memory,
+                Err(err) => {
+                    unsafe { graphics_binding.device.destroy_image(image, None) };
+                    return Err(err);
+                }
             };
 
             let view_create_info = ash::vk::ImageViewCreateInfo {
                 image,
-                view_type: ash::vk::ImageViewType::TYPE_2D,
+                view_type,
                 format,
                 subresource_range: ash::vk::ImageSubresourceRange {
-                    aspect_mask: ash::vk::ImageAspectFlags::COLOR,
+                    aspect_mask: aspect_mask(format),
                     base_mip_level: 0,
-                    level_count: 1,
+                    level_count: create_info.mip_count,
                     base_array_layer: 0,
-                    layer_count: 1,
+                    layer_count: array_layers,
                 },
                 ..Default::default()
             };
 
-            let view = unsafe {
+            let res = unsafe {
                 graphics_binding
                     .device
-                    .create_image_view(&view_create_info, None)?
+                    .create_image_view(&view_create_info, None)
+            };
+
+            let view = match res {
+                Ok(view) => view,
+                Err(err) => {
+                    unsafe {
+                        graphics_binding.device.free_memory(memory, None);
+                        graphics_binding.device.destroy_image(image, None);
+                    }
+                    return Err(err.into());
+                }
             };
             (image, memory, view)
         };
 
+        let res = unsafe {
+            graphics_binding.device.create_fence(
+                &ash::vk::FenceCreateInfo {
+                    flags: ash::vk::FenceCreateFlags::SIGNALED,
+                    ..Default::default()
+                },
+                None,
+            )
+        };
+
+        let release_fence = match res {
+            Ok(fence) => fence,
+            Err(err) => {
+                unsafe {
+                    let device = &graphics_binding.device;
+                    device.destroy_image_view(color_image_view, None);
+                    device.free_memory(color_image_memory, None);
+                    device.destroy_image(color_image, None);
+                }
+                return Err(err.into());
+            }
+        };
+
         Ok(Self {
             width: create_info.width,
             height: create_info.height,
             format,
+            samples,
+            array_layers: create_info.array_size * create_info.face_count,
+            mip_count: create_info.mip_count,
+            layout: attachment_layout(format, create_info.usage_flags),
             image: color_image,
             image_memory: color_image_memory,
             image_view: color_image_view,
+            release_fence,
         })
     }
+
+    /// Records the move of the whole image from its initial undefined layout to the one apps get
+    /// it in.
+    pub fn transition_to_attachment(
+        &self,
+        device: &ash::Device,
+        command_buffer: ash::vk::CommandBuffer,
+    ) {
+        let (dst_access_mask, dst_stage) = layout_access(self.layout);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                ash::vk::PipelineStageFlags::TOP_OF_PIPE,
+                dst_stage,
+                ash::vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[ash::vk::ImageMemoryBarrier {
+                    src_access_mask: ash::vk::AccessFlags::empty(),
+                    dst_access_mask,
+                    old_layout: ash::vk::ImageLayout::UNDEFINED,
+                    new_layout: self.layout,
+                    src_queue_family_index: ash::vk::QUEUE_FAMILY_IGNORED,
+                    dst_queue_family_index: ash::vk::QUEUE_FAMILY_IGNORED,
+                    image: self.image,
+                    subresource_range: ash::vk::ImageSubresourceRange {
+                        aspect_mask: aspect_mask(self.format),
+                        base_mip_level: 0,
+                        level_count: self.mip_count,
+                        base_array_layer: 0,
+                        layer_count: self.array_layers,
+                    },
+                    ..Default::default()
+                }],
+            );
+        }
+    }
+}
+
+/// Allocates device local memory for a swapchain image and binds it, nothing is left allocated
+/// on failure.
+fn allocate_image_memory(
+    graphics_binding: &GraphicsBinding,
+    memory_properties: &ash::vk::PhysicalDeviceMemoryProperties,
+    image: ash::vk::Image,
+) -> Result<ash::vk::DeviceMemory> {
+    let device = &graphics_binding.device;
+
+    let mem_req = unsafe { device.get_image_memory_requirements(image) };
+    let Some(mem_type_index) = find_memory_type_index(
+        &mem_req,
+        memory_properties,
+        ash::vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    ) else {
+        return Err("no device local memory for the swapchain image".into());
+    };
+
+    let alloc_info = ash::vk::MemoryAllocateInfo {
+        allocation_size: mem_req.size,
+        memory_type_index: mem_type_index,
+        ..Default::default()
+    };
+
+    let memory = unsafe { device.allocate_memory(&alloc_info, None)? };
+    if let Err(err) = unsafe { device.bind_image_memory(image, memory, 0) } {
+        unsafe { device.free_memory(memory, None) };
+        return Err(err.into());
+    }
+
+    Ok(memory)
+}
+
+/// Layout of the images while apps render to them. XR_KHR_vulkan_enable hands out color and
+/// depth attachments in their optimal layouts, images that can't be attachments stay general.
+fn attachment_layout(
+    format: ash::vk::Format,
+    usage_flags: xr::SwapchainUsageFlags,
+) -> ash::vk::ImageLayout {
+    if aspect_mask(format) == ash::vk::ImageAspectFlags::COLOR {
+        if usage_flags.contains(xr::SwapchainUsageFlags::COLOR_ATTACHMENT) {
+            return ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL;
+        }
+    } else if usage_flags.contains(xr::SwapchainUsageFlags::DEPTH_STENCIL_ATTACHMENT) {
+        return ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL;
+    }
+
+    ash::vk::ImageLayout::GENERAL
+}
+
+/// The writes apps make to an image in the layout and the stage they're made at.
+pub fn layout_access(
+    layout: ash::vk::ImageLayout,
+) -> (ash::vk::AccessFlags, ash::vk::PipelineStageFlags) {
+    match layout {
+        ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (
+            ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        ),
+        ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => (
+            ash::vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ash::vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+        ),
+        _ => (
+            ash::vk::AccessFlags::MEMORY_WRITE,
+            ash::vk::PipelineStageFlags::ALL_COMMANDS,
+        ),
+    }
 }
 
 static INSTANCE_COUNTER: atomic::AtomicU64 = atomic::AtomicU64::new(1);
@@ -506,9 +1034,152 @@ type SharedSimulatedSwapchain = UnsafeCell<SimulatedSwapchain>;
 static INSTANCES: LazyLock<Mutex<HashMap<u64, SharedSimulatedSwapchain>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
-pub fn with_swapchain<T, F>(obj_id: u64, f: F) -> Result<T>
+/// Releases a destroyed swapchain and its images, the session forgets about it.
+pub fn remove(swapchain_id: u64) -> Result<()> {
+    let Some(swapchain) = INSTANCES.lock()?.remove(&swapchain_id) else {
+        return Err(xr::Result::ERROR_HANDLE_INVALID.into());
+    };
+
+    // dropped with the swapchains unlocked, it waits for the GPU to be done with the images
+    let swapchain = swapchain.into_inner();
+
+    with_session_unchecked(swapchain.session_id, |session| {
+        session.swapchain_ids.remove(&swapchain_id);
+        Ok(())
+    })?;
+
+    log::debug!("destroyed {swapchain_id}");
+    Ok(())
+}
+
+pub fn with_swapchain<T, F>(obj_id: u64, mut f: F) -> Result<T>
 where
     F: FnMut(&mut SimulatedSwapchain) -> Result<T>,
 {
-    with_obj_instance(&INSTANCES, obj_id, f)
+    let res = with_obj_instance(&INSTANCES, obj_id, |swapchain| {
+        check_session_lost(swapchain.instance_id, swapchain.session_id)?;
+        f(swapchain)
+    });
+
+    match res {
+        Ok(res) => Ok(res),
+        Err(err) => match err {
+            Error::ExpectedSome(err) => {
+                log::error!("error: {err}");
+                Err(xr::Result::ERROR_HANDLE_INVALID.into())
+            }
+            _ => Err(err),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_timeouts_become_fence_timeouts() {
+        assert_eq!(fence_timeout(xr::Duration::INFINITE), u64::MAX);
+        assert_eq!(
+            fence_timeout(xr::Duration::from_nanos(5_000_000)),
+            5_000_000
+        );
+        assert_eq!(fence_timeout(xr::Duration::NONE), 0);
+        assert_eq!(fence_timeout(xr::Duration::from_nanos(-1)), 0);
+    }
+
+    #[test]
+    fn attachments_are_handed_out_in_their_optimal_layout() {
+        assert_eq!(
+            attachment_layout(
+                ash::vk::Format::R8G8B8A8_SRGB,
+                xr::SwapchainUsageFlags::COLOR_ATTACHMENT | xr::SwapchainUsageFlags::SAMPLED
+            ),
+            ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        );
+        assert_eq!(
+            attachment_layout(
+                ash::vk::Format::D32_SFLOAT,
+                xr::SwapchainUsageFlags::DEPTH_STENCIL_ATTACHMENT
+            ),
+            ash::vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        );
+        // a color format can't be a depth attachment and the other way around
+        assert_eq!(
+            attachment_layout(
+                ash::vk::Format::R8G8B8A8_SRGB,
+                xr::SwapchainUsageFlags::DEPTH_STENCIL_ATTACHMENT
+            ),
+            ash::vk::ImageLayout::GENERAL
+        );
+        assert_eq!(
+            attachment_layout(
+                ash::vk::Format::D32_SFLOAT,
+                xr::SwapchainUsageFlags::COLOR_ATTACHMENT
+            ),
+            ash::vk::ImageLayout::GENERAL
+        );
+        assert_eq!(
+            attachment_layout(
+                ash::vk::Format::R8G8B8A8_UNORM,
+                xr::SwapchainUsageFlags::UNORDERED_ACCESS
+            ),
+            ash::vk::ImageLayout::GENERAL
+        );
+    }
+
+    #[test]
+    fn writes_are_waited_for_at_their_stage() {
+        assert_eq!(
+            layout_access(ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+            (
+                ash::vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            )
+        );
+        assert_eq!(
+            layout_access(ash::vk::ImageLayout::GENERAL),
+            (
+                ash::vk::AccessFlags::MEMORY_WRITE,
+                ash::vk::PipelineStageFlags::ALL_COMMANDS
+            )
+        );
+    }
+
+    #[test]
+    fn memory_types_must_be_allowed_and_have_the_flags() {
+        let mut memory_properties = ash::vk::PhysicalDeviceMemoryProperties {
+            memory_type_count: 3,
+            ..Default::default()
+        };
+        memory_properties.memory_types[0].property_flags =
+            ash::vk::MemoryPropertyFlags::HOST_VISIBLE;
+        memory_properties.memory_types[1].property_flags =
+            ash::vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        memory_properties.memory_types[2].property_flags =
+            ash::vk::MemoryPropertyFlags::DEVICE_LOCAL | ash::vk::MemoryPropertyFlags::HOST_VISIBLE;
+        let memory_req = |memory_type_bits| ash::vk::MemoryRequirements {
+            memory_type_bits,
+            ..Default::default()
+        };
+        let device_local = ash::vk::MemoryPropertyFlags::DEVICE_LOCAL;
+
+        assert_eq!(
+            find_memory_type_index(&memory_req(0b111), &memory_properties, device_local),
+            Some(1)
+        );
+        assert_eq!(
+            find_memory_type_index(&memory_req(0b101), &memory_properties, device_local),
+            Some(2)
+        );
+        assert_eq!(
+            find_memory_type_index(&memory_req(0b001), &memory_properties, device_local),
+            None
+        );
+        // types past the count don't exist
+        assert_eq!(
+            find_memory_type_index(&memory_req(0b1000), &memory_properties, device_local),
+            None
+        );
+    }
 }
@@ -1,6 +1,11 @@
-use crate::{prelude::*, session::with_session};
+use crate::{
+    device::with_device,
+    math::{pose_inverse, pose_multiply},
+    prelude::*,
+    session::with_session,
+    spaces::SpaceSnapshot,
+};
 
-#[allow(unreachable_code)]
 pub extern "system" fn locate_views(
     xr_session: xr::Session,
     info: *const xr::ViewLocateInfo,
@@ -9,42 +14,80 @@ pub extern "system" fn locate_views(
     count_out: *mut u32,
     views: *mut xr::View,
 ) -> xr::Result {
-    if info.is_null() || view_state.is_null() || count_out.is_null() || views.is_null() {
+    if info.is_null() || view_state.is_null() || count_out.is_null() {
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
-    let info = unsafe { &*info };
+    let (info, view_state, count_out) = unsafe { (&*info, &mut *view_state, &mut *count_out) };
+
+    if info.ty != xr::StructureType::VIEW_LOCATE_INFO
+        || view_state.ty != xr::StructureType::VIEW_STATE
+    {
+        return xr::Result::ERROR_VALIDATION_FAILURE;
+    }
 
     if !matches!(
         info.view_configuration_type,
         xr::ViewConfigurationType::PRIMARY_STEREO
     ) {
-        return xr::Result::ERROR_VALIDATION_FAILURE;
+        return xr::Result::ERROR_VIEW_CONFIGURATION_TYPE_UNSUPPORTED;
     }
 
-    if capacity_in != 2 {
-        return xr::Result::ERROR_SIZE_INSUFFICIENT;
+    if info.display_time.as_nanos() <= 0 {
+        return xr::Result::ERROR_TIME_INVALID;
     }
 
-    if views.is_null() {
-        return xr::Result::ERROR_VALIDATION_FAILURE;
-    }
-
-    unsafe { *count_out = 2 }
-
     log::debug!("locate_views {info:?}");
 
+    let space = match SpaceSnapshot::new(info.space.into_raw()) {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
     with_session(xr_session.into_raw(), |session| {
         if !session.space_ids.contains_key(&info.space.into_raw()) {
-            return Err(xr::Result::ERROR_SESSION_LOST.into());
+            return Err(xr::Result::ERROR_HANDLE_INVALID.into());
+        }
+
+        *count_out = 2;
+
+        if capacity_in == 0 {
+            return Ok(());
+        }
+
+        if capacity_in < 2 {
+            return Err(xr::Result::ERROR_SIZE_INSUFFICIENT.into());
         }
 
-        let view_state = unsafe { &mut *view_state };
-        view_state.view_state_flags = xr::ViewStateFlags::from_raw(0b1111);
-        for i in 0..2 {
-            let _view = unsafe { &mut *(views.add(i)) };
+        if views.is_null() {
+            return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
         }
 
+        let Some(space_state) = space.state(session) else {
+            view_state.view_state_flags = xr::ViewStateFlags::EMPTY;
+            return Ok(());
+        };
+
+        let head = with_device(|device| device.head.clone());
+        let base = pose_inverse(&space_state.pose);
+
+        view_state.view_state_flags =
+            xr::ViewStateFlags::ORIENTATION_VALID | xr::ViewStateFlags::POSITION_VALID;
+        if session.profile.tracking.orientation {
+            view_state.view_state_flags |= xr::ViewStateFlags::ORIENTATION_TRACKED;
+        }
+        if session.profile.tracking.position {
+            view_state.view_state_flags |= xr::ViewStateFlags::POSITION_TRACKED;
+        }
+
+        for eye in 0..2 {
+            let view = unsafe { &mut *(views.add(eye)) };
+            view.pose = pose_multiply(&base, &head.eye_pose(eye));
+            view.fov = head.fov[eye];
+        }
+
+        log::debug!("locate_views -> {view_state:?}");
+
         Ok(())
     })
     .into_xr_result()
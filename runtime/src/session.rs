@@ -2,20 +2,38 @@ use std::{
     cell::UnsafeCell,
     collections::{HashMap, HashSet},
     sync::{Arc, LazyLock, Mutex, atomic},
+    time::Duration,
 };
 
-use ash::vk::Handle;
+use ash::vk::Handle as _;
 
 use crate::{
+    clock,
+    device::with_device,
     event::{Event, schedule_event},
-    instance::api::with_instance,
-    loader::START_TIME,
+    handle::{self, Handle},
+    haptics::SimulatedHaptics,
+    input::interaction_profile::{ResolvedBinding, update_interaction_profiles},
+    instance::api::{check_instance_lost, with_instance, with_instance_unchecked},
     prelude::*,
-    rendering::frame::SessionFrame,
+    profile::DeviceProfile,
+    rendering::{capture::FrameCapture, frame::SessionFrame},
     system::HMD_SYSTEM_ID,
     utils::with_obj_instance,
 };
 
+/// Set to `1` to warn when apps rely on the order this runtime happens to do things in, like
+/// beginning a session before polling the event that made it ready.
+pub const STRICT_ORDERING_ENV: &str = "OPENXR_DEVICE_SIMULATOR_STRICT_ORDERING";
+
+static IS_STRICT_ORDERING: LazyLock<bool> = LazyLock::new(|| {
+    let enabled = std::env::var(STRICT_ORDERING_ENV).is_ok_and(|value| value == "1");
+    if enabled {
+        log::info!("warning about non-portable app ordering");
+    }
+    enabled
+});
+
 pub extern "system" fn create(
     xr_instance: xr::Instance,
     create_info: *const xr::SessionCreateInfo,
@@ -46,6 +64,8 @@ pub extern "system" fn create(
                 xr_instance.into_raw(),
                 next_id,
                 create_info,
+                instance.profile.clone(),
+                instance.enabled_extensions.clone(),
             )?),
         );
 
@@ -55,6 +75,10 @@ pub extern "system" fn create(
 
         *xr_session = xr::Session::from_raw(next_id);
 
+        handle::register(
+            Handle::Session(next_id),
+            Some(Handle::Instance(instance.id)),
+        )?;
         instance.set_session(next_id)
     })
     .into_xr_result()
@@ -65,20 +89,7 @@ pub extern "system" fn destroy(xr_obj: xr::Session) -> xr::Result {
         return xr::Result::ERROR_HANDLE_INVALID;
     }
 
-    let instance_id = xr_obj.into_raw();
-
-    if INSTANCES
-        .lock()
-        .expect("couldn't acquire instances")
-        .remove(&instance_id)
-        .is_some()
-    {
-        log::debug!("destroyed {instance_id}");
-    } else {
-        log::debug!("instance {instance_id} not found");
-    }
-
-    xr::Result::SUCCESS
+    handle::destroy(Handle::Session(xr_obj.into_raw())).into_xr_result()
 }
 
 pub extern "system" fn attach_action_sets(
@@ -106,13 +117,16 @@ pub extern "system" fn attach_action_sets(
         )
     };
 
-    with_session(xr_session.into_raw(), |session| {
+    let res = with_session(xr_session.into_raw(), |session| {
         for item in action_sets {
             session.attach_action_set(item.into_raw())?;
         }
         Ok(())
-    })
-    .into_xr_result()
+    });
+
+    // suggested bindings only take effect once the action sets are attached
+    res.and_then(|_| update_interaction_profiles(xr_session.into_raw()))
+        .into_xr_result()
 }
 
 pub extern "system" fn begin(
@@ -150,6 +164,7 @@ pub enum SimulatedSessionSpace {
     Action,
 }
 
+#[derive(Clone)]
 pub struct GraphicsBinding {
     pub(crate) instance: Arc<ash::Instance>,
     pub(crate) physical_device: ash::vk::PhysicalDevice,
@@ -158,6 +173,42 @@ pub struct GraphicsBinding {
     pub(crate) queue_index: u32,
 }
 
+/// Vulkan wants the submissions to a queue externally synchronized, the runtime's own ones take
+/// the lock of the queue they go to. The app shares its queue with the runtime and must not submit
+/// to it while the runtime may do so, during xrReleaseSwapchainImage and xrEndFrame, as
+/// XR_KHR_vulkan_enable requires.
+static QUEUE_LOCKS: LazyLock<Mutex<HashMap<u64, Arc<Mutex<()>>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+impl GraphicsBinding {
+    /// The queue the app submits its rendering to, the runtime submits its own work after it.
+    pub fn queue(&self) -> ash::vk::Queue {
+        unsafe {
+            self.device
+                .get_device_queue(self.queue_family_index, self.queue_index)
+        }
+    }
+
+    /// Submits runtime work to the app's queue, see `QUEUE_LOCKS`.
+    pub fn queue_submit(
+        &self,
+        submits: &[ash::vk::SubmitInfo],
+        fence: ash::vk::Fence,
+    ) -> Result<()> {
+        let queue = self.queue();
+        let queue_lock = QUEUE_LOCKS
+            .lock()?
+            .entry(queue.as_raw())
+            .or_default()
+            .clone();
+
+        let _guard = queue_lock.lock()?;
+        unsafe { self.device.queue_submit(queue, submits, fence)? };
+
+        Ok(())
+    }
+}
+
 impl std::fmt::Debug for GraphicsBinding {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("GraphicsBinding")
@@ -204,17 +255,41 @@ impl TryFrom<&xr::GraphicsBindingVulkanKHR> for GraphicsBinding {
 pub struct SimulatedSession {
     pub(crate) instance_id: u64,
     pub(crate) id: u64,
+    pub(crate) profile: Arc<DeviceProfile>,
+    /// extensions enabled on the instance
+    pub(crate) enabled_extensions: Arc<HashSet<String>>,
     pub(crate) graphics_binding: GraphicsBinding,
     pub(crate) space_ids: HashMap<u64, SimulatedSessionSpace>,
     pub(crate) action_set_ids: HashSet<u64>,
     pub(crate) swapchain_ids: HashSet<u64>,
     pub(crate) state: xr::SessionState,
+    /// the latest state the app polled an event for
+    pub(crate) polled_state: xr::SessionState,
+    pub(crate) created_time: Duration,
     pub(crate) is_running: bool,
+    /// the simulated user wears the headset, sessions only get ready and focused while they do
+    pub(crate) is_headset_worn: bool,
+    /// a system overlay has the input focus, the session stays visible behind it
+    pub(crate) is_overlay_shown: bool,
+    /// the session goes on to exiting once it's ended
+    pub(crate) is_exit_requested: bool,
     pub(crate) frame: SessionFrame,
+    pub(crate) local_origin: xr::Posef,
+    /// interaction profile picked for each top level user path
+    pub(crate) interaction_profiles: HashMap<&'static str, u64>,
+    pub(crate) bindings: Arc<Vec<ResolvedBinding>>,
+    pub(crate) haptics: SimulatedHaptics,
+    pub(crate) capture: FrameCapture,
 }
 
 impl SimulatedSession {
-    pub fn new(instance_id: u64, id: u64, create_info: &xr::SessionCreateInfo) -> Result<Self> {
+    pub fn new(
+        instance_id: u64,
+        id: u64,
+        create_info: &xr::SessionCreateInfo,
+        profile: Arc<DeviceProfile>,
+        enabled_extensions: Arc<HashSet<String>>,
+    ) -> Result<Self> {
         if create_info.next.is_null() {
             return Err(xr::Result::ERROR_GRAPHICS_DEVICE_INVALID.into());
         }
@@ -226,16 +301,30 @@ impl SimulatedSession {
             return Err(xr::Result::ERROR_GRAPHICS_DEVICE_INVALID.into());
         }
 
-        let sess = Self {
+        let local_origin = with_device(|device| device.head.gravity_aligned_pose());
+
+        let mut sess = Self {
             instance_id,
             id,
+            profile,
+            enabled_extensions,
             graphics_binding: graphics_binding.try_into()?,
             space_ids: HashMap::new(),
             action_set_ids: HashSet::new(),
             swapchain_ids: HashSet::new(),
             state: xr::SessionState::IDLE,
+            polled_state: xr::SessionState::UNKNOWN,
+            created_time: clock::now(),
             is_running: false,
+            is_headset_worn: true,
+            is_overlay_shown: false,
+            is_exit_requested: false,
             frame: SessionFrame::default(),
+            local_origin,
+            interaction_profiles: HashMap::new(),
+            bindings: Arc::new(Vec::new()),
+            haptics: SimulatedHaptics::default(),
+            capture: FrameCapture::new(),
         };
 
         schedule_event(
@@ -243,37 +332,88 @@ impl SimulatedSession {
             &Event::SessionStateChanged {
                 session: xr::Session::from_raw(sess.id),
                 state: sess.state,
-                time: START_TIME.elapsed().into(),
+                time: clock::now().into(),
             },
         )?;
 
+        sess.check_ready()?;
+
         Ok(sess)
     }
 
+    fn set_state(&mut self, state: xr::SessionState) -> Result<()> {
+        log::debug!("[{}] session state {:?} -> {state:?}", self.id, self.state);
+
+        self.state = state;
+        schedule_event(
+            self.instance_id,
+            &Event::SessionStateChanged {
+                session: xr::Session::from_raw(self.id),
+                state: self.state,
+                time: clock::now().into(),
+            },
+        )
+    }
+
+    /// Steps down through the running states one at a time, as the spec requires, until `state`
+    /// is reached. Sessions already below it are left alone.
+    fn step_down_to(&mut self, state: xr::SessionState) -> Result<()> {
+        const RUNNING_STATES: &[xr::SessionState] = &[
+            xr::SessionState::FOCUSED,
+            xr::SessionState::VISIBLE,
+            xr::SessionState::SYNCHRONIZED,
+            xr::SessionState::STOPPING,
+        ];
+
+        if matches!(self.state, xr::SessionState::READY) && self.is_running {
+            self.synchronize()?;
+        }
+
+        let Some(from) = RUNNING_STATES.iter().position(|&item| item == self.state) else {
+            return Ok(());
+        };
+        let Some(to) = RUNNING_STATES.iter().position(|&item| item == state) else {
+            return Ok(());
+        };
+
+        for &next in RUNNING_STATES.iter().take(to + 1).skip(from + 1) {
+            self.set_state(next)?;
+        }
+
+        Ok(())
+    }
+
+    /// Idle sessions become ready once the profile's delay since their creation has passed, as
+    /// long as the headset is worn.
     pub fn check_ready(&mut self) -> Result<()> {
         if let xr::SessionState::IDLE = self.state {
-            if !self.space_ids.is_empty()
-                && !self.swapchain_ids.is_empty()
-                && !self.action_set_ids.is_empty()
+            if self.is_headset_worn
+                && !self.is_exit_requested
+                && clock::now() >= self.created_time + self.profile.ready_delay()
             {
-                self.state = xr::SessionState::READY;
-                schedule_event(
-                    self.instance_id,
-                    &Event::SessionStateChanged {
-                        session: xr::Session::from_raw(self.id),
-                        state: self.state,
-                        time: START_TIME.elapsed().into(),
-                    },
-                )?;
+                self.set_state(xr::SessionState::READY)?;
             }
         }
 
         Ok(())
     }
 
+    pub fn state_polled(&mut self, state: xr::SessionState) {
+        self.polled_state = state;
+    }
+
+    fn warn_unpolled_state(&self, function: &str, state: xr::SessionState) {
+        if *IS_STRICT_ORDERING && self.polled_state != state {
+            log::warn!(
+                "[{}] {function} called before the {state:?} state was polled, the app relies on \
+                 this runtime's timing",
+                self.id
+            );
+        }
+    }
+
     pub fn set_space(&mut self, space_type: SimulatedSessionSpace, space_id: u64) -> Result<()> {
         self.space_ids.insert(space_id, space_type);
-        self.check_ready()?;
 
         Ok(())
     }
@@ -283,7 +423,6 @@ impl SimulatedSession {
             Err(xr::Result::ERROR_ACTIONSETS_ALREADY_ATTACHED.into())
         } else {
             log::debug!("attached action set {action_set_id}");
-            self.check_ready()?;
 
             Ok(())
         }
@@ -293,12 +432,38 @@ impl SimulatedSession {
         self.action_set_ids.contains(&action_set_id)
     }
 
+    pub fn set_interaction_profiles(
+        &mut self,
+        profiles: HashMap<&'static str, u64>,
+        bindings: Vec<ResolvedBinding>,
+    ) -> Result<()> {
+        let changed = profiles != self.interaction_profiles;
+
+        self.interaction_profiles = profiles;
+        self.bindings = Arc::new(bindings);
+
+        if changed {
+            log::debug!(
+                "[{}] interaction profiles changed: {:?}",
+                self.id,
+                self.interaction_profiles
+            );
+            schedule_event(
+                self.instance_id,
+                &Event::InteractionProfileChanged {
+                    session: xr::Session::from_raw(self.id),
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+
     pub fn add_swapchain(&mut self, swapchain_id: u64) -> Result<()> {
         if !self.swapchain_ids.insert(swapchain_id) {
             Err(xr::Result::ERROR_ACTIONSETS_ALREADY_ATTACHED.into())
         } else {
             log::debug!("attached swapchain {swapchain_id}");
-            self.check_ready()?;
 
             Ok(())
         }
@@ -313,8 +478,16 @@ impl SimulatedSession {
             return Err(xr::Result::ERROR_SESSION_NOT_READY.into());
         }
 
+        self.warn_unpolled_state("xrBeginSession", xr::SessionState::READY);
+
         self.is_running = true;
         log::debug!("{}: session began", self.id);
+
+        // the exit was requested while it was ready, it's stopped right away
+        if self.is_exit_requested {
+            self.step_down_to(xr::SessionState::STOPPING)?;
+        }
+
         Ok(())
     }
 
@@ -322,24 +495,82 @@ impl SimulatedSession {
         matches!(self.state, xr::SessionState::FOCUSED)
     }
 
+    /// Whether the frames the app submits are shown to the user.
+    pub fn is_visible(&self) -> bool {
+        matches!(
+            self.state,
+            xr::SessionState::VISIBLE | xr::SessionState::FOCUSED
+        )
+    }
+
     pub fn request_exit(&mut self) -> Result<()> {
         if !self.is_running {
             return Err(xr::Result::ERROR_SESSION_NOT_RUNNING.into());
         }
 
-        self.state = xr::SessionState::STOPPING;
-        schedule_event(
-            self.instance_id,
-            &Event::SessionStateChanged {
-                session: xr::Session::from_raw(self.id),
-                state: self.state,
-                time: START_TIME.elapsed().into(),
-            },
-        )?;
+        self.is_exit_requested = true;
+        self.step_down_to(xr::SessionState::STOPPING)
+    }
+
+    /// The runtime asks the app to quit, running sessions are stopped first and idle sessions exit
+    /// right away. A ready session can't go back to idle, it's stopped once the app begins it.
+    pub fn exit(&mut self) -> Result<()> {
+        if self.is_running {
+            return self.request_exit();
+        }
+
+        self.is_exit_requested = true;
+
+        if matches!(self.state, xr::SessionState::IDLE) {
+            self.set_state(xr::SessionState::EXITING)?;
+        }
+
+        Ok(())
+    }
+
+    /// Taking the headset off takes the focus away from a running session. An idle session doesn't
+    /// get ready until the headset is put on again, a ready one stays ready since it can't go back
+    /// to idle, and only becomes visible once the headset is on.
+    pub fn set_headset_worn(&mut self, is_worn: bool) -> Result<()> {
+        self.is_headset_worn = is_worn;
+
+        if is_worn {
+            return self.check_ready();
+        }
+
+        if self.is_running {
+            self.step_down_to(xr::SessionState::VISIBLE)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// A system overlay steals the input focus while it's shown, the session gets it back with
+    /// the next frame once it's hidden.
+    pub fn set_overlay_shown(&mut self, is_shown: bool) -> Result<()> {
+        self.is_overlay_shown = is_shown;
+
+        if is_shown {
+            self.step_down_to(xr::SessionState::VISIBLE)?;
+        }
 
         Ok(())
     }
 
+    /// Called after every submitted frame, the session becomes visible with the first one and
+    /// focused with the next as long as nothing holds it back.
+    pub fn frame_ended(&mut self) -> Result<()> {
+        match self.state {
+            xr::SessionState::SYNCHRONIZED if self.is_headset_worn => {
+                self.set_state(xr::SessionState::VISIBLE)
+            }
+            xr::SessionState::VISIBLE if self.is_headset_worn && !self.is_overlay_shown => {
+                self.set_state(xr::SessionState::FOCUSED)
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn end(&mut self) -> Result<()> {
         if !self.is_running {
             return Err(xr::Result::ERROR_SESSION_NOT_RUNNING.into());
@@ -349,31 +580,33 @@ impl SimulatedSession {
             return Err(xr::Result::ERROR_SESSION_NOT_STOPPING.into());
         }
 
+        self.warn_unpolled_state("xrEndSession", xr::SessionState::STOPPING);
+
         self.is_running = false;
-        self.state = xr::SessionState::IDLE;
-        schedule_event(
-            self.instance_id,
-            &Event::SessionStateChanged {
-                session: xr::Session::from_raw(self.id),
-                state: self.state,
-                time: START_TIME.elapsed().into(),
-            },
-        )?;
+        self.set_state(xr::SessionState::IDLE)?;
+
+        if self.is_exit_requested {
+            self.set_state(xr::SessionState::EXITING)?;
+        }
 
         Ok(())
     }
 
+    /// Simulates the runtime losing the session, every call on it and its swapchains and spaces
+    /// fails from now on until the app destroys it.
+    pub fn lose(&mut self) -> Result<()> {
+        if !LOST_SESSIONS.lock()?.insert(self.id) {
+            return Ok(());
+        }
+
+        log::info!("[{}] session lost", self.id);
+        self.is_running = false;
+        self.set_state(xr::SessionState::LOSS_PENDING)
+    }
+
     pub fn synchronize(&mut self) -> Result<()> {
         if matches!(self.state, xr::SessionState::READY) {
-            self.state = xr::SessionState::SYNCHRONIZED;
-            schedule_event(
-                self.instance_id,
-                &Event::SessionStateChanged {
-                    session: xr::Session::from_raw(self.id),
-                    state: self.state,
-                    time: START_TIME.elapsed().into(),
-                },
-            )?;
+            self.set_state(xr::SessionState::SYNCHRONIZED)?;
         }
 
         Ok(())
@@ -387,18 +620,241 @@ type SharedSimulatedSession = UnsafeCell<SimulatedSession>;
 static INSTANCES: LazyLock<Mutex<HashMap<u64, SharedSimulatedSession>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
-pub fn with_session<T, F>(xr_obj_id: u64, f: F) -> Result<T>
+/// sessions the control interface made lost, kept apart so the objects that belong to them can
+/// check without locking the sessions
+static LOST_SESSIONS: LazyLock<Mutex<HashSet<u64>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Fails with `ERROR_INSTANCE_LOST` or `ERROR_SESSION_LOST` once the instance or the session was
+/// lost.
+pub fn check_session_lost(instance_id: u64, session_id: u64) -> Result<()> {
+    check_instance_lost(instance_id)?;
+
+    if LOST_SESSIONS.lock()?.contains(&session_id) {
+        return Err(xr::Result::ERROR_SESSION_LOST.into());
+    }
+
+    Ok(())
+}
+
+pub fn with_session<T, F>(xr_obj_id: u64, mut f: F) -> Result<T>
 where
     F: FnMut(&mut SimulatedSession) -> Result<T>,
 {
-    match with_obj_instance(&INSTANCES, xr_obj_id, f) {
+    let res = with_obj_instance(&INSTANCES, xr_obj_id, |session| {
+        check_session_lost(session.instance_id, session.id)?;
+        f(session)
+    });
+
+    match res {
         Ok(res) => Ok(res),
         Err(err) => match err {
             Error::ExpectedSome(err) => {
                 log::error!("error: {err}");
-                Err(openxr_sys::Result::ERROR_SESSION_LOST.into())
+                Err(openxr_sys::Result::ERROR_HANDLE_INVALID.into())
             }
             _ => Err(err),
         },
     }
 }
+
+/// Like `with_session` but also reaches lost sessions, for releasing the objects that belong to
+/// them.
+pub fn with_session_unchecked<T, F>(session_id: u64, f: F) -> Result<T>
+where
+    F: FnMut(&mut SimulatedSession) -> Result<T>,
+{
+    with_obj_instance(&INSTANCES, session_id, f)
+}
+
+/// Releases a destroyed session, its instance can create a new one.
+pub fn remove(session_id: u64) -> Result<()> {
+    LOST_SESSIONS.lock()?.remove(&session_id);
+
+    let Some(session) = INSTANCES.lock()?.remove(&session_id) else {
+        return Err(xr::Result::ERROR_HANDLE_INVALID.into());
+    };
+
+    with_instance_unchecked(session.into_inner().instance_id, |instance| {
+        instance.remove_session(session_id);
+        Ok(())
+    })?;
+
+    log::debug!("destroyed {session_id}");
+    Ok(())
+}
+
+/// Moves the sessions of an instance that waited long enough to ready.
+pub fn check_ready_sessions(instance_id: u64) -> Result<()> {
+    for session in INSTANCES.lock()?.values() {
+        let session = unsafe { &mut *session.get() };
+        if session.instance_id == instance_id {
+            session.check_ready()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// ids of the sessions that aren't lost
+pub fn active_session_ids() -> Result<Vec<u64>> {
+    let sessions = INSTANCES.lock()?;
+    let lost_sessions = LOST_SESSIONS.lock()?;
+
+    Ok(sessions
+        .keys()
+        .filter(|session_id| !lost_sessions.contains(session_id))
+        .copied()
+        .collect())
+}
+
+pub fn with_all_sessions<F>(mut f: F) -> Result<()>
+where
+    F: FnMut(&mut SimulatedSession) -> Result<()>,
+{
+    let sessions = INSTANCES.lock()?;
+    if sessions.is_empty() {
+        return Err("no active session".into());
+    }
+
+    let lost_sessions = LOST_SESSIONS.lock()?.clone();
+
+    for (session_id, session) in sessions.iter() {
+        if !lost_sessions.contains(session_id) {
+            f(unsafe { &mut *session.get() })?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+
+    use super::*;
+    use crate::{event::create_queue, utils::create_identity_pose};
+
+    /// A session without a graphics device, its Vulkan functions panic if they're ever called.
+    /// Every test passes its own id, the event queues are shared with the whole process.
+    fn session(id: u64, ready_delay_ms: u64) -> SimulatedSession {
+        create_queue(id).unwrap();
+
+        let mut profile = DeviceProfile::default();
+        profile.session.ready_delay_ms = ready_delay_ms;
+
+        let graphics_binding = unsafe {
+            GraphicsBinding {
+                instance: Arc::new(ash::Instance::load_with(
+                    |_| std::ptr::null(),
+                    vk::Instance::null(),
+                )),
+                physical_device: vk::PhysicalDevice::null(),
+                device: Arc::new(ash::Device::load_with(
+                    |_| std::ptr::null(),
+                    vk::Device::null(),
+                )),
+                queue_family_index: 0,
+                queue_index: 0,
+            }
+        };
+
+        SimulatedSession {
+            instance_id: id,
+            id,
+            profile: Arc::new(profile),
+            enabled_extensions: Arc::new(HashSet::new()),
+            graphics_binding,
+            space_ids: HashMap::new(),
+            action_set_ids: HashSet::new(),
+            swapchain_ids: HashSet::new(),
+            state: xr::SessionState::IDLE,
+            polled_state: xr::SessionState::UNKNOWN,
+            created_time: clock::now(),
+            is_running: false,
+            is_headset_worn: true,
+            is_overlay_shown: false,
+            is_exit_requested: false,
+            frame: SessionFrame::default(),
+            local_origin: create_identity_pose(),
+            interaction_profiles: HashMap::new(),
+            bindings: Arc::new(Vec::new()),
+            haptics: SimulatedHaptics::default(),
+            capture: FrameCapture::new(),
+        }
+    }
+
+    #[test]
+    fn sessions_get_ready_without_spaces_swapchains_or_action_sets() {
+        let mut session = session(u64::MAX - 301, 0);
+
+        session.check_ready().unwrap();
+
+        assert_eq!(session.state, xr::SessionState::READY);
+    }
+
+    #[test]
+    fn sessions_wait_for_the_ready_delay() {
+        let mut session = session(u64::MAX - 302, 60 * 60 * 1000);
+
+        session.check_ready().unwrap();
+
+        assert_eq!(session.state, xr::SessionState::IDLE);
+        assert!(matches!(
+            session.begin(),
+            Err(Error::XrResult(xr::Result::ERROR_SESSION_NOT_READY))
+        ));
+    }
+
+    #[test]
+    fn ready_sessions_are_stopped_in_order() {
+        let mut session = session(u64::MAX - 303, 0);
+        session.check_ready().unwrap();
+
+        session.begin().unwrap();
+        session.synchronize().unwrap();
+        session.frame_ended().unwrap();
+        session.frame_ended().unwrap();
+        assert_eq!(session.state, xr::SessionState::FOCUSED);
+
+        assert!(matches!(
+            session.end(),
+            Err(Error::XrResult(xr::Result::ERROR_SESSION_NOT_STOPPING))
+        ));
+        session.request_exit().unwrap();
+        assert_eq!(session.state, xr::SessionState::STOPPING);
+
+        session.end().unwrap();
+        assert_eq!(session.state, xr::SessionState::EXITING);
+        assert!(!session.is_running);
+    }
+
+    #[test]
+    fn lost_sessions_fail_until_destroyed() {
+        let id = u64::MAX - 304;
+        INSTANCES
+            .lock()
+            .unwrap()
+            .insert(id, UnsafeCell::new(session(id, 0)));
+
+        with_session(id, |session| session.lose()).unwrap();
+
+        assert!(matches!(
+            with_session(id, |_| Ok(())),
+            Err(Error::XrResult(xr::Result::ERROR_SESSION_LOST))
+        ));
+        assert!(matches!(
+            check_session_lost(id, id),
+            Err(Error::XrResult(xr::Result::ERROR_SESSION_LOST))
+        ));
+        assert!(!active_session_ids().unwrap().contains(&id));
+        with_session_unchecked(id, |session| {
+            assert_eq!(session.state, xr::SessionState::LOSS_PENDING);
+            assert!(!session.is_running);
+            Ok(())
+        })
+        .unwrap();
+
+        LOST_SESSIONS.lock().unwrap().remove(&id);
+        INSTANCES.lock().unwrap().remove(&id);
+    }
+}
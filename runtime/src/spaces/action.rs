@@ -1,4 +1,11 @@
-use crate::{prelude::*, session::with_session};
+use crate::{
+    input::action::with_action,
+    math::{is_pose_valid, pose_multiply},
+    prelude::*,
+    session::with_session,
+};
+
+use super::SpaceState;
 
 pub extern "system" fn create(
     xr_session: xr::Session,
@@ -15,12 +22,29 @@ pub extern "system" fn create(
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
+    if !is_pose_valid(&create_info.pose_in_action_space) {
+        return xr::Result::ERROR_POSE_INVALID;
+    }
+
+    let subaction_path = create_info.subaction_path.into_raw();
+    if let Err(err) = with_action(create_info.action.into_raw(), |action| {
+        if subaction_path != 0 {
+            action.subaction_value(subaction_path)?;
+        }
+        if action.action_type != xr::ActionType::POSE_INPUT {
+            return Err(xr::Result::ERROR_ACTION_TYPE_MISMATCH.into());
+        }
+        Ok(())
+    }) {
+        return err.into();
+    }
+
     with_session(xr_session.into_raw(), |session| {
         let space_id = super::create(
             session,
             super::SimulatedSpaceType::Action(SimulatedActionSpace {
                 action: create_info.action.into_raw(),
-                subaction_path: create_info.subaction_path.into_raw(),
+                subaction_path,
                 pose: create_info.pose_in_action_space,
             }),
         )?;
@@ -30,10 +54,22 @@ pub extern "system" fn create(
     .into_xr_result()
 }
 
-#[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SimulatedActionSpace {
     pub(crate) action: u64,
     pub(crate) subaction_path: u64,
     pub(crate) pose: xr::Posef,
 }
+
+impl SimulatedActionSpace {
+    pub fn current_pose(&self) -> Result<Option<xr::Posef>> {
+        with_action(self.action, |action| {
+            action.current_pose(self.subaction_path)
+        })
+    }
+
+    pub fn state(&self, action_pose: &xr::Posef) -> SpaceState {
+        // the simulated controllers have no velocities to report
+        SpaceState::without_velocity(pose_multiply(action_pose, &self.pose))
+    }
+}
@@ -5,8 +5,14 @@ use std::{
 };
 
 use crate::{
+    handle::{self, Handle},
+    math::{pose_inverse, pose_multiply, quat_conjugate, quat_rotate, vec3_cross, vec3_sub},
     prelude::*,
-    session::{SimulatedSession, SimulatedSessionSpace, with_session},
+    session::{
+        SimulatedSession, SimulatedSessionSpace, check_session_lost, with_session,
+        with_session_unchecked,
+    },
+    utils::{find_in_next_chain_mut, with_obj_instance},
 };
 
 pub mod action;
@@ -22,16 +28,21 @@ pub fn create(session: &mut SimulatedSession, space: SimulatedSpaceType) -> Resu
 
     spaces.insert(
         next_id,
-        UnsafeCell::new(SimulatedSpace::new(session.id, next_id, space)?),
+        UnsafeCell::new(SimulatedSpace::new(
+            session.instance_id,
+            session.id,
+            next_id,
+            space,
+        )?),
     );
 
     log::debug!("created: {:?}", unsafe { &*spaces[&next_id].get() });
 
+    handle::register(Handle::Space(next_id), Some(Handle::Session(session.id)))?;
     session.set_space(session_space, next_id)?;
     Ok(next_id)
 }
 
-#[allow(unreachable_code)]
 pub extern "system" fn locate_spaces(
     xr_session: xr::Session,
     info: *const xr::SpacesLocateInfo,
@@ -41,11 +52,78 @@ pub extern "system" fn locate_spaces(
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
-    let (info, _locations) = unsafe { (&*info, &mut *locations) };
+    let (info, locations) = unsafe { (&*info, &mut *locations) };
+
+    if info.ty != xr::StructureType::SPACES_LOCATE_INFO
+        || locations.ty != xr::StructureType::SPACE_LOCATIONS
+    {
+        return xr::Result::ERROR_VALIDATION_FAILURE;
+    }
+
+    if info.space_count == 0
+        || info.spaces.is_null()
+        || locations.location_count != info.space_count
+        || locations.locations.is_null()
+    {
+        return xr::Result::ERROR_VALIDATION_FAILURE;
+    }
+
+    if info.time.as_nanos() <= 0 {
+        return xr::Result::ERROR_TIME_INVALID;
+    }
+
+    let velocities = find_in_next_chain_mut::<xr::SpaceVelocities>(
+        locations.next,
+        xr::StructureType::SPACE_VELOCITIES,
+    );
+
+    if let Some(velocities) = &velocities {
+        if velocities.velocity_count != info.space_count || velocities.velocities.is_null() {
+            return xr::Result::ERROR_VALIDATION_FAILURE;
+        }
+    }
+
+    let (xr_spaces, location_data) = unsafe {
+        (
+            std::slice::from_raw_parts(info.spaces, info.space_count as usize),
+            std::slice::from_raw_parts_mut(locations.locations, info.space_count as usize),
+        )
+    };
+
+    let mut velocity_data = velocities.map(|velocities| unsafe {
+        std::slice::from_raw_parts_mut(velocities.velocities, info.space_count as usize)
+    });
+
+    log::debug!("locate_spaces: {info:?}");
+
+    let snapshots = SpaceSnapshot::new(info.base_space.into_raw()).and_then(|base_space| {
+        let spaces = xr_spaces
+            .iter()
+            .map(|xr_space| SpaceSnapshot::new(xr_space.into_raw()))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((base_space, spaces))
+    });
+
+    let (base_space, spaces) = match snapshots {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
+    with_session(xr_session.into_raw(), |session| {
+        if base_space.session_id != session.id
+            || spaces.iter().any(|space| space.session_id != session.id)
+        {
+            return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+        }
+
+        for (i, space) in spaces.iter().enumerate() {
+            let located = space.locate_in(&base_space, session);
+            located.write_location_data(&mut location_data[i]);
+            if let Some(velocity_data) = velocity_data.as_mut() {
+                located.write_velocity_data(&mut velocity_data[i]);
+            }
+        }
 
-    with_session(xr_session.into_raw(), |_session| {
-        log::debug!("locate_spaces: {info:?}");
-        return Err(xr::Result::ERROR_FUNCTION_UNSUPPORTED.into());
         Ok(())
     })
     .into_xr_result()
@@ -61,23 +139,44 @@ pub extern "system" fn locate(
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
-    with_simulated_spaces(&[xr_space.into_raw(), xr_base_space.into_raw()], |spaces| {
-        let space_location = unsafe { &mut *space_location };
-        let (space, _base_space) = (&*spaces[0], &*spaces[1]);
+    let space_location = unsafe { &mut *space_location };
 
-        space_location.location_flags = xr::SpaceLocationFlags::from_raw(0b1111);
-        space_location.pose = match &space.space {
-            SimulatedSpaceType::Reference(simulated_reference_space) => {
-                simulated_reference_space.pose
-            }
-            SimulatedSpaceType::Action(simulated_action_space) => simulated_action_space.pose,
-        };
+    if space_location.ty != xr::StructureType::SPACE_LOCATION {
+        return xr::Result::ERROR_VALIDATION_FAILURE;
+    }
 
-        log::debug!("locate: {xr_time:?}, {space_location:?}",);
+    if xr_time.as_nanos() <= 0 {
+        return xr::Result::ERROR_TIME_INVALID;
+    }
 
-        Ok(())
-    })
-    .into_xr_result()
+    let located = SpaceSnapshot::new(xr_space.into_raw()).and_then(|space| {
+        let base_space = SpaceSnapshot::new(xr_base_space.into_raw())?;
+        if space.session_id != base_space.session_id {
+            return Err(xr::Result::ERROR_VALIDATION_FAILURE.into());
+        }
+
+        with_session(space.session_id, |session| {
+            Ok(space.locate_in(&base_space, session))
+        })
+    });
+
+    let located = match located {
+        Ok(value) => value,
+        Err(err) => return err.into(),
+    };
+
+    located.write_location(space_location);
+
+    if let Some(velocity) = find_in_next_chain_mut::<xr::SpaceVelocity>(
+        space_location.next,
+        xr::StructureType::SPACE_VELOCITY,
+    ) {
+        located.write_velocity(velocity);
+    }
+
+    log::debug!("locate: {xr_time:?}, {space_location:?}");
+
+    xr::Result::SUCCESS
 }
 
 pub extern "system" fn destroy(xr_obj: xr::Space) -> xr::Result {
@@ -85,24 +184,10 @@ pub extern "system" fn destroy(xr_obj: xr::Space) -> xr::Result {
         return xr::Result::ERROR_HANDLE_INVALID;
     }
 
-    let instance_id = xr_obj.into_raw();
-
-    if INSTANCES
-        .lock()
-        .expect("couldn't acquire instances")
-        .remove(&instance_id)
-        .is_some()
-    {
-        log::debug!("destroyed {instance_id}");
-    } else {
-        log::debug!("instance {instance_id} not found");
-    }
-
-    xr::Result::SUCCESS
+    handle::destroy(Handle::Space(xr_obj.into_raw())).into_xr_result()
 }
 
-#[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SimulatedSpaceType {
     Reference(reference::SimulatedReferenceSpace),
     Action(action::SimulatedActionSpace),
@@ -111,14 +196,21 @@ pub enum SimulatedSpaceType {
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct SimulatedSpace {
+    instance_id: u64,
     session_id: u64,
     id: u64,
     space: SimulatedSpaceType,
 }
 
 impl SimulatedSpace {
-    pub fn new(session_id: u64, id: u64, space: SimulatedSpaceType) -> Result<Self> {
+    pub fn new(
+        instance_id: u64,
+        session_id: u64,
+        id: u64,
+        space: SimulatedSpaceType,
+    ) -> Result<Self> {
         Ok(Self {
+            instance_id,
             session_id,
             id,
             space,
@@ -126,6 +218,164 @@ impl SimulatedSpace {
     }
 }
 
+/// pose and velocities of a space in the tracking origin
+#[derive(Debug, Clone, Copy)]
+pub struct SpaceState {
+    pub(crate) pose: xr::Posef,
+    pub(crate) linear_velocity: xr::Vector3f,
+    pub(crate) angular_velocity: xr::Vector3f,
+    pub(crate) is_velocity_valid: bool,
+    pub(crate) is_orientation_tracked: bool,
+    pub(crate) is_position_tracked: bool,
+}
+
+impl SpaceState {
+    pub fn stationary(pose: xr::Posef) -> Self {
+        Self {
+            pose,
+            linear_velocity: xr::Vector3f::default(),
+            angular_velocity: xr::Vector3f::default(),
+            is_velocity_valid: true,
+            is_orientation_tracked: true,
+            is_position_tracked: true,
+        }
+    }
+
+    /// a pose whose velocities aren't known
+    pub fn without_velocity(pose: xr::Posef) -> Self {
+        Self {
+            is_velocity_valid: false,
+            ..Self::stationary(pose)
+        }
+    }
+
+    /// expresses this state relative to `base`, i.e. base⁻¹·self
+    pub fn relative_to(&self, base: &SpaceState) -> SpaceState {
+        let base_inverse = quat_conjugate(&base.pose.orientation);
+        let lever = vec3_sub(&self.pose.position, &base.pose.position);
+        let linear_velocity = vec3_sub(
+            &vec3_sub(&self.linear_velocity, &base.linear_velocity),
+            &vec3_cross(&base.angular_velocity, &lever),
+        );
+        let angular_velocity = vec3_sub(&self.angular_velocity, &base.angular_velocity);
+
+        SpaceState {
+            pose: pose_multiply(&pose_inverse(&base.pose), &self.pose),
+            linear_velocity: quat_rotate(&base_inverse, &linear_velocity),
+            angular_velocity: quat_rotate(&base_inverse, &angular_velocity),
+            is_velocity_valid: self.is_velocity_valid && base.is_velocity_valid,
+            is_orientation_tracked: self.is_orientation_tracked && base.is_orientation_tracked,
+            is_position_tracked: self.is_position_tracked && base.is_position_tracked,
+        }
+    }
+}
+
+/// result of locating a space in a base space, `None` when either of them can't be located
+#[derive(Debug, Clone, Copy)]
+pub struct SpaceLocated(Option<SpaceState>);
+
+impl SpaceLocated {
+    pub fn location_flags(&self) -> xr::SpaceLocationFlags {
+        let Some(state) = &self.0 else {
+            return xr::SpaceLocationFlags::EMPTY;
+        };
+
+        let mut flags =
+            xr::SpaceLocationFlags::ORIENTATION_VALID | xr::SpaceLocationFlags::POSITION_VALID;
+        if state.is_orientation_tracked {
+            flags |= xr::SpaceLocationFlags::ORIENTATION_TRACKED;
+        }
+        if state.is_position_tracked {
+            flags |= xr::SpaceLocationFlags::POSITION_TRACKED;
+        }
+        flags
+    }
+
+    pub fn velocity_flags(&self) -> xr::SpaceVelocityFlags {
+        match &self.0 {
+            Some(state) if state.is_velocity_valid => {
+                xr::SpaceVelocityFlags::LINEAR_VALID | xr::SpaceVelocityFlags::ANGULAR_VALID
+            }
+            _ => xr::SpaceVelocityFlags::EMPTY,
+        }
+    }
+
+    pub fn write_location(&self, location: &mut xr::SpaceLocation) {
+        location.location_flags = self.location_flags();
+        if let Some(state) = &self.0 {
+            location.pose = state.pose;
+        }
+    }
+
+    pub fn write_location_data(&self, location: &mut xr::SpaceLocationData) {
+        location.location_flags = self.location_flags();
+        if let Some(state) = &self.0 {
+            location.pose = state.pose;
+        }
+    }
+
+    pub fn write_velocity(&self, velocity: &mut xr::SpaceVelocity) {
+        velocity.velocity_flags = self.velocity_flags();
+        if let Some(state) = &self.0 {
+            velocity.linear_velocity = state.linear_velocity;
+            velocity.angular_velocity = state.angular_velocity;
+        }
+    }
+
+    pub fn write_velocity_data(&self, velocity: &mut xr::SpaceVelocityData) {
+        velocity.velocity_flags = self.velocity_flags();
+        if let Some(state) = &self.0 {
+            velocity.linear_velocity = state.linear_velocity;
+            velocity.angular_velocity = state.angular_velocity;
+        }
+    }
+}
+
+/// copy of a space and the current value of its pose action, taken without holding the session
+#[derive(Debug)]
+pub struct SpaceSnapshot {
+    pub(crate) session_id: u64,
+    pub(crate) space: SimulatedSpaceType,
+    pub(crate) action_pose: Option<xr::Posef>,
+}
+
+impl SpaceSnapshot {
+    pub fn new(space_id: u64) -> Result<Self> {
+        let (session_id, space) = with_space(space_id, |space| {
+            Ok((space.session_id, space.space.clone()))
+        })?;
+
+        let action_pose = match &space {
+            SimulatedSpaceType::Reference(_) => None,
+            SimulatedSpaceType::Action(action_space) => action_space.current_pose()?,
+        };
+
+        Ok(Self {
+            session_id,
+            space,
+            action_pose,
+        })
+    }
+
+    pub fn state(&self, session: &SimulatedSession) -> Option<SpaceState> {
+        match &self.space {
+            SimulatedSpaceType::Reference(reference_space) => Some(reference_space.state(session)),
+            SimulatedSpaceType::Action(action_space) => self
+                .action_pose
+                .as_ref()
+                .map(|action_pose| action_space.state(action_pose)),
+        }
+    }
+
+    pub fn locate_in(&self, base: &SpaceSnapshot, session: &SimulatedSession) -> SpaceLocated {
+        SpaceLocated(
+            self.state(session)
+                .zip(base.state(session))
+                .map(|(state, base_state)| state.relative_to(&base_state)),
+        )
+    }
+}
+
 static INSTANCE_COUNTER: atomic::AtomicU64 = atomic::AtomicU64::new(1);
 
 type SharedSimulatedSpace = UnsafeCell<SimulatedSpace>;
@@ -133,23 +383,318 @@ type SharedSimulatedSpace = UnsafeCell<SimulatedSpace>;
 static INSTANCES: LazyLock<Mutex<HashMap<u64, SharedSimulatedSpace>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
-#[inline]
-pub fn with_simulated_spaces<const MAX: usize, F: Fn(&[&mut SimulatedSpace]) -> Result<()>>(
-    ids: &[u64; MAX],
-    f: F,
-) -> Result<()> {
-    let instances = INSTANCES.lock()?;
-    let mut res = Vec::with_capacity(MAX);
-    let slice = 0..MAX;
-    for i in slice {
-        let id = ids[i];
-        res.push(unsafe {
-            &mut *instances
-                .get(&id)
-                .ok_or_else(|| Error::ExpectedSome(format!("space {id} does not exist")))?
-                .get()
-        });
-    }
-
-    f(res.as_ref())
+/// Releases a destroyed space, the session forgets about it.
+pub fn remove(space_id: u64) -> Result<()> {
+    let Some(space) = INSTANCES.lock()?.remove(&space_id) else {
+        return Err(xr::Result::ERROR_HANDLE_INVALID.into());
+    };
+
+    with_session_unchecked(space.into_inner().session_id, |session| {
+        session.space_ids.remove(&space_id);
+        Ok(())
+    })?;
+
+    log::debug!("destroyed {space_id}");
+    Ok(())
+}
+
+pub fn with_space<T, F>(obj_id: u64, mut f: F) -> Result<T>
+where
+    F: FnMut(&mut SimulatedSpace) -> Result<T>,
+{
+    let res = with_obj_instance(&INSTANCES, obj_id, |space| {
+        check_session_lost(space.instance_id, space.session_id)?;
+        f(space)
+    });
+
+    match res {
+        Ok(res) => Ok(res),
+        Err(err) => match err {
+            Error::ExpectedSome(err) => {
+                log::error!("error: {err}");
+                Err(xr::Result::ERROR_HANDLE_INVALID.into())
+            }
+            _ => Err(err),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils::{assert_vec3_eq, pose_at},
+        utils::create_identity_pose,
+    };
+
+    #[test]
+    fn relative_to_the_origin_is_the_same_state() {
+        let state = SpaceState::stationary(pose_at(1.0, 2.0, 3.0, 0.4));
+        let relative = state.relative_to(&SpaceState::stationary(create_identity_pose()));
+
+        assert_vec3_eq(&relative.pose.position, &state.pose.position);
+        assert!((relative.pose.orientation.y - state.pose.orientation.y).abs() < 1e-5);
+    }
+
+    #[test]
+    fn relative_to_a_turned_base() {
+        // the base looks down -X, so a point further down -X is in front of it
+        let base = SpaceState::stationary(pose_at(1.0, 0.0, 0.0, std::f32::consts::FRAC_PI_2));
+        let state = SpaceState::stationary(pose_at(-1.0, 1.0, 0.0, std::f32::consts::FRAC_PI_2));
+        let relative = state.relative_to(&base);
+
+        assert_vec3_eq(
+            &relative.pose.position,
+            &xr::Vector3f {
+                x: 0.0,
+                y: 1.0,
+                z: -2.0,
+            },
+        );
+        assert!((relative.pose.orientation.w.abs() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn a_rotating_base_sees_stationary_spaces_move() {
+        let base = SpaceState {
+            angular_velocity: xr::Vector3f {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+            ..SpaceState::stationary(create_identity_pose())
+        };
+        let state = SpaceState::stationary(pose_at(1.0, 0.0, 0.0, 0.0));
+        let relative = state.relative_to(&base);
+
+        assert_vec3_eq(
+            &relative.linear_velocity,
+            &xr::Vector3f {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0,
+            },
+        );
+        assert_vec3_eq(
+            &relative.angular_velocity,
+            &xr::Vector3f {
+                x: 0.0,
+                y: -1.0,
+                z: 0.0,
+            },
+        );
+    }
+
+    #[test]
+    fn velocities_are_only_valid_when_both_spaces_have_them() {
+        let stationary = SpaceState::stationary(create_identity_pose());
+        let without_velocity = SpaceState::without_velocity(create_identity_pose());
+
+        let located = SpaceLocated(Some(stationary.relative_to(&stationary)));
+        assert_eq!(
+            located.velocity_flags(),
+            xr::SpaceVelocityFlags::LINEAR_VALID | xr::SpaceVelocityFlags::ANGULAR_VALID
+        );
+
+        let located = SpaceLocated(Some(without_velocity.relative_to(&stationary)));
+        assert_eq!(located.velocity_flags(), xr::SpaceVelocityFlags::EMPTY);
+
+        let located = SpaceLocated(Some(stationary.relative_to(&without_velocity)));
+        assert_eq!(located.velocity_flags(), xr::SpaceVelocityFlags::EMPTY);
+        assert_eq!(
+            located.location_flags(),
+            xr::SpaceLocationFlags::ORIENTATION_VALID
+                | xr::SpaceLocationFlags::POSITION_VALID
+                | xr::SpaceLocationFlags::ORIENTATION_TRACKED
+                | xr::SpaceLocationFlags::POSITION_TRACKED
+        );
+    }
+
+    #[test]
+    fn spaces_are_only_tracked_when_both_are() {
+        let stationary = SpaceState::stationary(create_identity_pose());
+        let head = SpaceState {
+            is_position_tracked: false,
+            ..SpaceState::stationary(create_identity_pose())
+        };
+
+        for located in [
+            SpaceLocated(Some(head.relative_to(&stationary))),
+            SpaceLocated(Some(stationary.relative_to(&head))),
+        ] {
+            assert_eq!(
+                located.location_flags(),
+                xr::SpaceLocationFlags::ORIENTATION_VALID
+                    | xr::SpaceLocationFlags::POSITION_VALID
+                    | xr::SpaceLocationFlags::ORIENTATION_TRACKED
+            );
+        }
+    }
+
+    #[test]
+    fn unlocatable_spaces_have_no_flags() {
+        let located = SpaceLocated(None);
+
+        assert_eq!(located.location_flags(), xr::SpaceLocationFlags::EMPTY);
+        assert_eq!(located.velocity_flags(), xr::SpaceVelocityFlags::EMPTY);
+    }
+
+    fn locate_info(spaces: &[xr::Space]) -> xr::SpacesLocateInfo {
+        xr::SpacesLocateInfo {
+            ty: xr::StructureType::SPACES_LOCATE_INFO,
+            next: std::ptr::null(),
+            base_space: xr::Space::from_raw(u64::MAX),
+            time: xr::Time::from_nanos(1),
+            space_count: spaces.len() as u32,
+            spaces: spaces.as_ptr(),
+        }
+    }
+
+    fn space_locations(data: &mut [xr::SpaceLocationData]) -> xr::SpaceLocations {
+        xr::SpaceLocations {
+            ty: xr::StructureType::SPACE_LOCATIONS,
+            next: std::ptr::null_mut(),
+            location_count: data.len() as u32,
+            locations: data.as_mut_ptr(),
+        }
+    }
+
+    fn location_data() -> xr::SpaceLocationData {
+        xr::SpaceLocationData {
+            location_flags: xr::SpaceLocationFlags::EMPTY,
+            pose: create_identity_pose(),
+        }
+    }
+
+    fn velocity_data() -> xr::SpaceVelocityData {
+        xr::SpaceVelocityData {
+            velocity_flags: xr::SpaceVelocityFlags::EMPTY,
+            linear_velocity: xr::Vector3f::default(),
+            angular_velocity: xr::Vector3f::default(),
+        }
+    }
+
+    fn locate(info: &xr::SpacesLocateInfo, locations: &mut xr::SpaceLocations) -> xr::Result {
+        locate_spaces(xr::Session::from_raw(u64::MAX), info, locations)
+    }
+
+    #[test]
+    fn locate_spaces_validates_its_arguments() {
+        let spaces = [xr::Space::from_raw(u64::MAX - 1); 2];
+        let mut data = [location_data(); 2];
+
+        let info = locate_info(&spaces);
+        let mut locations = space_locations(&mut data);
+        assert_eq!(
+            locate_spaces(xr::Session::NULL, std::ptr::null(), &mut locations),
+            xr::Result::ERROR_VALIDATION_FAILURE
+        );
+        assert_eq!(
+            locate_spaces(xr::Session::NULL, &info, std::ptr::null_mut()),
+            xr::Result::ERROR_VALIDATION_FAILURE
+        );
+
+        let info = xr::SpacesLocateInfo {
+            ty: xr::StructureType::SPACE_LOCATION,
+            ..locate_info(&spaces)
+        };
+        let mut locations = space_locations(&mut data);
+        assert_eq!(
+            locate(&info, &mut locations),
+            xr::Result::ERROR_VALIDATION_FAILURE
+        );
+
+        let info = locate_info(&[]);
+        let mut locations = space_locations(&mut []);
+        assert_eq!(
+            locate(&info, &mut locations),
+            xr::Result::ERROR_VALIDATION_FAILURE
+        );
+
+        let info = locate_info(&spaces);
+        let mut locations = space_locations(&mut data[..1]);
+        assert_eq!(
+            locate(&info, &mut locations),
+            xr::Result::ERROR_VALIDATION_FAILURE
+        );
+
+        let info = xr::SpacesLocateInfo {
+            time: xr::Time::from_nanos(0),
+            ..locate_info(&spaces)
+        };
+        let mut locations = space_locations(&mut data);
+        assert_eq!(
+            locate(&info, &mut locations),
+            xr::Result::ERROR_TIME_INVALID
+        );
+    }
+
+    #[test]
+    fn locate_spaces_needs_a_velocity_per_space() {
+        let spaces = [xr::Space::from_raw(u64::MAX - 1); 2];
+        let mut data = [location_data(); 2];
+        let mut velocity_data = [velocity_data(); 1];
+
+        let info = locate_info(&spaces);
+        let mut velocities = xr::SpaceVelocities {
+            ty: xr::StructureType::SPACE_VELOCITIES,
+            next: std::ptr::null_mut(),
+            velocity_count: velocity_data.len() as u32,
+            velocities: velocity_data.as_mut_ptr(),
+        };
+        let mut locations = xr::SpaceLocations {
+            next: &mut velocities as *mut _ as *mut _,
+            ..space_locations(&mut data)
+        };
+
+        assert_eq!(
+            locate(&info, &mut locations),
+            xr::Result::ERROR_VALIDATION_FAILURE
+        );
+    }
+
+    #[test]
+    fn locate_spaces_rejects_unknown_spaces() {
+        let spaces = [xr::Space::from_raw(u64::MAX - 1)];
+        let mut data = [location_data()];
+
+        let info = locate_info(&spaces);
+        let mut locations = space_locations(&mut data);
+
+        assert_eq!(
+            locate(&info, &mut locations),
+            xr::Result::ERROR_HANDLE_INVALID
+        );
+    }
+
+    #[test]
+    fn located_states_are_written_out() {
+        let state = SpaceState {
+            linear_velocity: xr::Vector3f {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            ..SpaceState::stationary(pose_at(0.0, 1.0, 0.0, 0.0))
+        };
+
+        let mut location = location_data();
+        let mut velocity = velocity_data();
+        let located = SpaceLocated(Some(state));
+        located.write_location_data(&mut location);
+        located.write_velocity_data(&mut velocity);
+
+        assert!(
+            location
+                .location_flags
+                .contains(xr::SpaceLocationFlags::POSITION_VALID)
+        );
+        assert_vec3_eq(&location.pose.position, &state.pose.position);
+        assert_vec3_eq(&velocity.linear_velocity, &state.linear_velocity);
+
+        let mut location = location_data();
+        SpaceLocated(None).write_location_data(&mut location);
+        assert_eq!(location.location_flags, xr::SpaceLocationFlags::EMPTY);
+        assert_vec3_eq(&location.pose.position, &xr::Vector3f::default());
+    }
 }
@@ -1,4 +1,19 @@
-use crate::{prelude::*, session::with_session};
+use crate::{
+    device::{DEFAULT_PLAY_AREA, with_device},
+    math::{is_pose_valid, pose_multiply, quat_rotate, vec3_add, vec3_cross},
+    prelude::*,
+    session::{SimulatedSession, with_session},
+    utils::create_identity_pose,
+};
+
+use super::SpaceState;
+
+const SUPPORTED_REFERENCE_SPACES: &[xr::ReferenceSpaceType] = &[
+    xr::ReferenceSpaceType::VIEW,
+    xr::ReferenceSpaceType::LOCAL,
+    xr::ReferenceSpaceType::LOCAL_FLOOR,
+    xr::ReferenceSpaceType::STAGE,
+];
 
 pub extern "system" fn enumerate(
     xr_session: xr::Session,
@@ -9,19 +24,20 @@ pub extern "system" fn enumerate(
     let count_out = unsafe { &mut *count_out };
 
     with_session(xr_session.into_raw(), |_session| {
+        *count_out = SUPPORTED_REFERENCE_SPACES.len() as u32;
+
         if capacity_in == 0 {
-            *count_out = 3;
             return Ok(());
         }
 
-        if *count_out != 3 {
+        if capacity_in < *count_out {
             return Err(xr::Result::ERROR_SIZE_INSUFFICIENT.into());
         }
 
         unsafe {
-            *space_types = xr::ReferenceSpaceType::VIEW;
-            *space_types.add(1) = xr::ReferenceSpaceType::LOCAL;
-            *space_types.add(2) = xr::ReferenceSpaceType::LOCAL_FLOOR;
+            for (i, space_type) in SUPPORTED_REFERENCE_SPACES.iter().enumerate() {
+                *space_types.add(i) = *space_type;
+            }
         }
 
         Ok(())
@@ -44,10 +60,19 @@ pub extern "system" fn create(
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
+    if !SUPPORTED_REFERENCE_SPACES.contains(&create_info.reference_space_type) {
+        return xr::Result::ERROR_REFERENCE_SPACE_UNSUPPORTED;
+    }
+
+    if !is_pose_valid(&create_info.pose_in_reference_space) {
+        return xr::Result::ERROR_POSE_INVALID;
+    }
+
     with_session(xr_session.into_raw(), |session| {
         let space_id = super::create(
             session,
             super::SimulatedSpaceType::Reference(SimulatedReferenceSpace {
+                ty: create_info.reference_space_type,
                 pose: create_info.pose_in_reference_space,
             }),
         )?;
@@ -71,6 +96,15 @@ pub extern "system" fn get_bounds_rect(
     log::debug!("get_bounds_rect {ref_space_type:?}");
 
     with_session(xr_session.into_raw(), |_session| {
+        if !SUPPORTED_REFERENCE_SPACES.contains(&ref_space_type) {
+            return Err(xr::Result::ERROR_REFERENCE_SPACE_UNSUPPORTED.into());
+        }
+
+        if ref_space_type == xr::ReferenceSpaceType::STAGE {
+            *bounds = DEFAULT_PLAY_AREA;
+            return Ok(xr::Result::SUCCESS);
+        }
+
         bounds.width = 0.0;
         bounds.height = 0.0;
         Ok(xr::Result::SPACE_BOUNDS_UNAVAILABLE)
@@ -78,8 +112,48 @@ pub extern "system" fn get_bounds_rect(
     .into_xr_result()
 }
 
-#[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SimulatedReferenceSpace {
+    pub(crate) ty: xr::ReferenceSpaceType,
     pub(crate) pose: xr::Posef,
 }
+
+impl SimulatedReferenceSpace {
+    /// origin of the reference space in the tracking origin, which is the center of the stage at
+    /// floor level
+    pub fn origin(&self, session: &SimulatedSession) -> xr::Posef {
+        match self.ty {
+            xr::ReferenceSpaceType::VIEW => with_device(|device| device.head.pose),
+            xr::ReferenceSpaceType::LOCAL => session.local_origin,
+            xr::ReferenceSpaceType::LOCAL_FLOOR => {
+                let mut origin = session.local_origin;
+                origin.position.y = 0.0;
+                origin
+            }
+            _ => create_identity_pose(),
+        }
+    }
+
+    pub fn state(&self, session: &SimulatedSession) -> SpaceState {
+        let origin = self.origin(session);
+        let pose = pose_multiply(&origin, &self.pose);
+
+        if self.ty != xr::ReferenceSpaceType::VIEW {
+            return SpaceState::stationary(pose);
+        }
+
+        let head = with_device(|device| device.head.clone());
+        let lever = quat_rotate(&origin.orientation, &self.pose.position);
+        SpaceState {
+            pose,
+            linear_velocity: vec3_add(
+                &head.linear_velocity,
+                &vec3_cross(&head.angular_velocity, &lever),
+            ),
+            angular_velocity: head.angular_velocity,
+            is_velocity_valid: true,
+            is_orientation_tracked: session.profile.tracking.orientation,
+            is_position_tracked: session.profile.tracking.position,
+        }
+    }
+}
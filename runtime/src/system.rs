@@ -1,7 +1,31 @@
-use crate::{instance::api::with_instance, prelude::*, utils::copy_str_to_cchar_arr};
+use std::{sync::Mutex, time::Duration};
+
+use crate::{clock, instance::api::with_instance, prelude::*, utils::copy_str_to_cchar_arr};
 
 pub const HMD_SYSTEM_ID: u64 = 1;
 
+/// runtime time until which the headset can't be used for new sessions
+static UNAVAILABLE_UNTIL: Mutex<Option<Duration>> = Mutex::new(None);
+
+/// Makes the headset unavailable for a while, like a runtime that's still starting, apps have to
+/// keep retrying xrGetSystem until it's back.
+pub fn set_unavailable_for(duration: Duration) -> Result<()> {
+    *UNAVAILABLE_UNTIL.lock()? = Some(clock::now() + duration);
+    Ok(())
+}
+
+fn is_available() -> Result<bool> {
+    let mut unavailable_until = UNAVAILABLE_UNTIL.lock()?;
+    match *unavailable_until {
+        Some(until) if clock::now() < until => Ok(false),
+        Some(_) => {
+            *unavailable_until = None;
+            Ok(true)
+        }
+        None => Ok(true),
+    }
+}
+
 pub extern "system" fn get_system(
     xr_instance: xr::Instance,
     info: *const xr::SystemGetInfo,
@@ -19,12 +43,16 @@ pub extern "system" fn get_system(
     log::debug!("get_system: {:?}", info);
 
     with_instance(xr_instance.into_raw(), |_instance| {
-        if info.form_factor == xr::FormFactor::HEAD_MOUNTED_DISPLAY {
-            *system_id = xr::SystemId::from_raw(HMD_SYSTEM_ID);
-            Ok(())
-        } else {
-            Err(xr::Result::ERROR_FORM_FACTOR_UNSUPPORTED.into())
+        if info.form_factor != xr::FormFactor::HEAD_MOUNTED_DISPLAY {
+            return Err(xr::Result::ERROR_FORM_FACTOR_UNSUPPORTED.into());
+        }
+
+        if !is_available()? {
+            return Err(xr::Result::ERROR_FORM_FACTOR_UNAVAILABLE.into());
         }
+
+        *system_id = xr::SystemId::from_raw(HMD_SYSTEM_ID);
+        Ok(())
     })
     .into_xr_result()
 }
@@ -44,20 +72,37 @@ pub extern "system" fn get_properties(
         return xr::Result::ERROR_VALIDATION_FAILURE;
     }
 
-    with_instance(xr_instance.into_raw(), |_instance| {
+    with_instance(xr_instance.into_raw(), |instance| {
+        let profile = &instance.profile;
+
         properties.system_id = system_id;
-        properties.vendor_id = 0x079c98d4;
-        copy_str_to_cchar_arr("openxr-device-simulator", &mut properties.system_name);
+        properties.vendor_id = profile.vendor_id;
+        copy_str_to_cchar_arr(&profile.system_name, &mut properties.system_name);
         properties.graphics_properties = xr::SystemGraphicsProperties {
-            max_swapchain_image_height: 1024,
-            max_swapchain_image_width: 1024,
-            max_layer_count: xr::MIN_COMPOSITION_LAYERS_SUPPORTED as u32,
+            max_swapchain_image_height: profile.graphics.max_swapchain_image_height,
+            max_swapchain_image_width: profile.graphics.max_swapchain_image_width,
+            max_layer_count: profile.graphics.max_layer_count,
         };
-        properties.tracking_properties.orientation_tracking = xr::TRUE;
-        properties.tracking_properties.position_tracking = xr::TRUE;
+        properties.tracking_properties.orientation_tracking = profile.tracking.orientation.into();
+        properties.tracking_properties.position_tracking = profile.tracking.position.into();
 
         log::debug!("get_properties({:?}): {:?}", system_id, &properties);
         Ok(())
     })
     .into_xr_result()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_system_comes_back_after_being_unavailable() {
+        set_unavailable_for(Duration::from_secs(60 * 60)).unwrap();
+        assert!(!is_available().unwrap());
+
+        set_unavailable_for(Duration::ZERO).unwrap();
+        assert!(is_available().unwrap());
+        assert_eq!(*UNAVAILABLE_UNTIL.lock().unwrap(), None);
+    }
+}
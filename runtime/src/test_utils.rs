@@ -0,0 +1,27 @@
+//! Fixtures shared by the unit tests.
+
+pub fn assert_vec3_eq(actual: &xr::Vector3f, expected: &xr::Vector3f) {
+    assert!(
+        (actual.x - expected.x).abs() < 1e-5
+            && (actual.y - expected.y).abs() < 1e-5
+            && (actual.z - expected.z).abs() < 1e-5,
+        "{actual:?} != {expected:?}"
+    );
+}
+
+/// rotation of `angle` radians around +Y, positive angles turn left
+pub fn yaw(angle: f32) -> xr::Quaternionf {
+    xr::Quaternionf {
+        x: 0.0,
+        y: (angle / 2.0).sin(),
+        z: 0.0,
+        w: (angle / 2.0).cos(),
+    }
+}
+
+pub fn pose_at(x: f32, y: f32, z: f32, angle: f32) -> xr::Posef {
+    xr::Posef {
+        orientation: yaw(angle),
+        position: xr::Vector3f { x, y, z },
+    }
+}
@@ -1,7 +1,7 @@
 use std::{
     cell::UnsafeCell,
     collections::HashMap,
-    ffi::c_char,
+    ffi::{c_char, c_void},
     sync::{LazyLock, Mutex},
     time::Duration,
 };
@@ -110,9 +110,12 @@ impl From<MyTime> for xr::Time {
     }
 }
 
+/// Times past the range of `XrTime` saturate instead of wrapping around.
 impl From<Duration> for MyTime {
     fn from(value: Duration) -> Self {
-        Self(xr::Time::from_nanos(value.as_nanos().try_into().unwrap()))
+        Self(xr::Time::from_nanos(
+            value.as_nanos().try_into().unwrap_or(i64::MAX),
+        ))
     }
 }
 
@@ -131,3 +134,32 @@ pub fn create_identity_pose() -> xr::Posef {
         },
     }
 }
+
+pub fn find_in_next_chain<'a, T>(next: *const c_void, ty: xr::StructureType) -> Option<&'a T> {
+    let mut item = next as *const xr::BaseInStructure;
+    while !item.is_null() {
+        unsafe {
+            if (*item).ty == ty {
+                return Some(&*(item as *const T));
+            }
+            item = (*item).next;
+        }
+    }
+    None
+}
+
+pub fn find_in_next_chain_mut<'a, T>(
+    next: *mut c_void,
+    ty: xr::StructureType,
+) -> Option<&'a mut T> {
+    let mut item = next as *mut xr::BaseOutStructure;
+    while !item.is_null() {
+        unsafe {
+            if (*item).ty == ty {
+                return Some(&mut *(item as *mut T));
+            }
+            item = (*item).next;
+        }
+    }
+    None
+}
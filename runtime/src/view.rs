@@ -79,13 +79,16 @@ pub extern "system" fn enumerate_configuration_views(
 
     let count_out = unsafe { &mut *count_out };
 
-    with_instance(xr_instance.into_raw(), |_instance| {
+    with_instance(xr_instance.into_raw(), |instance| {
+        let profile_views = &instance.profile.views;
+
+        *count_out = profile_views.len() as u32;
+
         if capacity_in == 0 {
-            *count_out = 2;
             return Ok(());
         }
 
-        if *count_out != 2 {
+        if (capacity_in as usize) < profile_views.len() {
             return Err(xr::Result::ERROR_SIZE_INSUFFICIENT.into());
         }
 
@@ -95,17 +98,8 @@ pub extern "system" fn enumerate_configuration_views(
 
         unsafe {
             // for left and right eyes
-            for i in 0..2 {
-                *views.add(i) = xr::ViewConfigurationView {
-                    ty: (*views).ty,
-                    next: std::ptr::null_mut(),
-                    recommended_image_rect_width: 1024,
-                    max_image_rect_width: 1024,
-                    recommended_image_rect_height: 1024,
-                    max_image_rect_height: 1024,
-                    recommended_swapchain_sample_count: 1,
-                    max_swapchain_sample_count: 1,
-                };
+            for (i, view) in profile_views.iter().enumerate() {
+                *views.add(i) = view.to_xr((*views.add(i)).ty);
             }
         };
 